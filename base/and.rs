@@ -17,12 +17,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! and( ( $ex:expr ) => ( &base::And::new( $ex ) ); );
 
 pub struct And<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'a> And<'a> {
-  pub fn new( expr: &Expression ) -> And {
+  pub fn new( expr: &'a dyn Expression ) -> And<'a> {
     And { expr: expr }
   }
 }
@@ -32,7 +32,7 @@ impl<'b> Expression for And<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
     match self.expr.apply( parse_state ) {
-      Some( _ ) => Some( ParseResult::fromParseState( *parse_state ) ),
+      Some( _ ) => Some( ParseResult::fromParseState( parse_state.clone() ) ),
       _ => None
     }
   }
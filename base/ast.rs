@@ -0,0 +1,267 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::{Node, NodeContents, Data, Children};
+
+/// Walks the children of a parse tree node while the node is converted to a
+/// typed AST. Every method either succeeds or leaves the cursor where it was.
+pub struct AstCursor<'n, 'a: 'n> {
+  nodes: Vec<&'n Node<'a>>,
+  position: usize,
+
+  // Set if the node matched a single token and took over its data; the node
+  // then stands in for that token.
+  collapsed: bool
+}
+
+
+// Returns true if `node` is a token: a node without a name, or any node that
+// took over a token's data. Nodes produced by error recovery aren't tokens.
+fn isToken( node: &Node, collapsed: bool ) -> bool {
+  ( collapsed || node.name.is_empty() ) && !node.isError()
+}
+
+
+impl<'n, 'a> AstCursor<'n, 'a> {
+  pub fn new( node: &'n Node<'a> ) -> AstCursor<'n, 'a> {
+    match node.contents {
+      Children( ref children ) => AstCursor { nodes: children.iter().collect(),
+                                              position: 0,
+                                              collapsed: false },
+      Data( _ ) | NodeContents::Error( _ ) => {
+        AstCursor { nodes: vec!( node ), position: 0, collapsed: true }
+      }
+    }
+  }
+
+  /// Consumes the next node if it has `Error` contents; it's what a recovery
+  /// expression produced after failing to match.
+  pub fn error( &mut self ) -> Option<()> {
+    match self.nodes.get( self.position ) {
+      Some( node ) if node.isError() => {
+        self.position += 1;
+        Some( () )
+      }
+      _ => None
+    }
+  }
+
+  /// Consumes the next node if it was produced by the rule `name`.
+  pub fn rule( &mut self, name: &str ) -> Option<&'n Node<'a>> {
+    match self.nodes.get( self.position ) {
+      Some( node ) if !self.collapsed && node.name == name => {
+        self.position += 1;
+        Some( *node )
+      }
+      _ => None
+    }
+  }
+
+  /// Consumes the next node if it's a token, and if `literal` is given, only
+  /// if the token matched that literal.
+  pub fn token( &mut self, literal: Option<&[u8]> ) -> Option<()> {
+    let matches = match self.nodes.get( self.position ) {
+      Some( node ) if isToken( node, self.collapsed ) => {
+        match ( literal, &node.contents ) {
+          ( Some( literal ), &Data( data ) ) => literal == data,
+          _ => true
+        }
+      }
+      _ => false
+    };
+
+    if matches {
+      self.position += 1;
+      Some( () )
+    } else {
+      None
+    }
+  }
+
+  /// Consumes the next node if it's a token and returns its text.
+  pub fn text( &mut self ) -> Option<String> {
+    match self.nodes.get( self.position ) {
+      Some( node ) if isToken( node, self.collapsed ) => {
+        self.position += 1;
+        Some( node.matchedText() )
+      }
+      _ => None
+    }
+  }
+
+  /// Runs `convert` and returns the text of the nodes it consumed, moving the
+  /// cursor back if it fails.
+  pub fn matched<T, F>( &mut self, convert: F ) -> Option<String>
+      where F: FnOnce( &mut AstCursor<'n, 'a> ) -> Option<T> {
+    let start = self.position;
+    self.attempt( convert ).map( |_| {
+      let mut data = vec!();
+      for node in &self.nodes[ start .. self.position ] {
+        data.extend( node.matchedData() );
+      }
+      String::from_utf8_lossy( &data ).into_owned()
+    } )
+  }
+
+  /// Runs `convert`, moving the cursor back if it fails.
+  pub fn attempt<T, F>( &mut self, convert: F ) -> Option<T>
+      where F: FnOnce( &mut AstCursor<'n, 'a> ) -> Option<T> {
+    let position = self.position;
+    let result = convert( self );
+    if result.is_none() {
+      self.position = position;
+    }
+    result
+  }
+
+  /// Runs `convert` for as long as it succeeds and consumes nodes, but no more
+  /// than `max` times if that's given. Fails if it succeeded fewer than `min`
+  /// times.
+  pub fn repeat<T, F>( &mut self,
+                       min: usize,
+                       max: Option<usize>,
+                       mut convert: F ) -> Option<Vec<T>>
+      where F: FnMut( &mut AstCursor<'n, 'a> ) -> Option<T> {
+    let start = self.position;
+    let mut values = vec!();
+    while max.map_or( true, |max| values.len() < max ) {
+      let position = self.position;
+      match self.attempt( &mut convert ) {
+        Some( value ) => {
+          values.push( value );
+          if self.position == position {
+            break;
+          }
+        }
+        _ => break
+      }
+    }
+
+    if values.len() < min {
+      self.position = start;
+      None
+    } else {
+      Some( values )
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base::{Node, Data};
+  use super::AstCursor;
+
+  fn tree() -> Node<'static> {
+    Node::withChildren( "A", vec!(
+      Node::withChildren( "B", vec!(
+        Node::withoutName( 0, 1, Data( b"b" ) ),
+        Node::withoutName( 1, 2, Data( b"b" ) ) ) ),
+      Node::withoutName( 2, 3, Data( b"," ) ),
+      Node::withChildren( "B", vec!(
+        Node::withoutName( 3, 4, Data( b"b" ) ),
+        Node::withoutName( 4, 5, Data( b"b" ) ) ) ) ) )
+  }
+
+
+  #[test]
+  fn AstCursor_RuleAndToken() {
+    let root = tree();
+    let mut cursor = AstCursor::new( &root );
+    assert!( cursor.rule( "C" ).is_none() );
+    assert!( cursor.token( None ).is_none() );
+    assert_eq!( cursor.rule( "B" ).unwrap().start, 0 );
+    assert!( cursor.token( Some( b"." ) ).is_none() );
+    assert!( cursor.token( Some( b"," ) ).is_some() );
+    assert!( cursor.text().is_none() );
+    assert_eq!( cursor.rule( "B" ).unwrap().start, 3 );
+    assert!( cursor.rule( "B" ).is_none() );
+  }
+
+
+  #[test]
+  fn AstCursor_CollapsedNode() {
+    let node = Node::withChildren( "A", vec!(
+      Node::withoutName( 0, 1, Data( b"a" ) ) ) );
+    let mut cursor = AstCursor::new( &node );
+    assert!( cursor.rule( "A" ).is_none() );
+    assert_eq!( cursor.text(), Some( "a".to_string() ) );
+  }
+
+
+  #[test]
+  fn AstCursor_Matched() {
+    let root = tree();
+    let mut cursor = AstCursor::new( &root );
+    assert!( cursor.rule( "B" ).is_some() );
+    let separator = cursor.matched( |cursor| {
+      cursor.token( Some( b";" ) ).or_else( || cursor.token( Some( b"," ) ) )
+    } );
+    assert_eq!( separator, Some( ",".to_string() ) );
+    assert_eq!( cursor.matched( |cursor| cursor.token( None ) ), None );
+    assert_eq!( cursor.matched( |cursor| cursor.rule( "B" ) ),
+                Some( "bb".to_string() ) );
+
+    let digits = Node::withChildren( "Value", vec!(
+      Node::withoutName( 0, 1, Data( b"4" ) ),
+      Node::withoutName( 1, 2, Data( b"2" ) ) ) );
+    let mut cursor = AstCursor::new( &digits );
+    assert_eq!( cursor.matched( |cursor| {
+      cursor.repeat( 1, None, |cursor| cursor.token( None ) )
+    } ), Some( "42".to_string() ) );
+  }
+
+
+  #[test]
+  fn AstCursor_AttemptRestores() {
+    let root = tree();
+    let mut cursor = AstCursor::new( &root );
+    let result: Option<()> = cursor.attempt( |cursor| {
+      cursor.rule( "B" ).and_then( |_| cursor.rule( "B" ) ).map( |_| () )
+    } );
+    assert!( result.is_none() );
+    assert!( cursor.rule( "B" ).is_some() );
+  }
+
+
+  #[test]
+  fn AstCursor_Repeat() {
+    let root = tree();
+    let mut cursor = AstCursor::new( &root );
+    let names = cursor.repeat( 1, None, |cursor| {
+      cursor.token( None ).or( Some( () ) )
+        .and_then( |_| cursor.rule( "B" ) ).map( |node| node.start )
+    } );
+    assert_eq!( names, Some( vec!( 0, 3 ) ) );
+
+    let mut cursor = AstCursor::new( &root );
+    assert!( cursor.repeat( 1, None, |cursor| cursor.rule( "C" ) ).is_none() );
+    assert_eq!( cursor.repeat( 0, None, |cursor| cursor.rule( "C" ) ),
+                Some( vec!() ) );
+  }
+
+
+  #[test]
+  fn AstCursor_RepeatAtMost() {
+    let root = tree();
+    let mut cursor = AstCursor::new( &root );
+    let names = cursor.repeat( 0, Some( 1 ), |cursor| {
+      cursor.token( None ).or( Some( () ) )
+        .and_then( |_| cursor.rule( "B" ) ).map( |node| node.start )
+    } );
+    assert_eq!( names, Some( vec!( 0 ) ) );
+    assert!( cursor.rule( "B" ).is_none() );
+    assert_eq!( cursor.repeat( 0, Some( 0 ), |cursor| cursor.rule( "C" ) ),
+                Some( vec!() ) );
+  }
+}
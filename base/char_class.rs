@@ -11,11 +11,41 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use base::unicode::{bytesFollowing, readCodepoint};
-use super::{Expression, ParseState, ParseResult};
+use std::char;
+use super::unicode::{bytesFollowing, readCodepoint, foldCase, caseVariants,
+                     unicodeProperty, hasProperty, Table};
+use super::{Expression, ParseState, ParseResult, Expected};
 
-macro_rules! class( ( $ex:expr ) => (
-      &base::CharClass::new( $ex.as_bytes() ) ) );
+macro_rules! class(
+  ( ^ $ex:expr ) => (
+      &base::CharClass::negated( concat!( "^", $ex ).as_bytes() ) );
+  ( $ex:expr ) => (
+      &base::CharClass::new( $ex.as_bytes() ) );
+);
+
+macro_rules! iclass(
+  ( ^ $ex:expr ) => (
+      &base::CharClass::negatedIgnoringCase(
+        concat!( "^", $ex ).as_bytes() ) );
+  ( $ex:expr ) => (
+      &base::CharClass::ignoringCase( $ex.as_bytes() ) );
+);
+
+macro_rules! bclass(
+  ( ^ $ex:expr ) => (
+      &base::CharClass::negated(
+        concat!( "^", $ex ).as_bytes() ).matchingBytes() );
+  ( $ex:expr ) => (
+      &base::CharClass::new( $ex.as_bytes() ).matchingBytes() );
+);
+
+macro_rules! ibclass(
+  ( ^ $ex:expr ) => (
+      &base::CharClass::negatedIgnoringCase(
+        concat!( "^", $ex ).as_bytes() ).matchingBytes() );
+  ( $ex:expr ) => (
+      &base::CharClass::ignoringCase( $ex.as_bytes() ).matchingBytes() );
+);
 
 
 fn toU32Vector( input: &[u8] ) -> Vec<u32> {
@@ -43,45 +73,150 @@ fn toU32Vector( input: &[u8] ) -> Vec<u32> {
 }
 
 
+#[derive(Clone, Copy)]
+enum ClassItem {
+  Char( u32 ),
+  Property( &'static [Table] )
+}
+
+
+// Returns the name of the `\p{Name}` property at the start of `chars` and the
+// number of chars it takes up.
+fn propertyAt( chars: &[u32] ) -> Option<( String, usize )> {
+  if !chars.starts_with( &[ '\\' as u32, 'p' as u32, '{' as u32 ] ) {
+    return None;
+  }
+  chars.iter().position( |ch| *ch == '}' as u32 ).map( |end| {
+    let name = chars[ 3 .. end ].iter()
+      .filter_map( |ch| char::from_u32( *ch ) )
+      .collect();
+    ( name, end + 1 )
+  } )
+}
+
+
+// Returns the value of the `\xNN` escape at the start of `chars`.
+fn hexEscapeAt( chars: &[u32] ) -> Option<u32> {
+  if chars.len() < 4 || chars[ 1 ] != 'x' as u32 {
+    return None;
+  }
+  let digit = |value: u32| {
+    char::from_u32( value ).and_then( |ch| ch.to_digit( 16 ) )
+  };
+  match ( digit( chars[ 2 ] ), digit( chars[ 3 ] ) ) {
+    ( Some( high ), Some( low ) ) => Some( high * 16 + low ),
+    _ => None
+  }
+}
+
+
+// Splits the class contents into chars and Unicode properties. A `\\` is a
+// literal backslash, and `\xNN` is the char (or in byte mode, the byte) with
+// that value; any other backslash that doesn't start a property is literal too.
+fn classItems( chars: &[u32] ) -> Vec<ClassItem> {
+  let mut items = vec!();
+  let mut index = 0;
+  while index < chars.len() {
+    if chars[ index ] == '\\' as u32 {
+      if chars.get( index + 1 ) == Some( &( '\\' as u32 ) ) {
+        items.push( ClassItem::Char( chars[ index ] ) );
+        index += 2;
+        continue;
+      }
+
+      if let Some( value ) = hexEscapeAt( &chars[ index.. ] ) {
+        items.push( ClassItem::Char( value ) );
+        index += 4;
+        continue;
+      }
+
+      if let Some( ( name, length ) ) = propertyAt( &chars[ index.. ] ) {
+        match unicodeProperty( &name ) {
+          Some( tables ) => items.push( ClassItem::Property( tables ) ),
+          None => panic!( "Unknown Unicode property: {}", name )
+        }
+        index += length;
+        continue;
+      }
+    }
+    items.push( ClassItem::Char( chars[ index ] ) );
+    index += 1;
+  }
+  items
+}
+
+
 pub struct CharClass {
+  // The contents the class was created from; used for error reporting.
+  contents: &'static [u8],
+
   // All the single chars in the char class.
   // May be unicode codepoints or binary octets stored as codepoints.
   single_chars: Vec<u32>,
 
   // Sequence of [from, to] (inclusive bounds) char ranges.
   // May be unicode codepoints or binary octets stored as codepoints.
-  ranges: Vec<( u32, u32 )>
+  ranges: Vec<( u32, u32 )>,
+
+  // The `\p{Name}` Unicode properties in the class. Only UTF-8 chars can have
+  // them, never raw bytes.
+  properties: Vec< &'static [Table] >,
+
+  // Whether UTF-8 chars also match if the class has them in another case.
+  // Raw bytes are always matched exactly.
+  ignore_case: bool,
+
+  // Whether the class matches the chars that are NOT in it.
+  negated: bool,
+
+  // Whether the class matches single bytes and never decodes UTF-8 chars.
+  only_bytes: bool
 }
 
 
 impl CharClass {
   // Takes the inner content of square brackets, so for [a-z], send "a-z".
-  pub fn new( contents: &[u8] ) -> CharClass {
-    fn rangeAtIndex( index: usize, chars: &[u32] ) -> Option<( u32, u32 )> {
-      match ( chars.get( index ),
-              chars.get( index + 1 ),
-              chars.get( index + 2 ) ) {
-        ( Some( char1 ), Some( char2 ), Some( char3 ) )
-            if *char2 == '-' as u32 => Some( ( *char1, *char3 ) ),
+  // Unicode properties are written as `\p{Name}`, and a literal backslash as
+  // `\\`.
+  pub fn new( contents: &'static [u8] ) -> CharClass {
+    fn rangeAtIndex( index: usize, items: &[ClassItem] )
+        -> Option<( u32, u32 )> {
+      match ( items.get( index ),
+              items.get( index + 1 ),
+              items.get( index + 2 ) ) {
+        ( Some( &ClassItem::Char( char1 ) ),
+          Some( &ClassItem::Char( char2 ) ),
+          Some( &ClassItem::Char( char3 ) ) )
+            if char2 == '-' as u32 => Some( ( char1, char3 ) ),
         _ => None
       }
     }
 
-    let chars = toU32Vector( &contents );
-    let mut char_class = CharClass { single_chars: Vec::new(),
-                                     ranges: Vec::new() };
+    let items = classItems( &toU32Vector( &contents ) );
+    let mut char_class = CharClass { contents: contents,
+                                     single_chars: Vec::new(),
+                                     ranges: Vec::new(),
+                                     properties: Vec::new(),
+                                     ignore_case: false,
+                                     negated: false,
+                                     only_bytes: false };
     let mut index = 0;
     loop {
-      match rangeAtIndex( index, &chars ) {
+      match rangeAtIndex( index, &items ) {
         Some( range ) => {
           char_class.ranges.push( range );
           index += 3;
         }
         _ => {
-          if index >= chars.len() {
-            break
+          match items.get( index ) {
+            Some( &ClassItem::Char( ch ) ) => {
+              char_class.single_chars.push( ch )
+            }
+            Some( &ClassItem::Property( tables ) ) => {
+              char_class.properties.push( tables )
+            }
+            None => break
           }
-          char_class.single_chars.push( chars[ index ] );
           index += 1;
         }
       };
@@ -90,6 +225,32 @@ impl CharClass {
     char_class
   }
 
+  /// A class that matches chars in any case, using simple case folding. Takes
+  /// the same contents as `new`.
+  pub fn ignoringCase( contents: &'static [u8] ) -> CharClass {
+    CharClass { ignore_case: true, .. CharClass::new( contents ) }
+  }
+
+  /// A class that matches a single char that isn't in it. Takes the contents
+  /// with the leading caret, so for [^a-z], send "^a-z".
+  pub fn negated( contents: &'static [u8] ) -> CharClass {
+    CharClass { contents: contents,
+                negated: true,
+                .. CharClass::new( &contents[ 1.. ] ) }
+  }
+
+  pub fn negatedIgnoringCase( contents: &'static [u8] ) -> CharClass {
+    CharClass { ignore_case: true, .. CharClass::negated( contents ) }
+  }
+
+  /// Makes the class match a single byte of the input, for binary formats.
+  /// The values in the class are then bytes (written as `\xNN` above 0x7F);
+  /// ignoring case only affects ASCII letters, and Unicode properties never
+  /// match.
+  pub fn matchingBytes( self ) -> CharClass {
+    CharClass { only_bytes: true, .. self }
+  }
+
   fn matches( &self, character: u32 ) -> bool {
     return self.single_chars.contains( &character ) ||
       self.ranges.iter().any(
@@ -97,10 +258,35 @@ impl CharClass {
   }
 
 
+  fn matchesByte( &self, byte: u8 ) -> bool {
+    let lower = byte | 0x20;
+    self.matches( byte as u32 ) ||
+      ( self.ignore_case && lower >= b'a' && lower <= b'z' &&
+        self.matches( ( byte ^ 0x20 ) as u32 ) )
+  }
+
+
+  fn matchesChar( &self, character: char ) -> bool {
+    let matches = |ch: char| {
+      self.matches( ch as u32 ) ||
+        self.properties.iter().any( |tables| hasProperty( ch, tables ) )
+    };
+    if !self.ignore_case {
+      return matches( character );
+    }
+
+    let folded = foldCase( character );
+    caseVariants( character ).iter().any( |ch| matches( *ch ) ) ||
+      self.single_chars.iter().any( |single| {
+        char::from_u32( *single ).map_or( false, |ch| foldCase( ch ) == folded )
+      } )
+  }
+
+
   fn applyToUtf8<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
     match readCodepoint( parse_state.input ) {
-      Some( ch ) if self.matches( ch as u32 ) => {
+      Some( ch ) if self.matchesChar( ch ) => {
         let num_following = bytesFollowing( parse_state.input[ 0 ] ).unwrap();
         parse_state.offsetToResult( parse_state.offset + num_following + 1 )
       }
@@ -118,13 +304,54 @@ impl CharClass {
       _ => None
     }
   }
+
+
+  // A negated class consumes a whole char if the input starts with one that
+  // isn't in the class, and falls back to bytes only where the input isn't
+  // UTF-8; a char that is in the class must not match by its leading byte.
+  fn applyNegated<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    match readCodepoint( parse_state.input ) {
+      Some( ch ) if !self.matchesChar( ch ) => {
+        let num_following = bytesFollowing( parse_state.input[ 0 ] ).unwrap();
+        parse_state.offsetToResult( parse_state.offset + num_following + 1 )
+      }
+      Some( _ ) => None,
+      None => match parse_state.input.get( 0 ) {
+        Some( byte ) if !self.matches( *byte as u32 ) => {
+          parse_state.offsetToResult( parse_state.offset + 1 )
+        }
+        _ => None
+      }
+    }
+  }
 }
 
 
 impl Expression for CharClass {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    self.applyToUtf8( parse_state ).or( self.applyToBytes( parse_state ) )
+    let result = if self.only_bytes {
+      parse_state.lookAt( 1 );
+      match parse_state.input.get( 0 ) {
+        Some( byte ) if self.matchesByte( *byte ) != self.negated => {
+          parse_state.offsetToResult( parse_state.offset + 1 )
+        }
+        _ => None
+      }
+    } else if self.negated {
+      parse_state.lookAtChar();
+      self.applyNegated( parse_state )
+    } else {
+      parse_state.lookAtChar();
+      self.applyToUtf8( parse_state )
+        .or_else( || self.applyToBytes( parse_state ) )
+    };
+    result.or_else( || parse_state.expected( if self.ignore_case {
+        Expected::ClassIgnoringCase( self.contents )
+      } else {
+        Expected::Class( self.contents )
+      } ) )
   }
 }
 
@@ -132,22 +359,23 @@ impl Expression for CharClass {
 #[cfg(test)]
 mod tests {
   use base;
-  use base::{Node, Data, ParseResult, Expression, ParseState};
+  use base::{Node, Data, ParseResult, Expression, Expected};
   use base::test_utils::ToParseState;
   use base::unicode::bytesFollowing;
   use super::{CharClass};
 
-  fn charClassMatch( char_class: &Expression, input: &[u8] ) -> bool {
+  fn charClassMatch( char_class: &dyn Expression, input: &[u8] ) -> bool {
     fn bytesRead( input: &[u8] ) -> usize {
       bytesFollowing( input[ 0 ] ).map_or( 1, |num| num + 1 )
     }
 
-    match char_class.apply( &ToParseState( input ) ) {
+    let orig_state = ToParseState( input );
+    match char_class.apply( &orig_state ) {
       Some( ParseResult { nodes, parse_state } ) => {
         let bytes_read = bytesRead( input );
         assert_eq!( nodes[ 0 ],
                     Node::withoutName( 0, bytes_read, Data( input ) ) );
-        assert_eq!( parse_state, ParseState{ input: &[], offset: bytes_read } );
+        assert_eq!( parse_state, orig_state.advanceTo( bytes_read ) );
         true
       }
       _ => false
@@ -193,5 +421,166 @@ mod tests {
   }
 
 
+  #[test]
+  fn CharClass_IgnoringCase_Match() {
+    assert!( charClassMatch( iclass!( "a-z" ), b"Q" ) );
+    assert!( charClassMatch( iclass!( "A-Z" ), b"q" ) );
+    assert!( charClassMatch( iclass!( "xyz" ), b"Y" ) );
+    assert!( charClassMatch( iclass!( "α-ω" ), "Η".as_bytes() ) );
+    assert!( charClassMatch( iclass!( "A-Z" ), "ſ".as_bytes() ) );
+    assert!( charClassMatch( iclass!( "ſ" ), b"S" ) );
+    assert!( charClassMatch( iclass!( "k" ), "\u{212A}".as_bytes() ) );
+  }
+
+
+  #[test]
+  fn CharClass_IgnoringCase_NoMatch() {
+    assert!( !charClassMatch( iclass!( "a-z" ), b"0" ) );
+    assert!( !charClassMatch( iclass!( "xyz" ), b"A" ) );
+    assert!( !charClassMatch( &CharClass::ignoringCase( &[ 0xC0 ] ),
+                              &[ 0xE0 ] ) );
+  }
+
+
+  #[test]
+  fn CharClass_IgnoringCase_NoMatch_RecordsExpected() {
+    let orig_state = input_state!( "0" );
+    assert!( iclass!( "a-z" ).apply( &orig_state ).is_none() );
+
+    let error = orig_state.context.parseError( b"0" );
+    assert_eq!( error.expected, vec!( Expected::ClassIgnoringCase( b"a-z" ) ) );
+  }
+
+
+  #[test]
+  fn CharClass_Negated_Match() {
+    assert!( charClassMatch( class!( ^ "a-z" ), b"0" ) );
+    assert!( charClassMatch( class!( ^ "\"" ), b"a" ) );
+    assert!( charClassMatch( class!( ^ "" ), b"a" ) );
+    assert!( charClassMatch( class!( ^ "a-z" ), "η".as_bytes() ) );
+    assert!( charClassMatch( iclass!( ^ "a-z" ), b"0" ) );
+    assert!( charClassMatch( &CharClass::negated( b"^a" ), &[ 255 ] ) );
+  }
+
+
+  #[test]
+  fn CharClass_Negated_NoMatch() {
+    assert!( !charClassMatch( class!( ^ "a-z" ), b"q" ) );
+    assert!( !charClassMatch( class!( ^ "α-ω" ), "η".as_bytes() ) );
+    assert!( !charClassMatch( iclass!( ^ "a-z" ), b"Q" ) );
+    assert!( !charClassMatch( &CharClass::negated( &[ b'^', 255 ] ),
+                              &[ 255 ] ) );
+    assert!( class!( ^ "a" ).apply( &input_state!( "" ) ).is_none() );
+  }
+
+
+  #[test]
+  fn CharClass_Negated_DoesNotMatchLeadingByteOfExcludedChar() {
+    // The class excludes `é` but not the byte it starts with.
+    assert!( !charClassMatch( &CharClass::negated( "^é".as_bytes() ),
+                              "é".as_bytes() ) );
+  }
+
+
+  #[test]
+  fn CharClass_Negated_NoMatch_RecordsExpected() {
+    let orig_state = input_state!( "q" );
+    assert!( class!( ^ "a-z" ).apply( &orig_state ).is_none() );
+
+    let error = orig_state.context.parseError( b"q" );
+    assert_eq!( error.expected, vec!( Expected::Class( b"^a-z" ) ) );
+    assert_eq!( error.to_string(), "line 1, column 1: expected [^a-z]" );
+  }
+
+
+  #[test]
+  fn CharClass_UnicodeProperties() {
+    assert!( charClassMatch( class!( "\\p{L}" ), b"a" ) );
+    assert!( charClassMatch( class!( "\\p{L}" ), "Ж".as_bytes() ) );
+    assert!( !charClassMatch( class!( "\\p{L}" ), b"1" ) );
+    assert!( charClassMatch( class!( "\\p{Greek}0-9" ), "λ".as_bytes() ) );
+    assert!( charClassMatch( class!( "\\p{Greek}0-9" ), b"7" ) );
+    assert!( !charClassMatch( class!( "\\p{Greek}0-9" ), b"x" ) );
+    assert!( charClassMatch( class!( "_\\p{XID_Start}" ), b"_" ) );
+    assert!( charClassMatch( class!( ^ "\\p{Lu}" ), b"a" ) );
+    assert!( !charClassMatch( class!( ^ "\\p{Lu}" ), b"A" ) );
+    assert!( charClassMatch( iclass!( "\\p{Lu}" ), b"a" ) );
+  }
+
+
+  #[test]
+  fn CharClass_UnicodeProperties_NotForRawBytes() {
+    // 0xE9 is `é` in Latin-1, but it isn't a UTF-8 char.
+    assert!( !charClassMatch( class!( "\\p{L}" ), &[ 0xE9 ] ) );
+  }
+
+
+  #[test]
+  fn CharClass_PropertyIsNotPartOfRange() {
+    assert!( charClassMatch( class!( "a\\p{Nd}-z" ), b"-" ) );
+    assert!( !charClassMatch( class!( "a\\p{Nd}-z" ), b"m" ) );
+  }
+
+
+  #[test]
+  fn CharClass_EscapedBackslash() {
+    assert!( charClassMatch( class!( "\\\\" ), b"\\" ) );
+    assert!( charClassMatch( class!( "\\" ), b"\\" ) );
+    assert!( charClassMatch( class!( "\\\\p{L}" ), b"p" ) );
+    assert!( !charClassMatch( class!( "\\\\p{L}" ), b"a" ) );
+  }
+
+
+  #[test]
+  fn CharClass_HexEscapes() {
+    assert!( charClassMatch( class!( "\\x41-\\x5a" ), b"Q" ) );
+    assert!( charClassMatch( class!( "\\x2d" ), b"-" ) );
+    assert!( charClassMatch( class!( "\\xE9" ), "é".as_bytes() ) );
+    assert!( !charClassMatch( class!( "\\x41-\\x5a" ), b"q" ) );
+    assert!( charClassMatch( class!( "\\x4" ), b"x" ) );
+  }
+
+
+  fn byteClassMatch( char_class: &dyn Expression, byte: u8 ) -> bool {
+    let input = [ byte ];
+    let orig_state = ToParseState( &input );
+    match char_class.apply( &orig_state ) {
+      Some( ParseResult { parse_state, .. } ) => {
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
+        true
+      }
+      _ => false
+    }
+  }
+
+
+  #[test]
+  fn CharClass_MatchingBytes() {
+    assert!( byteClassMatch( bclass!( "\\x80-\\xFF" ), 0x89 ) );
+    assert!( byteClassMatch( bclass!( "\\x00-\\x1F" ), 0x00 ) );
+    assert!( byteClassMatch( bclass!( "a-z" ), b'q' ) );
+    assert!( byteClassMatch( bclass!( ^ "a-z" ), 0xC3 ) );
+    assert!( byteClassMatch( ibclass!( "a-z" ), b'Q' ) );
+    assert!( byteClassMatch( ibclass!( ^ "a-z" ), b'[' ) );
+    assert!( !byteClassMatch( ibclass!( "a-z" ), b'@' ) );
+    assert!( !byteClassMatch( bclass!( ^ "a-z" ), b'q' ) );
+    assert!( !byteClassMatch( bclass!( "\\p{L}" ), b'a' ) );
+  }
+
+
+  #[test]
+  fn CharClass_MatchingBytes_NeverDecodesChars() {
+    let orig_state = input_state!( "é" );
+    match bclass!( "\\xC3" ).apply( &orig_state ) {
+      Some( ParseResult{ parse_state, .. } ) => {
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
+      }
+      _ => panic!( "No match!" )
+    };
+    assert!( bclass!( "\\xE9" ).apply( &orig_state ).is_none() );
+    assert!( bclass!( ^ "\\xC3" ).apply( &orig_state ).is_none() );
+  }
+
+
   // TODO: tests for escaped chars in class
 }
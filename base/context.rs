@@ -0,0 +1,746 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+use super::{Node, ParseState, ParseResult, ParseError, Expected, Edit,
+            Shift};
+
+// A rule is identified by its name; names are unique within a grammar.
+type MemoKey = ( &'static str, usize );
+
+struct MemoEntry<'a> {
+  // The nodes the rule produced and the offset it matched up to, or `None` if
+  // the rule failed.
+  result: Option< ( Vec< Node<'a> >, usize ) >,
+  lookahead: usize,
+
+  // What the rule recorded as expected. It's recorded again whenever the entry
+  // is used, so that memoizing a rule doesn't change the reported errors.
+  failure: Failure
+}
+
+
+// The farthest offset at which an expression failed and what was expected
+// there.
+#[derive(Clone)]
+struct Failure {
+  offset: usize,
+  expected: Vec<Expected>
+}
+
+
+impl Failure {
+  fn none() -> Failure {
+    Failure { offset: 0, expected: vec!() }
+  }
+}
+
+
+/// Where the failure tracking was before a rule was applied. See
+/// `ParseContext::ruleFailed`.
+#[derive(Debug, Clone, Copy)]
+pub struct FailureCheckpoint {
+  offset: usize,
+  num_expected: usize
+}
+
+
+/// State shared by every `ParseState` created during a single parse.
+pub struct ParseContext<'a> {
+  // Results of memoized rules, keyed by (rule name, offset).
+  memo: RefCell< HashMap< MemoKey, MemoEntry<'a> > >,
+
+  // Nodes from a previous parse that a rule can return instead of parsing the
+  // input again, keyed by (rule name, offset), with how they have to be moved
+  // to get to that offset. See `reuse`.
+  reusable: RefCell< HashMap< MemoKey, ( Node<'a>, Shift ) > >,
+
+  failure: RefCell<Failure>,
+
+  // Greater than zero while applying an expression whose failure doesn't mean
+  // the input is wrong, like the expression of a `!` predicate.
+  quiet: Cell<usize>,
+
+  // Set once a cut is passed in the innermost choice alternative being applied.
+  cut: Cell<bool>,
+
+  // The offset just past the farthest byte any expression looked at, quiet or
+  // not. If it's past the end of the input, more input could have changed the
+  // result of the parse.
+  lookahead: Cell<usize>
+}
+
+
+impl<'a> ParseContext<'a> {
+  pub fn new() -> ParseContext<'a> {
+    ParseContext { memo: RefCell::new( HashMap::new() ),
+                   reusable: RefCell::new( HashMap::new() ),
+                   failure: RefCell::new( Failure::none() ),
+                   quiet: Cell::new( 0 ),
+                   cut: Cell::new( false ),
+                   lookahead: Cell::new( 0 ) }
+  }
+
+  /// Records that `expected` was not found at `offset`. Only the expectations
+  /// at the farthest offset are kept.
+  pub fn expected( &self, offset: usize, expected: Expected ) {
+    if self.quiet.get() > 0 {
+      return;
+    }
+
+    let mut failure = self.failure.borrow_mut();
+    if offset > failure.offset {
+      failure.offset = offset;
+      failure.expected.clear();
+    }
+    if offset == failure.offset {
+      failure.expected.push( expected );
+    }
+  }
+
+  /// Returns what was expected at the farthest failure, if that failure is at
+  /// or after `offset`.
+  pub fn expectedFrom( &self, offset: usize ) -> Vec<Expected> {
+    let failure = self.failure.borrow();
+    if failure.offset >= offset {
+      failure.expected.clone()
+    } else {
+      vec!()
+    }
+  }
+
+  pub fn failureCheckpoint( &self ) -> FailureCheckpoint {
+    let failure = self.failure.borrow();
+    FailureCheckpoint { offset: failure.offset,
+                        num_expected: failure.expected.len() }
+  }
+
+  /// Records that the rule `rule_name` failed at `offset`. If nothing inside
+  /// the rule got past `offset`, the expectations the rule recorded there are
+  /// replaced with the rule name; "expected Number" is more useful than
+  /// "expected [0-9]".
+  pub fn ruleFailed( &self,
+                     checkpoint: FailureCheckpoint,
+                     offset: usize,
+                     rule_name: &'static str ) {
+    if self.quiet.get() > 0 {
+      return;
+    }
+
+    let mut failure = self.failure.borrow_mut();
+    if failure.offset > offset {
+      return;
+    }
+
+    if failure.offset == offset && checkpoint.offset == offset {
+      failure.expected.truncate( checkpoint.num_expected );
+    } else {
+      failure.offset = offset;
+      failure.expected.clear();
+    }
+    failure.expected.push( Expected::Rule( rule_name ) );
+  }
+
+  // Applies `function` and returns its result along with what it recorded as
+  // expected. It's recorded even if failure tracking is disabled, but then
+  // only returned; see `replayFailure`.
+  fn trackingFailure<T, F>( &self, function: F ) -> ( T, Failure )
+      where F: FnOnce() -> T {
+    let outer_failure = self.failure.borrow().clone();
+    let outer_quiet = self.quiet.get();
+    self.quiet.set( 0 );
+    let result = function();
+    self.quiet.set( outer_quiet );
+
+    let mut failure = mem::replace( &mut *self.failure.borrow_mut(),
+                                    outer_failure.clone() );
+    if failure.offset == outer_failure.offset {
+      failure.expected = failure.expected.split_off(
+        outer_failure.expected.len() );
+    }
+    ( result, failure )
+  }
+
+  // Records the expectations of `failure` as if the expressions that recorded
+  // them had failed again.
+  fn replayFailure( &self, failure: &Failure ) {
+    for expected in failure.expected.iter() {
+      self.expected( failure.offset, expected.clone() );
+    }
+  }
+
+  /// Applies `function` with failure tracking disabled.
+  pub fn quietly<T, F>( &self, function: F ) -> T where F: FnOnce() -> T {
+    self.quiet.set( self.quiet.get() + 1 );
+    let result = function();
+    self.quiet.set( self.quiet.get() - 1 );
+    result
+  }
+
+  /// Records that a cut was passed. See `Cut`.
+  pub fn cut( &self ) {
+    self.cut.set( true );
+  }
+
+  /// Applies `function` with cuts of its own, so that the cuts passed while
+  /// applying it don't commit the choice around it. Returns the result of
+  /// `function` and whether it passed a cut.
+  pub fn cutScope<T, F>( &self, function: F ) -> ( T, bool )
+      where F: FnOnce() -> T {
+    let outer_cut = self.cut.get();
+    self.cut.set( false );
+    let result = function();
+    let cut = self.cut.get();
+    self.cut.set( outer_cut );
+    ( result, cut )
+  }
+
+  /// Records that an expression looked at the input up to, but not including,
+  /// `end`.
+  pub fn lookedAt( &self, end: usize ) {
+    if end > self.lookahead.get() {
+      self.lookahead.set( end );
+    }
+  }
+
+  /// Returns the offset just past the farthest byte any expression looked at.
+  pub fn lookahead( &self ) -> usize {
+    self.lookahead.get()
+  }
+
+  /// Applies `function` and returns its result along with the offset just
+  /// past the farthest byte it looked at, or zero if it looked at nothing.
+  pub fn measuringLookahead<T, F>( &self, function: F ) -> ( T, usize )
+      where F: FnOnce() -> T {
+    let outer_lookahead = self.lookahead.get();
+    self.lookahead.set( 0 );
+    let result = function();
+    let lookahead = self.lookahead.get();
+    self.lookahead.set( cmp::max( outer_lookahead, lookahead ) );
+    ( result, lookahead )
+  }
+
+  /// Makes the nodes of `previous`, the tree parsed from the input before
+  /// `edit` was applied to it, available for reuse while parsing the edited
+  /// input. Only the largest subtrees the edit couldn't have changed are kept.
+  /// They're shared with `previous`, and only moved to their place in the
+  /// edited input once they're reused.
+  pub fn reuse( &self, previous: &Node<'a>, edit: &Edit ) {
+    let shift = edit.shift();
+    let mut reusable = self.reusable.borrow_mut();
+    for node in edit.unaffectedNodes( previous ) {
+      // The label was put on the node by whatever used the rule, not by the
+      // rule itself. The labels inside the node are kept.
+      let mut node = node.clone();
+      node.label = "";
+      reusable.insert( ( node.name, shift.offset( node.start ) ),
+                       ( node, shift ) );
+    }
+  }
+
+  /// Builds the error describing the farthest failure seen so far. `input`
+  /// must be the full parse input.
+  pub fn parseError( &self, input: &[u8] ) -> ParseError {
+    self.parseErrorFrom( input, 0 )
+  }
+
+  /// Like `parseError`, but for a parse whose `input` starts at offset `start`
+  /// instead of zero. The offset of the returned error is still relative to
+  /// `start`.
+  pub fn parseErrorFrom( &self, input: &[u8], start: usize ) -> ParseError {
+    let failure = self.failure.borrow();
+    ParseError::new( input,
+                     cmp::max( failure.offset, start ) - start,
+                     failure.expected.clone() )
+  }
+
+  /// Returns the error for a parse that matched only the first `offset` bytes
+  /// of `input` when it had to match all of it. The end of the input was
+  /// expected at `offset`, along with whatever else failed to match there.
+  pub fn unconsumedError( &self, input: &[u8], offset: usize ) -> ParseError {
+    let failure = self.failure.borrow();
+    let mut expected = vec!( Expected::EndOfInput );
+    if failure.offset == offset {
+      expected.extend( failure.expected.iter().cloned() );
+    }
+    ParseError::new( input, offset, expected )
+  }
+}
+
+
+// Returns the node the rule `rule_name` produced at the offset of
+// `parse_state` in a previous parse, if it can be reused; see
+// `ParseContext::reuse`. Each node is handed out once.
+fn reusedResult<'a>( rule_name: &'static str, parse_state: &ParseState<'a> )
+    -> Option< ParseResult<'a> > {
+  let context = &parse_state.context;
+  if context.reusable.borrow().is_empty() {
+    return None;
+  }
+
+  let reused = context.reusable.borrow_mut()
+    .remove( &( rule_name, parse_state.offset ) );
+  reused.map( |( node, shift )| {
+    let node = shift.moveNode( &node );
+    context.lookedAt( node.lookahead );
+    let end = node.end;
+    ParseResult::oneNode( node, parse_state.advanceTo( end ) )
+  } )
+}
+
+
+// Remembers the node `rule_name` produced at the offset of `parse_state` in a
+// previous parse as the rule's result there, if the node can be reused.
+fn rememberReused<'a>( key: MemoKey, parse_state: &ParseState<'a> )
+    -> Option< Option< ParseResult<'a> > > {
+  reusedResult( key.0, parse_state ).map( |result| {
+    let lookahead = result.nodes[ 0 ].lookahead;
+    let result = Some( result );
+    remember( key, parse_state, &result, lookahead, Failure::none() );
+    result
+  } )
+}
+
+
+// Returns `Some` with the memoized result of the rule at the offset of
+// `parse_state`, or `None` if nothing was memoized there yet.
+fn cachedResult<'a>( key: &MemoKey, parse_state: &ParseState<'a> )
+    -> Option< Option< ParseResult<'a> > > {
+  let context = &parse_state.context;
+  let memo = context.memo.borrow();
+  memo.get( key ).map( |entry| {
+    context.lookedAt( entry.lookahead );
+    context.replayFailure( &entry.failure );
+    entry.result.as_ref().map( |&( ref nodes, end )| {
+      ParseResult { nodes: nodes.clone(),
+                    parse_state: parse_state.advanceTo( end ) }
+    } )
+  } )
+}
+
+
+fn remember<'a>( key: MemoKey,
+                 parse_state: &ParseState<'a>,
+                 result: &Option< ParseResult<'a> >,
+                 lookahead: usize,
+                 failure: Failure ) {
+  let entry = MemoEntry {
+    result: result.as_ref().map( |result| {
+      ( result.nodes.clone(), result.parse_state.offset )
+    } ),
+    lookahead: lookahead,
+    failure: failure
+  };
+  parse_state.context.memo.borrow_mut().insert( key, entry );
+}
+
+
+/// Applies `rule` at `parse_state` unless a result for the same rule and offset
+/// has already been computed, in which case a copy of that result is returned;
+/// the copy shares the trees below its nodes with the remembered result.
+/// This is what makes a parser a packrat parser.
+pub fn memoized<'a, F>( rule_name: &'static str,
+                        parse_state: &ParseState<'a>,
+                        rule: F ) -> Option< ParseResult<'a> >
+    where F: Fn( &ParseState<'a> ) -> Option< ParseResult<'a> > {
+  let key = ( rule_name, parse_state.offset );
+  match cachedResult( &key, parse_state )
+          .or_else( || rememberReused( key, parse_state ) ) {
+    Some( result ) => return result,
+    _ => ()
+  }
+
+  let context = &parse_state.context;
+  let ( ( result, lookahead ), failure ) = context.trackingFailure( || {
+    context.measuringLookahead( || rule( parse_state ) )
+  } );
+  context.replayFailure( &failure );
+  remember( key, parse_state, &result, lookahead, failure );
+  result
+}
+
+
+/// Applies the left-recursive `rule` at `parse_state` by growing a seed, as
+/// described in "Packrat Parsers Can Support Left Recursion" by Warth et al.
+///
+/// A failure is memoized for the rule first, so the left-recursive call fails
+/// and the rule matches one of its non-recursive alternatives. That match is
+/// memoized in turn and the rule is applied again, now with the recursive call
+/// returning the previous match. This repeats for as long as the match keeps
+/// getting longer.
+///
+/// Only the rules chosen as the heads of left-recursive cycles should use this;
+/// the other rules on the cycle must not be memoized, or they would keep
+/// returning results computed from a stale seed.
+pub fn leftRecursive<'a, F>( rule_name: &'static str,
+                             parse_state: &ParseState<'a>,
+                             rule: F ) -> Option< ParseResult<'a> >
+    where F: Fn( &ParseState<'a> ) -> Option< ParseResult<'a> > {
+  let key = ( rule_name, parse_state.offset );
+  match cachedResult( &key, parse_state )
+          .or_else( || rememberReused( key, parse_state ) ) {
+    Some( result ) => return result,
+    _ => ()
+  }
+
+  // The seeds are only used by the recursive calls, which record their own
+  // failures as they grow the seed.
+  remember( key, parse_state, &None, 0, Failure::none() );
+  let context = &parse_state.context;
+  let ( ( seed, lookahead ), failure ) = context.trackingFailure( || {
+    let mut seed: Option< ParseResult<'a> > = None;
+    let mut lookahead = 0;
+    loop {
+      // Failing to grow the seed is also part of the result; with more input,
+      // the seed might have grown.
+      let ( result, result_lookahead ) =
+        context.measuringLookahead( || rule( parse_state ) );
+      lookahead = cmp::max( lookahead, result_lookahead );
+      let grew = match ( &result, &seed ) {
+        ( &Some( ref result ), &Some( ref seed ) ) => {
+          result.parse_state.offset > seed.parse_state.offset
+        }
+        ( &Some( _ ), &None ) => true,
+        _ => false
+      };
+
+      if !grew {
+        return ( seed, lookahead );
+      }
+      remember( key, parse_state, &result, lookahead, Failure::none() );
+      seed = result;
+    }
+  } );
+  context.replayFailure( &failure );
+  remember( key, parse_state, &seed, lookahead, failure );
+  seed
+}
+
+
+/// Applies `rule` at `parse_state` and makes the nodes it produced the children
+/// of a node named `rule_name`. If a node `rule_name` produced at the same
+/// offset in a previous parse can be reused, that node is returned instead.
+/// This is how the rules that produce nodes are applied.
+pub fn namedNode<'a, F>( rule_name: &'static str,
+                         parse_state: &ParseState<'a>,
+                         rule: F ) -> Option< ParseResult<'a> >
+    where F: FnOnce( &ParseState<'a> ) -> Option< ParseResult<'a> > {
+  match reusedResult( rule_name, parse_state ) {
+    Some( result ) => return Some( result ),
+    _ => ()
+  }
+
+  let context = &parse_state.context;
+  let ( result, lookahead ) =
+    context.measuringLookahead( || rule( parse_state ) );
+  result.map( |result| {
+    let mut node = Node::withChildren( rule_name, result.nodes );
+    node.lookahead = cmp::max( lookahead, node.end );
+    ParseResult::oneNode( node, result.parse_state )
+  } )
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::cell::Cell;
+  use base;
+  use base::{Node, Data, Children, ParseResult, ParseState, ParseError,
+             Expression, Expected};
+  use super::{memoized, leftRecursive, namedNode, ParseContext};
+
+  #[test]
+  fn memoized_AppliesRuleOncePerOffset() {
+    let orig_state = input_state!( "foo" );
+    let num_calls = Cell::new( 0 );
+    let rule = |parse_state: &ParseState<'static>| {
+      num_calls.set( num_calls.get() + 1 );
+      lit!( "foo" ).apply( parse_state )
+    };
+
+    for _ in 0 .. 3 {
+      match memoized( "foo", &orig_state, &rule ) {
+        Some( ParseResult{ nodes, parse_state } ) => {
+          assert_eq!( nodes[ 0 ],
+                      Node::withoutName( 0, 3, Data( b"foo" ) ) );
+          assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
+        }
+        _ => panic!( "No match." )
+      }
+    }
+    assert_eq!( num_calls.get(), 1 );
+  }
+
+
+  #[test]
+  fn memoized_RemembersFailure() {
+    let orig_state = input_state!( "bar" );
+    let num_calls = Cell::new( 0 );
+    let rule = |parse_state: &ParseState<'static>| {
+      num_calls.set( num_calls.get() + 1 );
+      lit!( "foo" ).apply( parse_state )
+    };
+
+    assert!( memoized( "foo", &orig_state, &rule ).is_none() );
+    assert!( memoized( "foo", &orig_state, &rule ).is_none() );
+    assert_eq!( num_calls.get(), 1 );
+  }
+
+
+  #[test]
+  fn memoized_KeysOnRuleAndOffset() {
+    let orig_state = input_state!( "foofoo" );
+    let num_calls = Cell::new( 0 );
+    let rule = |parse_state: &ParseState<'static>| {
+      num_calls.set( num_calls.get() + 1 );
+      lit!( "foo" ).apply( parse_state )
+    };
+
+    assert!( memoized( "foo", &orig_state, &rule ).is_some() );
+    assert!( memoized( "bar", &orig_state, &rule ).is_some() );
+    assert!( memoized( "foo", &orig_state.advanceTo( 3 ), &rule ).is_some() );
+    assert_eq!( num_calls.get(), 3 );
+  }
+
+
+  fn plainB<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    namedNode( "B", parse_state, |parse_state| {
+      seq!( lit!( "b" ), lit!( "c" ) ).apply( parse_state )
+    } )
+  }
+
+  fn memoizedB<'a>( parse_state: &ParseState<'a> )
+      -> Option< ParseResult<'a> > {
+    memoized( "B", parse_state, plainB )
+  }
+
+  // The error for parsing "bd" with `A <- !B 'x' / B`, where `b` is `B`.
+  fn errorWithB( b: base::Rule ) -> ParseError {
+    let orig_state = input_state!( "bd" );
+    let b = &base::WrapEx { rule: b, name: "B" };
+    assert!( or!( seq!( not!( b ), lit!( "x" ) ), b )
+               .apply( &orig_state ).is_none() );
+    orig_state.context.parseError( b"bd" )
+  }
+
+  #[test]
+  fn memoized_ReplaysExpectedOfQuietApplication() {
+    let error = errorWithB( plainB );
+    assert_eq!( error.offset, 1 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"c" ) ) );
+    assert_eq!( errorWithB( memoizedB ), error );
+  }
+
+
+  #[test]
+  fn memoized_SharesChildrenOfRememberedNodes() {
+    let orig_state = input_state!( "foo" );
+    let rule = |parse_state: &ParseState<'static>| {
+      namedNode( "A", parse_state, |parse_state| {
+        seq!( lit!( "f" ), lit!( "oo" ) ).apply( parse_state )
+      } )
+    };
+    let children = || {
+      match memoized( "A", &orig_state, &rule ).unwrap().nodes[ 0 ].contents {
+        Children( ref children ) => &**children as *const Vec<Node>,
+        _ => panic!( "No children." )
+      }
+    };
+    assert!( children() == children() );
+  }
+
+
+  // Sum <- Sum '+' '1' / '1'
+  fn sum<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    leftRecursive( "sum", parse_state, |parse_state| {
+      or!( seq!( ex!( sum ), lit!( "+" ), lit!( "1" ) ),
+           lit!( "1" ) ).apply( parse_state )
+    } )
+  }
+
+
+  #[test]
+  fn leftRecursive_GrowsSeed() {
+    let orig_state = input_state!( "1+1+1-" );
+    match sum( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes.len(), 5 );
+        assert_eq!( nodes[ 0 ], Node::withoutName( 0, 1, Data( b"1" ) ) );
+        assert_eq!( nodes[ 4 ], Node::withoutName( 4, 5, Data( b"1" ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 5 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn leftRecursive_NoMatch() {
+    assert!( sum( &input_state!( "+1" ) ).is_none() );
+  }
+
+
+  // Left <- Right 'b' / 'a'
+  // Right <- Left
+  fn left<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    leftRecursive( "left", parse_state, |parse_state| {
+      or!( seq!( ex!( right ), lit!( "b" ) ), lit!( "a" ) ).apply( parse_state )
+    } )
+  }
+
+
+  fn right<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    ex!( left ).apply( parse_state )
+  }
+
+
+  #[test]
+  fn leftRecursive_Indirect() {
+    let orig_state = input_state!( "abbb" );
+    match right( &orig_state ) {
+      Some( ParseResult{ parse_state, .. } ) => {
+        assert_eq!( parse_state, orig_state.advanceTo( 4 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn namedNode_ReusesPreviousNode() {
+    let previous = Node::withChildren( "A", vec!(
+      Node::withoutName( 0, 2, Data( b"ab" ) ) ) );
+    let edit = base::Edit::new( 0, 0, b"x" );
+    let input = b"xab";
+    let orig_state = ParseState::new( input );
+    orig_state.context.reuse( &previous, &edit );
+
+    let reused = namedNode( "A", &orig_state.advanceTo( 1 ), |_| {
+      panic!( "The node should be reused." )
+    } ).unwrap();
+    assert_eq!( reused.nodes[ 0 ],
+                Node::withChildren( "A", vec!(
+                  Node::withoutName( 1, 3, Data( b"ab" ) ) ) ) );
+    assert_eq!( reused.parse_state, orig_state.advanceTo( 3 ) );
+
+    // Reused nodes are handed out once, and the rule is applied as usual.
+    let applied = namedNode( "A", &orig_state.advanceTo( 1 ), |parse_state| {
+      lit!( "ab" ).apply( parse_state )
+    } ).unwrap();
+    assert_eq!( applied.nodes, reused.nodes );
+  }
+
+
+  #[test]
+  fn memoized_RemembersReusedNode() {
+    let previous = Node::withChildren( "A", vec!(
+      Node::withoutName( 0, 2, Data( b"ab" ) ) ) );
+    let edit = base::Edit::new( 0, 0, b"x" );
+    let orig_state = ParseState::new( b"xab" );
+    orig_state.context.reuse( &previous, &edit );
+
+    let rule = |parse_state: &ParseState<'static>| {
+      memoized( "A", parse_state, |_| panic!( "The node should be reused." ) )
+    };
+    let first = rule( &orig_state.advanceTo( 1 ) ).unwrap();
+    let second = rule( &orig_state.advanceTo( 1 ) ).unwrap();
+    assert_eq!( first.nodes[ 0 ].start, 1 );
+    assert_eq!( second.nodes, first.nodes );
+    assert_eq!( second.parse_state, orig_state.advanceTo( 3 ) );
+  }
+
+
+  #[test]
+  fn ParseContext_KeepsFarthestExpected() {
+    let context = ParseContext::new();
+    context.expected( 1, Expected::Literal( b"a" ) );
+    context.expected( 3, Expected::Literal( b"b" ) );
+    context.expected( 3, Expected::Any );
+    context.expected( 2, Expected::Literal( b"c" ) );
+
+    let error = context.parseError( b"xxxx" );
+    assert_eq!( error.offset, 3 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"b" ),
+                                      Expected::Any ) );
+  }
+
+
+  #[test]
+  fn ParseContext_QuietlyIgnoresExpected() {
+    let context = ParseContext::new();
+    context.expected( 1, Expected::Any );
+    context.quietly( || context.expected( 2, Expected::Literal( b"a" ) ) );
+
+    let error = context.parseError( b"xx" );
+    assert_eq!( error.offset, 1 );
+    assert_eq!( error.expected, vec!( Expected::Any ) );
+  }
+
+
+  #[test]
+  fn ParseContext_LookaheadIncludesQuietExpressions() {
+    let orig_state = input_state!( "ab" );
+    assert!( seq!( lit!( "a" ), not!( lit!( "bc" ) ) )
+               .apply( &orig_state ).is_some() );
+    assert_eq!( orig_state.context.lookahead(), 3 );
+  }
+
+
+  #[test]
+  fn ParseContext_RuleFailedReplacesExpectedAtRuleStart() {
+    let context = ParseContext::new();
+    context.expected( 1, Expected::Literal( b"a" ) );
+    let checkpoint = context.failureCheckpoint();
+    context.expected( 1, Expected::Literal( b"b" ) );
+    context.expected( 1, Expected::Literal( b"c" ) );
+    context.ruleFailed( checkpoint, 1, "Foo" );
+
+    let error = context.parseError( b"xx" );
+    assert_eq!( error.offset, 1 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"a" ),
+                                      Expected::Rule( "Foo" ) ) );
+  }
+
+
+  #[test]
+  fn ParseContext_RuleFailedKeepsFartherExpected() {
+    let context = ParseContext::new();
+    let checkpoint = context.failureCheckpoint();
+    context.expected( 2, Expected::Literal( b"b" ) );
+    context.ruleFailed( checkpoint, 1, "Foo" );
+
+    let error = context.parseError( b"xx" );
+    assert_eq!( error.offset, 2 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"b" ) ) );
+  }
+
+
+  #[test]
+  fn ParseContext_UnconsumedErrorExpectsEndOfInput() {
+    let context = ParseContext::new();
+    context.expected( 2, Expected::Literal( b"b" ) );
+
+    let error = context.unconsumedError( b"aab", 2 );
+    assert_eq!( error.offset, 2 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"b" ),
+                                      Expected::EndOfInput ) );
+
+    // What failed farther on wasn't expected where the match stopped.
+    let error = context.unconsumedError( b"aab", 1 );
+    assert_eq!( error.offset, 1 );
+    assert_eq!( error.expected, vec!( Expected::EndOfInput ) );
+  }
+}
@@ -0,0 +1,74 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::{Expression, ParseState, ParseResult};
+
+/// The cut operator `%`. It matches without consuming input and commits the
+/// innermost choice it's in to the current alternative: if the alternative
+/// fails after the cut, the choice fails without trying the alternatives after
+/// it. That makes the parser report the error in the alternative the input was
+/// meant for, and spares it work that can't succeed.
+pub struct Cut;
+impl Expression for Cut {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    parse_state.context.cut();
+    Some( ParseResult::fromParseState( parse_state.clone() ) )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base;
+  use base::{ParseState, ParseResult, Expression, Expected};
+  use super::Cut;
+
+  fn A<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    seq!( lit!( "a" ), &Cut ).apply( parse_state )
+  }
+
+  #[test]
+  fn Cut_CommitsChoice() {
+    let orig_state = input_state!( "ac" );
+    assert!( or!( seq!( lit!( "a" ), &Cut, lit!( "b" ) ), lit!( "ac" ) )
+               .apply( &orig_state ).is_none() );
+
+    let error = orig_state.context.parseError( b"ac" );
+    assert_eq!( error.offset, 1 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"b" ) ) );
+  }
+
+
+  #[test]
+  fn Cut_NoCutTriesAllAlternatives() {
+    assert!( or!( seq!( lit!( "a" ), lit!( "b" ) ), lit!( "ac" ) )
+               .apply( &input_state!( "ac" ) ).is_some() );
+  }
+
+
+  #[test]
+  fn Cut_OnlyCommitsInnermostChoice() {
+    assert!( or!( seq!( or!( seq!( lit!( "a" ), &Cut ), lit!( "z" ) ),
+                        lit!( "b" ) ),
+                  lit!( "ac" ) )
+               .apply( &input_state!( "ac" ) ).is_some() );
+  }
+
+
+  #[test]
+  fn Cut_DoesNotLeaveRule() {
+    assert!( or!( seq!( ex!( A ), lit!( "b" ) ), lit!( "ac" ) )
+               .apply( &input_state!( "ac" ) ).is_some() );
+  }
+}
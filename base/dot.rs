@@ -11,12 +11,13 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use super::{Expression, ParseState, ParseResult};
-use base::unicode::{bytesFollowing, readCodepoint};
+use super::{Expression, ParseState, ParseResult, Expected};
+use super::unicode::{bytesFollowing, readCodepoint};
 
 pub struct Dot;
 impl Expression for Dot {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    parse_state.lookAtChar();
     match readCodepoint( parse_state.input ) {
       Some( _ ) => {
         let num_following = bytesFollowing( parse_state.input[ 0 ] ).unwrap();
@@ -28,7 +29,22 @@ impl Expression for Dot {
 
     match parse_state.input.get( 0 ) {
       Some( _ ) => parse_state.offsetToResult( parse_state.offset + 1 ),
-      _ => None
+      _ => parse_state.expected( Expected::Any )
+    }
+  }
+}
+
+
+/// What `.` is in byte mode: it matches exactly one byte, even where the input
+/// has a multi-byte UTF-8 char.
+pub struct AnyByte;
+impl Expression for AnyByte {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    parse_state.lookAt( 1 );
+    match parse_state.input.get( 0 ) {
+      Some( _ ) => parse_state.offsetToResult( parse_state.offset + 1 ),
+      _ => parse_state.expected( Expected::AnyByte )
     }
   }
 }
@@ -36,16 +52,17 @@ impl Expression for Dot {
 
 #[cfg(test)]
 mod tests {
-  use super::{Dot};
-  use base::{Node, Data, ParseResult, ParseState, Expression};
+  use super::{Dot, AnyByte};
+  use base::{Node, Data, ParseResult, Expression, Expected};
 
   #[test]
   fn Dot_Match_InputOneChar() {
-    match Dot.apply( &input_state!( "x" ) ) {
+    let orig_state = input_state!( "x" );
+    match Dot.apply( &orig_state ) {
       Some( ParseResult{ nodes, parse_state } ) => {
         assert_eq!( nodes[ 0 ],
                     Node::withoutName( 0, 1, Data( b"x" ) ) );
-        assert_eq!( parse_state, ParseState{ input: &[], offset: 1 } );
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
       }
       _ => panic!( "No match!" )
     };
@@ -54,11 +71,12 @@ mod tests {
 
   #[test]
   fn Dot_Match_InputOneWideChar() {
-    match Dot.apply( &input_state!( "葉" ) ) {
+    let orig_state = input_state!( "葉" );
+    match Dot.apply( &orig_state ) {
       Some( ParseResult{ nodes, parse_state } ) => {
         assert_eq!( nodes[ 0 ],
                     Node::withoutName( 0, 3, Data( "葉".as_bytes() ) ) );
-        assert_eq!( parse_state, ParseState{ input: &[], offset: 3 } );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
       }
       _ => panic!( "No match!" )
     };
@@ -67,12 +85,12 @@ mod tests {
 
   #[test]
   fn Dot_Match_InputSeveralChars() {
-    match Dot.apply( &input_state!( "xb" ) ) {
+    let orig_state = input_state!( "xb" );
+    match Dot.apply( &orig_state ) {
       Some( ParseResult{ nodes, parse_state } ) => {
         assert!( nodes[ 0 ] ==
                  Node::withoutName( 0, 1, Data( b"x" ) ) );
-        assert_eq!( parse_state, ParseState{ input: b"b",
-                                             offset: 1 } );
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
       }
       _ => panic!( "No match!" )
     };
@@ -83,4 +101,27 @@ mod tests {
   fn Dot_NoMatch() {
     assert!( Dot.apply( &input_state!( "" ) ).is_none() )
   }
+
+
+  #[test]
+  fn AnyByte_MatchesOneByteOfWideChar() {
+    let orig_state = input_state!( "葉" );
+    match AnyByte.apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        let first_byte = &"葉".as_bytes()[ .. 1 ];
+        assert_eq!( nodes[ 0 ], Node::withoutName( 0, 1, Data( first_byte ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
+      }
+      _ => panic!( "No match!" )
+    };
+  }
+
+
+  #[test]
+  fn AnyByte_NoMatch_RecordsExpected() {
+    let orig_state = input_state!( "" );
+    assert!( AnyByte.apply( &orig_state ).is_none() );
+    assert_eq!( orig_state.context.parseError( b"" ).expected,
+                vec!( Expected::AnyByte ) );
+  }
 }
@@ -0,0 +1,211 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ascii;
+use std::fmt;
+use std::str;
+
+/// Something the parser expected to find at the offset where it failed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Expected {
+  /// The literal with the given bytes.
+  Literal( &'static [u8] ),
+
+  /// The literal with the given bytes, in any case.
+  LiteralIgnoringCase( &'static [u8] ),
+
+  /// A character from the class with the given contents, so `a-z` for `[a-z]`.
+  Class( &'static [u8] ),
+
+  /// A character from the class with the given contents, in any case.
+  ClassIgnoringCase( &'static [u8] ),
+
+  /// Any character; this is what a `.` expects.
+  Any,
+
+  /// Any single byte; this is what a `.` expects in byte mode.
+  AnyByte,
+
+  /// A match of the rule with the given name.
+  Rule( &'static str ),
+
+  /// The end of the input; a parse that must consume all of its input expects
+  /// this where its start rule stopped matching.
+  EndOfInput
+}
+
+
+// Quotes the literal text, as a byte string if it isn't valid UTF-8 (which
+// only byte mode literals can be).
+fn literalText( text: &[u8] ) -> String {
+  match str::from_utf8( text ) {
+    Ok( text ) => format!( "{:?}", text ),
+    Err( _ ) => {
+      let escaped: Vec<u8> = text.iter()
+        .flat_map( |byte| ascii::escape_default( *byte ) )
+        .collect();
+      format!( "b\"{}\"", String::from_utf8_lossy( &escaped ) )
+    }
+  }
+}
+
+
+impl fmt::Display for Expected {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    match *self {
+      Expected::Literal( text ) => {
+        write!( formatter, "{}", literalText( text ) )
+      }
+      Expected::LiteralIgnoringCase( text ) => {
+        write!( formatter, "{}i", literalText( text ) )
+      }
+      Expected::Class( contents ) => {
+        write!( formatter, "[{}]", String::from_utf8_lossy( contents ) )
+      }
+      Expected::ClassIgnoringCase( contents ) => {
+        write!( formatter, "[{}]i", String::from_utf8_lossy( contents ) )
+      }
+      Expected::Any => write!( formatter, "any character" ),
+      Expected::AnyByte => write!( formatter, "any byte" ),
+      Expected::Rule( name ) => write!( formatter, "{}", name ),
+      Expected::EndOfInput => write!( formatter, "end of input" )
+    }
+  }
+}
+
+
+/// Describes why the parse failed. Since a PEG parser backtracks, the reported
+/// position is the farthest one the parser reached in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  /// The byte (NOT char) offset of the farthest failure in the parse input.
+  pub offset: usize,
+
+  /// The 1-based line of `offset`.
+  pub line: usize,
+
+  /// The 1-based column of `offset`, counted in chars.
+  pub column: usize,
+
+  /// Everything that would have allowed the parser to proceed past `offset`.
+  /// Sorted and without duplicates.
+  pub expected: Vec<Expected>
+}
+
+
+impl ParseError {
+  pub fn new( input: &[u8], offset: usize, mut expected: Vec<Expected> )
+      -> ParseError {
+    expected.sort();
+    expected.dedup();
+
+    let before = &input[ .. offset ];
+    let line_start = before.iter().rposition( |byte| *byte == b'\n' )
+      .map_or( 0, |index| index + 1 );
+    let column = String::from_utf8_lossy( &before[ line_start .. ] )
+      .chars().count() + 1;
+    let line = before.iter().filter( |byte| **byte == b'\n' ).count() + 1;
+
+    ParseError { offset: offset,
+                 line: line,
+                 column: column,
+                 expected: expected }
+  }
+}
+
+
+impl fmt::Display for ParseError {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    try!( write!( formatter, "line {}, column {}: ", self.line, self.column ) );
+    match self.expected.len() {
+      0 => return write!( formatter, "unexpected input" ),
+      1 => try!( write!( formatter, "expected " ) ),
+      _ => try!( write!( formatter, "expected one of " ) )
+    }
+
+    for ( index, expected ) in self.expected.iter().enumerate() {
+      if index != 0 {
+        try!( write!( formatter, ", " ) );
+      }
+      try!( write!( formatter, "{}", expected ) );
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::{ParseError, Expected};
+
+  #[test]
+  fn ParseError_LineAndColumn() {
+    let error = ParseError::new( b"ab\ncd\nef", 7, vec!() );
+    assert_eq!( error.line, 3 );
+    assert_eq!( error.column, 2 );
+
+    let error = ParseError::new( b"ab\ncd\nef", 0, vec!() );
+    assert_eq!( error.line, 1 );
+    assert_eq!( error.column, 1 );
+
+    let error = ParseError::new( b"ab\n", 3, vec!() );
+    assert_eq!( error.line, 2 );
+    assert_eq!( error.column, 1 );
+  }
+
+
+  #[test]
+  fn ParseError_ColumnCountsChars() {
+    let error = ParseError::new( "葉葉x".as_bytes(), 6, vec!() );
+    assert_eq!( error.column, 3 );
+  }
+
+
+  #[test]
+  fn ParseError_SortsAndDedupsExpected() {
+    let error = ParseError::new( b"", 0, vec!( Expected::Rule( "Foo" ),
+                                              Expected::Any,
+                                              Expected::Literal( b"x" ),
+                                              Expected::Any ) );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"x" ),
+                                      Expected::Any,
+                                      Expected::Rule( "Foo" ) ) );
+  }
+
+
+  #[test]
+  fn ParseError_Display() {
+    let error = ParseError::new( b"a\nb", 2, vec!( Expected::Literal( b"x" ),
+                                                  Expected::Class( b"0-9" ),
+                                                  Expected::Rule( "Foo" ) ) );
+    assert_eq!( error.to_string(),
+                r#"line 2, column 1: expected one of "x", [0-9], Foo"# );
+
+    let error = ParseError::new( b"", 0,
+                                 vec!( Expected::LiteralIgnoringCase( b"x" ),
+                                       Expected::ClassIgnoringCase( b"a-z" ) ) );
+    assert_eq!( error.to_string(),
+                r#"line 1, column 1: expected one of "x"i, [a-z]i"# );
+
+    let error = ParseError::new( b"a", 1, vec!( Expected::Any ) );
+    assert_eq!( error.to_string(), "line 1, column 2: expected any character" );
+
+    let error = ParseError::new( b"", 0, vec!( Expected::Literal( b"\x89PNG" ),
+                                              Expected::AnyByte ) );
+    assert_eq!( error.to_string(),
+                r#"line 1, column 1: expected one of b"\x89PNG", any byte"# );
+
+    let error = ParseError::new( b"ab", 1, vec!( Expected::EndOfInput ) );
+    assert_eq!( error.to_string(), "line 1, column 2: expected end of input" );
+  }
+}
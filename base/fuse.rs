@@ -16,12 +16,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! fuse( ( $ex:expr ) => ( &base::Fuse::new( $ex ) ); );
 
 pub struct Fuse<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'a> Fuse<'a> {
-  pub fn new( expr: & Expression ) -> Fuse {
+  pub fn new( expr: &'a dyn Expression ) -> Fuse<'a> {
     Fuse { expr: expr }
   }
 }
@@ -0,0 +1,198 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::rc::Rc;
+use super::{Node, Children};
+
+/// A change to the parse input: the bytes in `start .. end` were replaced with
+/// `replacement`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edit<'e> {
+  pub start: usize,
+  pub end: usize,
+  pub replacement: &'e [u8]
+}
+
+
+impl<'e> Edit<'e> {
+  pub fn new( start: usize, end: usize, replacement: &'e [u8] ) -> Edit<'e> {
+    Edit { start: start, end: end, replacement: replacement }
+  }
+
+  /// Returns `input` with the edit applied to it.
+  pub fn apply( &self, input: &[u8] ) -> Vec<u8> {
+    let mut output = input[ .. self.start ].to_vec();
+    output.extend( self.replacement.iter().cloned() );
+    output.extend( input[ self.end .. ].iter().cloned() );
+    output
+  }
+
+  /// Returns how the input after the edit is shifted from the input before
+  /// it.
+  pub fn shift( &self ) -> Shift {
+    Shift { from: self.end, to: self.start + self.replacement.len() }
+  }
+
+  // Returns true if the edit could have changed `node`, because the node
+  // looked at some of the replaced input. A node right after the edit isn't
+  // affected; it's only reused when parsing gets to its shifted offset.
+  fn affects( &self, node: &Node ) -> bool {
+    node.lookahead > self.start && node.start < self.end
+  }
+
+  /// Returns the largest subtrees of `previous`, the tree parsed from the input
+  /// before the edit, that the edit couldn't have changed. Only nodes named
+  /// after the rule that produced them and that matched some input are
+  /// returned, since only those can stand in for applying the rule again. The
+  /// nodes are where they were before the edit; see `Shift::moveNode`.
+  pub fn unaffectedNodes<'p, 'a>( &self, previous: &'p Node<'a> )
+      -> Vec< &'p Node<'a> > {
+    let mut nodes = vec!();
+    let mut stack = vec!( previous );
+    while let Some( node ) = stack.pop() {
+      if !node.name.is_empty() && node.start < node.end &&
+         !self.affects( node ) {
+        nodes.push( node );
+        continue;
+      }
+
+      match node.contents {
+        Children( ref children ) => stack.extend( children.iter().rev() ),
+        _ => ()
+      }
+    }
+    nodes
+  }
+}
+
+
+/// How an `Edit` shifts the input after it: what was at offset `from` before
+/// the edit is at offset `to` after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift {
+  from: usize,
+  to: usize
+}
+
+
+impl Shift {
+  /// Returns where `offset` in the input before the edit ends up after it.
+  /// Offsets before the end of the edited range stay where they are.
+  pub fn offset( &self, offset: usize ) -> usize {
+    if offset >= self.from {
+      offset - self.from + self.to
+    } else {
+      offset
+    }
+  }
+
+  /// Returns `node`, a node the edit didn't affect, at its place in the input
+  /// after the edit. A node before the edit stays where it is, so it's shared
+  /// with the tree before the edit instead of being copied; so is a node after
+  /// an edit that didn't change the length of the input. Only nodes that have
+  /// to move are copied, with their offsets shifted. Their data still refers
+  /// to the input before the edit, where it's the same as after it.
+  pub fn moveNode<'a>( &self, node: &Node<'a> ) -> Node<'a> {
+    if node.start < self.from || self.from == self.to {
+      return node.clone();
+    }
+
+    let contents = match node.contents {
+      Children( ref children ) => {
+        Children( Rc::new( children.iter()
+                             .map( |child| self.moveNode( child ) )
+                             .collect() ) )
+      }
+      ref contents => contents.clone()
+    };
+    Node { name: node.name,
+           label: node.label,
+           start: self.offset( node.start ),
+           end: self.offset( node.end ),
+           lookahead: self.offset( node.lookahead ),
+           contents: contents }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base::{Node, Data};
+  use super::Edit;
+
+  fn leaf<'a>( name: &'static str,
+               input: &'a [u8],
+               start: usize,
+               end: usize,
+               lookahead: usize ) -> Node<'a> {
+    let mut node = Node::withoutName( start, end, Data( &input[ start .. end ] ) );
+    node.name = name;
+    node.lookahead = lookahead;
+    node
+  }
+
+  #[test]
+  fn Edit_Apply() {
+    assert_eq!( Edit::new( 1, 3, b"xyz" ).apply( b"abcd" ), b"axyzd".to_vec() );
+    assert_eq!( Edit::new( 4, 4, b"e" ).apply( b"abcd" ), b"abcde".to_vec() );
+  }
+
+
+  #[test]
+  fn Edit_UnaffectedNodes() {
+    let old_input = b"ab cd ef";
+    let tree = Node::withChildren( "List", vec!(
+      leaf( "Word", old_input, 0, 2, 3 ),
+      leaf( "Word", old_input, 3, 5, 6 ),
+      leaf( "Word", old_input, 6, 8, 9 ) ) );
+
+    let edit = Edit::new( 4, 5, b"xx" );
+    let nodes = edit.unaffectedNodes( &tree );
+    assert_eq!( nodes, vec!( &leaf( "Word", old_input, 0, 2, 3 ),
+                             &leaf( "Word", old_input, 6, 8, 9 ) ) );
+  }
+
+
+  #[test]
+  fn Edit_UnaffectedNodes_Insertion() {
+    let old_input = b"ab cd";
+    let tree = Node::withChildren( "List", vec!(
+      leaf( "Word", old_input, 0, 2, 3 ),
+      leaf( "Word", old_input, 3, 5, 6 ) ) );
+
+    // The first word looked at the space after it, so it could now be "abx".
+    let edit = Edit::new( 2, 2, b"x" );
+    assert_eq!( edit.unaffectedNodes( &tree ),
+                vec!( &leaf( "Word", old_input, 3, 5, 6 ) ) );
+  }
+
+
+  #[test]
+  fn Shift_MoveNode() {
+    let old_input = b"ab cd ef";
+    let mut word = leaf( "Word", old_input, 6, 8, 9 );
+    word.label = "last";
+    let tree = Node::withChildren( "List", vec!(
+      leaf( "Word", old_input, 3, 5, 6 ), word ) );
+
+    let shift = Edit::new( 1, 2, b"xyz" ).shift();
+    let moved = shift.moveNode( &tree );
+    assert_eq!( ( moved.start, moved.end, moved.lookahead ), ( 5, 10, 10 ) );
+    assert_eq!( moved.child( "last" ).map( |node| node.start ), Some( 8 ) );
+    assert_eq!( moved.matchedText(), "cdef" );
+
+    // Nodes before the edit aren't moved.
+    let first = leaf( "Word", old_input, 0, 1, 1 );
+    assert_eq!( shift.moveNode( &first ), first );
+  }
+}
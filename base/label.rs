@@ -0,0 +1,69 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::{Expression, ParseState, ParseResult};
+
+macro_rules! label( ( $label:ident, $ex:expr ) => (
+    &base::Label::new( stringify!( $label ), $ex ) ); );
+
+pub struct Label<'a> {
+  label: &'static str,
+  expr: &'a ( dyn Expression + 'a )
+}
+
+
+impl<'a> Label<'a> {
+  pub fn new( label: &'static str, expr: &'a dyn Expression ) -> Label<'a> {
+    Label { label: label, expr: expr }
+  }
+}
+
+
+impl<'b> Expression for Label<'b> {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    self.expr.apply( parse_state ).map( |mut result| {
+      for node in result.nodes.iter_mut() {
+        node.label = self.label;
+      }
+      result
+    } )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base;
+  use base::{ParseResult, Expression};
+
+  #[test]
+  fn Label_Match() {
+    let orig_state = input_state!( "aab" );
+    match label!( x, seq!( plus!( lit!( "a" ) ), lit!( "b" ) ) )
+        .apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes.len(), 3 );
+        assert!( nodes.iter().all( |node| node.label == "x" ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Label_NoMatch() {
+    assert!( label!( x, lit!( "b" ) ).apply( &input_state!( "a" ) ).is_none() );
+  }
+}
@@ -11,51 +11,131 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use super::{Expression, ParseState, ParseResult};
+use std::cmp;
+use super::unicode::{bytesFollowing, readCodepoint, foldCase};
+use super::{Expression, ParseState, ParseResult, Expected};
 
 macro_rules! lit( ( $ex:expr ) => (
       &base::Literal::new( $ex.as_bytes() ) ) );
 
+macro_rules! ilit( ( $ex:expr ) => (
+      &base::Literal::ignoringCase( $ex.as_bytes() ) ) );
+
+// Byte mode literals, which take byte strings.
+macro_rules! blit( ( $ex:expr ) => (
+      &base::Literal::new( $ex ) ) );
+
+macro_rules! iblit( ( $ex:expr ) => (
+      &base::Literal::ignoringCase( $ex ) ) );
+
+
+// The length of the char at the start of `input`, or 1 if there's no UTF-8
+// sequence there.
+fn charLength( input: &[u8] ) -> usize {
+  input.get( 0 )
+    .and_then( |byte| bytesFollowing( *byte ) )
+    .map_or( 1, |num_following| num_following + 1 )
+}
+
 
 pub struct Literal {
-  text: &'static [u8]
+  text: &'static [u8],
+  ignore_case: bool
 }
 
 
 impl Literal {
   pub fn new( text: &'static [u8] ) -> Literal {
-    Literal { text: text }
+    Literal { text: text, ignore_case: false }
   }
-}
 
+  /// A literal that matches its text in any case, using simple case folding
+  /// on the UTF-8 chars of the text and the input. The matched input can have
+  /// a different length than the text.
+  pub fn ignoringCase( text: &'static [u8] ) -> Literal {
+    Literal { text: text, ignore_case: true }
+  }
 
-impl Expression for Literal {
-  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+
+  fn applyExact<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
+    let num_matching = parse_state.input.iter().zip( self.text.iter() )
+      .take_while( |&( a, b )| a == b )
+      .count();
+    parse_state.lookAt( cmp::min( num_matching + 1, self.text.len() ) );
+
     if parse_state.input.len() < self.text.len() ||
        &parse_state.input[ .. self.text.len() ] != self.text {
-      return None;
+      return parse_state.expected( Expected::Literal( self.text ) );
     }
 
     parse_state.offsetToResult( parse_state.offset + self.text.len() )
   }
+
+
+  // Returns the length of the matched input, or how much of the input was
+  // looked at to find the mismatch.
+  fn matchIgnoringCase( &self, input: &[u8] ) -> Result< usize, usize > {
+    let mut text_index = 0;
+    let mut input_index = 0;
+    while text_index < self.text.len() {
+      let text = &self.text[ text_index.. ];
+      let rest = &input[ input_index.. ];
+      match ( readCodepoint( text ), readCodepoint( rest ) ) {
+        ( Some( a ), Some( b ) ) if foldCase( a ) == foldCase( b ) => {}
+        ( None, None ) if rest.first() == text.first() => {}
+        _ => return Err( input_index + charLength( rest ) )
+      }
+      text_index += charLength( text );
+      input_index += charLength( rest );
+    }
+    Ok( input_index )
+  }
+
+
+  fn applyIgnoringCase<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    match self.matchIgnoringCase( parse_state.input ) {
+      Ok( length ) => {
+        parse_state.lookAt( length );
+        parse_state.offsetToResult( parse_state.offset + length )
+      }
+      Err( looked_at ) => {
+        parse_state.lookAt( looked_at );
+        parse_state.expected( Expected::LiteralIgnoringCase( self.text ) )
+      }
+    }
+  }
+}
+
+
+impl Expression for Literal {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    if self.ignore_case {
+      self.applyIgnoringCase( parse_state )
+    } else {
+      self.applyExact( parse_state )
+    }
+  }
 }
 
 
 #[cfg(test)]
 mod tests {
   use base;
-  use base::{Node, Data, ParseResult, ParseState, Expression};
+  use base::{Node, Data, ParseResult, Expression, Expected};
+  use base::test_utils::ToParseState;
 
   #[test]
   fn Literal_Match() {
     let expr = lit!( "foo" );
-    match expr.apply( &input_state!( "foobar" ) ) {
+    let orig_state = input_state!( "foobar" );
+    match expr.apply( &orig_state ) {
       Some( ParseResult{ nodes, parse_state } ) => {
         assert_eq!( nodes[ 0 ],
                     Node::withoutName( 0, 3, Data( b"foo" ) ) );
-        assert_eq!( parse_state, ParseState{ input: b"bar",
-                                             offset: 3 } );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
       }
       _ => panic!( "No match!" )
     };
@@ -68,4 +148,86 @@ mod tests {
     assert!( expr.apply( &input_state!( "foobar" ) ).is_none() );
     assert!( expr.apply( &input_state!( "" ) ).is_none() );
   }
+
+
+  #[test]
+  fn Literal_LooksUpToFirstMismatch() {
+    let orig_state = input_state!( "foobar" );
+    assert!( lit!( "fox" ).apply( &orig_state ).is_none() );
+    assert_eq!( orig_state.context.lookahead(), 3 );
+    assert!( lit!( "barn" ).apply( &orig_state.advanceTo( 3 ) ).is_none() );
+    assert_eq!( orig_state.context.lookahead(), 7 );
+  }
+
+
+  #[test]
+  fn Literal_NoMatch_RecordsExpected() {
+    let orig_state = input_state!( "foobar" );
+    assert!( lit!( "zoo" ).apply( &orig_state.advanceTo( 2 ) ).is_none() );
+
+    let error = orig_state.context.parseError( b"foobar" );
+    assert_eq!( error.offset, 2 );
+    assert_eq!( error.expected, vec!( Expected::Literal( b"zoo" ) ) );
+  }
+
+
+  #[test]
+  fn Literal_Bytes() {
+    let orig_state = ToParseState( b"\x89PNG\r\n" );
+    match blit!( b"\x89PNG" ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes[ 0 ],
+                    Node::withoutName( 0, 4, Data( b"\x89PNG" ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 4 ) );
+      }
+      _ => panic!( "No match!" )
+    };
+    assert!( iblit!( b"\x89png" ).apply( &orig_state ).is_some() );
+    assert!( blit!( b"\x88PNG" ).apply( &orig_state ).is_none() );
+  }
+
+
+  #[test]
+  fn Literal_IgnoringCase_Match() {
+    let orig_state = input_state!( "SeLeCt *" );
+    match ilit!( "select" ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes[ 0 ],
+                    Node::withoutName( 0, 6, Data( b"SeLeCt" ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 6 ) );
+      }
+      _ => panic!( "No match!" )
+    };
+
+    assert!( ilit!( "ΣΊΣΥΦΟΣ" ).apply( &input_state!( "σίσυφος" ) ).is_some() );
+    assert!( ilit!( "123" ).apply( &input_state!( "123" ) ).is_some() );
+    assert!( ilit!( "" ).apply( &input_state!( "x" ) ).is_some() );
+  }
+
+
+  #[test]
+  fn Literal_IgnoringCase_MatchesFoldedCharsOfOtherLength() {
+    // The Kelvin sign is three bytes long and folds to `k`.
+    let orig_state = input_state!( "\u{212A}ey" );
+    match ilit!( "key" ).apply( &orig_state ) {
+      Some( ParseResult{ parse_state, .. } ) => {
+        assert_eq!( parse_state, orig_state.advanceTo( 5 ) );
+      }
+      _ => panic!( "No match!" )
+    };
+  }
+
+
+  #[test]
+  fn Literal_IgnoringCase_NoMatch_RecordsExpected() {
+    let orig_state = input_state!( "selEKT" );
+    assert!( ilit!( "select" ).apply( &orig_state ).is_none() );
+    assert!( ilit!( "select" ).apply( &input_state!( "sel" ) ).is_none() );
+    assert_eq!( orig_state.context.lookahead(), 5 );
+
+    let error = orig_state.context.parseError( b"selEKT" );
+    assert_eq!( error.offset, 0 );
+    assert_eq!( error.expected,
+                vec!( Expected::LiteralIgnoringCase( b"select" ) ) );
+  }
 }
@@ -16,15 +16,27 @@ pub use self::and::And;
 pub use self::fuse::Fuse;
 pub use self::char_class::CharClass;
 pub use self::literal::Literal;
-pub use self::dot::Dot;
+pub use self::dot::{Dot, AnyByte};
+pub use self::cut::Cut;
 pub use self::option::OptionEx;
 pub use self::star::Star;
 pub use self::plus::Plus;
+pub use self::repeat::Repeat;
 pub use self::or::Or;
 pub use self::sequence::Sequence;
 pub use self::wrap::WrapEx;
+pub use self::label::Label;
+pub use self::recover::Recover;
 pub use self::node::{Node, NodeContents, Data, Children, PreOrderNodes};
-
+pub use self::context::{ParseContext, FailureCheckpoint, memoized,
+                        leftRecursive, namedNode};
+pub use self::incremental::{Edit, Shift};
+pub use self::error::{ParseError, Expected};
+pub use self::ast::AstCursor;
+pub use self::stream::{StreamInput, StreamError};
+pub use self::unicode::unicodeProperty;
+use std::fmt;
+use std::rc::Rc;
 
 mod node;
 #[cfg(test)]
@@ -47,6 +59,8 @@ mod star;
 #[macro_use]
 mod plus;
 #[macro_use]
+mod repeat;
+#[macro_use]
 mod or;
 #[macro_use]
 mod fuse;
@@ -54,18 +68,35 @@ mod fuse;
 mod sequence;
 #[macro_use]
 mod wrap;
+#[macro_use]
+mod label;
+#[macro_use]
+mod recover;
+mod cut;
 mod unicode;
+mod context;
+mod error;
+mod ast;
+mod stream;
+mod incremental;
 
 
 #[doc(hidden)]
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Clone)]
 pub struct ParseState<'a> {
   pub input: &'a [u8],  // Unconsumed input from "main" slice.
-  pub offset: usize  // Offset of 'input' from start of "main" slice.
+  pub offset: usize,  // Offset of 'input' from start of "main" slice.
+  pub context: Rc< ParseContext<'a> >  // Shared by all states of one parse.
 }
 
 
 impl<'a> ParseState<'a> {
+  pub fn new( input: &'a [u8] ) -> ParseState<'a> {
+    ParseState { input: input,
+                 offset: 0,
+                 context: Rc::new( ParseContext::new() ) }
+  }
+
   fn advanceTo( &self, new_offset: usize ) -> ParseState<'a> {
     let mut clone = self.clone();
     clone.input = &clone.input[ new_offset - clone.offset .. ];
@@ -77,6 +108,27 @@ impl<'a> ParseState<'a> {
     &self.input[ .. new_offset - self.offset ]
   }
 
+  // Records that an expression looked at the `length` bytes following the
+  // current offset.
+  fn lookAt( &self, length: usize ) {
+    self.context.lookedAt( self.offset + length );
+  }
+
+  // Records that an expression looked at the char at the current offset, or at
+  // the byte there if it doesn't start a UTF-8 sequence.
+  fn lookAtChar( &self ) {
+    self.lookAt( self.input.get( 0 )
+                   .and_then( |byte| unicode::bytesFollowing( *byte ) )
+                   .map_or( 1, |num_following| num_following + 1 ) );
+  }
+
+  // Records that `expected` wasn't found at the current offset and returns
+  // the failed result.
+  fn expected( &self, expected: Expected ) -> Option< ParseResult<'a> > {
+    self.context.expected( self.offset, expected );
+    None
+  }
+
   fn offsetToResult( &self, new_offset: usize )
       -> Option< ParseResult<'a> > {
     Some( ParseResult::oneNode(
@@ -87,6 +139,23 @@ impl<'a> ParseState<'a> {
   }
 }
 
+// The context is deliberately ignored; two states are the same if they point
+// to the same place in the input.
+impl<'a> PartialEq for ParseState<'a> {
+  fn eq( &self, other: &ParseState<'a> ) -> bool {
+    self.input == other.input && self.offset == other.offset
+  }
+}
+
+
+impl<'a> fmt::Debug for ParseState<'a> {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    write!( formatter,
+            "ParseState {{ input: {:?}, offset: {:?} }}",
+            self.input, self.offset )
+  }
+}
+
 #[doc(hidden)]
 pub struct ParseResult<'a> {
   pub nodes: Vec< Node<'a> >,
@@ -112,3 +181,11 @@ pub trait Expression {
 }
 
 pub type Rule = for<'a> fn( &ParseState<'a> ) -> Option< ParseResult<'a> >;
+
+
+/// Runs a rule's semantic action on the node the rule produced. Passing the
+/// action through here lets its argument type be inferred.
+pub fn applyAction<'a, T, F>( node: &Node<'a>, action: F ) -> T
+    where F: Fn( &Node<'a> ) -> T {
+  action( node )
+}
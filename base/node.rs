@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use std::fmt;
+use std::rc::Rc;
 use std::str;
+use super::Expected;
 pub use self::NodeContents::{Data, Children};
 
 static NO_NAME : &'static str = "<none>";
@@ -43,23 +45,33 @@ impl<'a, 'b:'a> Iterator for PreOrderNodes<'a, 'b> {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum NodeContents<'a> {
   /// A `&[u8]` byte slice this node matched in the parse input. Only leaf nodes
   /// have `Data` contents.
   Data( &'a [u8] ),
 
   /// Children of the node, if any. Only non-leaf nodes have `Children`
-  /// contents.
-  Children( Vec<Node<'a>> )
+  /// contents. They're shared between the clones of the node, so cloning a
+  /// node doesn't copy the tree below it.
+  Children( Rc< Vec<Node<'a>> > ),
+
+  /// Input skipped by a recovery expression like `Statement^SkipLine` after
+  /// the expression before the `^` failed. Holds what the parser expected
+  /// where the node starts. The node's range is the skipped input.
+  Error( Vec<Expected> )
 }
 
 
-#[derive(PartialEq)]
+#[derive(Clone)]
 pub struct Node<'a> {
   /// The name of the node.
   pub name: &'static str,
 
+  /// The label the node was captured with in the grammar, like `lhs` for
+  /// `lhs:Term`, or an empty string.
+  pub label: &'static str,
+
   /// The (inclusive) start index of the range this node matches. It's the byte
   /// (NOT char) offset of the parse input.
   pub start: usize,
@@ -68,6 +80,12 @@ pub struct Node<'a> {
   /// (NOT char) offset of the parse input.
   pub end: usize,
 
+  /// The (exclusive) end index of the input the parser looked at to produce
+  /// this node. It's never less than `end`, but can be more; `[a-z]+` has to
+  /// look at the char after the letters it matched. Changing the input before
+  /// this index could change the node.
+  pub lookahead: usize,
+
   /// The contents of the node; this can be either children nodes or a matched
   /// `&[u8]` slice.
   pub contents: NodeContents<'a>
@@ -87,6 +105,9 @@ impl<'a> Node<'a> {
   fn format( &self, formatter: &mut fmt::Formatter, indent_spaces: u32 )
       -> fmt::Result {
     try!( indent( formatter, indent_spaces ) );
+    if !self.label.is_empty() {
+      try!( write!( formatter, "{}: ", self.label ) );
+    }
     try!( write!( formatter,
                   "{0:?} [{1:?}, {2:?}>",
                   self.displayName(), self.start, self.end ) );
@@ -112,6 +133,9 @@ impl<'a> Node<'a> {
           try!( child.format( formatter, indent_spaces + 1) )
         }
       }
+      NodeContents::Error( ref expected ) => {
+        try!( writeln!( formatter, ": error, expected {:?}", expected ) );
+      }
     };
 
     Ok(())
@@ -129,7 +153,12 @@ impl<'a> Node<'a> {
   /// Creates a `Node` with an empty name.
   pub fn withoutName( start: usize, end: usize, contents: NodeContents<'a> )
       -> Node<'a> {
-    Node { name: "", start: start, end: end, contents: contents }
+    Node { name: "",
+           label: "",
+           start: start,
+           end: end,
+           lookahead: end,
+           contents: contents }
   }
 
   /// Creates a `Node` with the provided `name` and makes it a parent of the
@@ -142,6 +171,7 @@ impl<'a> Node<'a> {
       match children.pop() {
         Some( mut child ) => {
           child.name = name;
+          child.label = "";
           return child;
         }
         _ => ()
@@ -157,9 +187,11 @@ impl<'a> Node<'a> {
     let end = children.last().map_or( 0, |node| node.end );
 
     Node { name: name,
+           label: "",
            start: start,
            end: end,
-           contents: Children( children ) }
+           lookahead: end,
+           contents: Children( Rc::new( children ) ) }
   }
 
 
@@ -172,7 +204,7 @@ impl<'a> Node<'a> {
 
 
   /// Concatenates and returns all `&[u8]` data in the leaf nodes beneath
-  /// the current node.
+  /// the current node. Input skipped by error recovery isn't included.
   #[allow(dead_code)]
   pub fn matchedData( &self ) -> Vec<u8> {
     match self.contents {
@@ -184,6 +216,50 @@ impl<'a> Node<'a> {
         }
         out
       }
+      NodeContents::Error( _ ) => vec!()
+    }
+  }
+
+  /// Returns the nodes with `Error` contents in the tree rooted at the node,
+  /// in pre-order. A parse that recovered from syntax errors succeeds, so this
+  /// is how to find out where the errors were.
+  #[allow(dead_code)]
+  pub fn errors( &self ) -> Vec<&Node<'a>> {
+    self.preOrder().filter( |node| node.isError() ).collect()
+  }
+
+  /// Returns true if the node has `Error` contents.
+  #[allow(dead_code)]
+  pub fn isError( &self ) -> bool {
+    match self.contents {
+      NodeContents::Error( _ ) => true,
+      _ => false
+    }
+  }
+
+  /// Like `matchedData`, but as a string; invalid UTF-8 sequences are replaced
+  /// with U+FFFD.
+  #[allow(dead_code)]
+  pub fn matchedText( &self ) -> String {
+    String::from_utf8_lossy( &self.matchedData() ).into_owned()
+  }
+
+  /// Returns the first child captured with `label`.
+  #[allow(dead_code)]
+  pub fn child( &self, label: &str ) -> Option<&Node<'a>> {
+    self.children_labeled( label ).into_iter().next()
+  }
+
+
+  /// Returns the children captured with `label`, in order. An expression like
+  /// `items:Item*` labels every node it produces.
+  #[allow(dead_code)]
+  pub fn children_labeled( &self, label: &str ) -> Vec<&Node<'a>> {
+    match self.contents {
+      Children( ref children ) => {
+        children.iter().filter( |child| child.label == label ).collect()
+      }
+      _ => vec!()
     }
   }
 
@@ -191,6 +267,18 @@ impl<'a> Node<'a> {
   // iterators for walking the node tree structure
 }
 
+// How far the parser looked to produce the nodes doesn't make them different.
+impl<'a> PartialEq for Node<'a> {
+  fn eq( &self, other: &Node<'a> ) -> bool {
+    self.name == other.name &&
+      self.label == other.label &&
+      self.start == other.start &&
+      self.end == other.end &&
+      self.contents == other.contents
+  }
+}
+
+
 impl<'a> fmt::Debug for Node<'a> {
   fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
     self.format( formatter, 0 )
@@ -200,14 +288,25 @@ impl<'a> fmt::Debug for Node<'a> {
 
 #[cfg(test)]
 mod tests {
-  use super::{Node, Data};
+  use std::rc::Rc;
+  use super::{Node, NodeContents, Data, Children};
 
   fn nameOnly( name: &'static str ) -> Node {
-    Node { name: name, start: 0, end: 0, contents: Data( b"" ) }
+    Node { name: name,
+           label: "",
+           start: 0,
+           end: 0,
+           lookahead: 0,
+           contents: Data( b"" ) }
   }
 
   fn contentsOnly( contents: &'static [u8] ) -> Node {
-    Node { name: "", start: 0, end: 0, contents: Data( contents ) }
+    Node { name: "",
+           label: "",
+           start: 0,
+           end: 0,
+           lookahead: 0,
+           contents: Data( contents ) }
   }
 
   fn testTree() -> Node<'static> {
@@ -250,4 +349,50 @@ mod tests {
     //   https://github.com/rust-lang/rust/issues/22649
     assert_eq!( b"efgd", &root.matchedData()[..] )
   }
+
+
+  #[test]
+  fn matchedText_FullTree() {
+    assert_eq!( "efgd", testTreeWithContents().matchedText() )
+  }
+
+
+  #[test]
+  fn child_ByLabel() {
+    let mut root = testTree();
+    match root.contents {
+      Children( ref mut children ) => {
+        let children = Rc::get_mut( children ).unwrap();
+        children[ 0 ].label = "x";
+        children[ 2 ].label = "x";
+        children[ 1 ].label = "y";
+      }
+      _ => panic!( "No children." )
+    }
+
+    assert_eq!( root.child( "y" ).unwrap().name, "c" );
+    assert!( root.child( "z" ).is_none() );
+    let names = root.children_labeled( "x" ).iter()
+      .map( |x| x.name ).collect::<Vec<_>>();
+    assert_eq!( names, vec!( "b", "d" ) );
+  }
+
+
+  #[test]
+  fn withChildren_CollapsedNodeDropsLabel() {
+    let mut child = contentsOnly( b"e" );
+    child.label = "x";
+    assert_eq!( Node::withChildren( "a", vec!( child ) ).label, "" );
+  }
+
+
+  #[test]
+  fn errors_InPreOrder() {
+    let error = Node::withoutName( 1, 2, NodeContents::Error( vec!() ) );
+    let tree = Node::withChildren( "a", vec!(
+      Node::withChildren( "b", vec!( contentsOnly( b"x" ), error.clone() ) ),
+      error.clone() ) );
+    assert_eq!( tree.errors(), vec!( &error, &error ) );
+    assert_eq!( tree.matchedData(), b"x".to_vec() );
+  }
 }
@@ -16,12 +16,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! not( ( $ex:expr ) => ( &base::NotEx::new($ex) ); );
 
 pub struct NotEx<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'a> NotEx<'a> {
-  pub fn new( expr: &Expression ) -> NotEx {
+  pub fn new( expr: &'a dyn Expression ) -> NotEx<'a> {
     NotEx { expr: expr }
   }
 }
@@ -30,9 +30,10 @@ impl<'a> NotEx<'a> {
 impl<'b> Expression for NotEx<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    match self.expr.apply( parse_state ) {
+    // The expression failing is what we want, so it shouldn't be reported.
+    match parse_state.context.quietly( || self.expr.apply( parse_state ) ) {
       Some( _ ) => None,
-      _ => Some( ParseResult::fromParseState( *parse_state ) )
+      _ => Some( ParseResult::fromParseState( parse_state.clone() ) )
     }
   }
 }
@@ -74,4 +75,12 @@ mod tests {
     assert!( not!( class!( "a-z" ) ).apply( &input_state!( "b" ) ).is_none() );
     assert!( not!( lit!( "x" ) ).apply( &input_state!( "x" ) ).is_none() );
   }
+
+
+  #[test]
+  fn NotEx_NoMatch_RecordsNothing() {
+    let orig_state = input_state!( "b" );
+    assert!( not!( not!( lit!( "x" ) ) ).apply( &orig_state ).is_none() );
+    assert!( orig_state.context.parseError( b"b" ).expected.is_empty() );
+  }
 }
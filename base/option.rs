@@ -16,12 +16,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! opt( ( $ex:expr ) => ( &base::OptionEx::new( $ex ) ); );
 
 pub struct OptionEx<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'a> OptionEx<'a> {
-  pub fn new( expr: &Expression ) -> OptionEx {
+  pub fn new( expr: &'a dyn Expression ) -> OptionEx<'a> {
     OptionEx { expr: expr }
   }
 }
@@ -31,7 +31,7 @@ impl<'b> Expression for OptionEx<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
     self.expr.apply( parse_state ).or(
-      Some( ParseResult::fromParseState( *parse_state ) ) )
+      Some( ParseResult::fromParseState( parse_state.clone() ) ) )
   }
 }
 
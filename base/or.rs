@@ -17,12 +17,12 @@ macro_rules! or( ( $( $ex:expr ),* ) => (
     &base::Or::new( &[ $( $ex ),* ] ) ); );
 
 pub struct Or<'a> {
-  exprs: &'a [&'a (Expression + 'a)]
+  exprs: &'a [&'a (dyn Expression + 'a)]
 }
 
 
 impl<'b> Or<'b> {
-  pub fn new<'a>( exprs: &'a [&Expression] ) -> Or<'a> {
+  pub fn new<'a>( exprs: &'a [&dyn Expression] ) -> Or<'a> {
     Or { exprs: exprs }
   }
 }
@@ -32,8 +32,10 @@ impl<'b> Expression for Or<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
     for expr in self.exprs.iter() {
-      match expr.apply( parse_state ) {
-        result @ Some( _ ) => return result,
+      // An alternative that failed after passing a cut fails the choice.
+      match parse_state.context.cutScope( || expr.apply( parse_state ) ) {
+        ( result @ Some( _ ), _ ) => return result,
+        ( None, true ) => return None,
         _ => ()
       }
     }
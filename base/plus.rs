@@ -16,12 +16,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! plus( ( $ex:expr ) => ( &base::Plus::new( $ex ) ); );
 
 pub struct Plus<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'b> Plus<'b> {
-  pub fn new( expr: &Expression ) -> Plus {
+  pub fn new( expr: &'b dyn Expression ) -> Plus<'b> {
     Plus { expr: expr }
   }
 }
@@ -30,7 +30,7 @@ impl<'b> Plus<'b> {
 impl<'b> Expression for Plus<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    let mut final_result = ParseResult::fromParseState( *parse_state );
+    let mut final_result = ParseResult::fromParseState( parse_state.clone() );
     let mut num_matches = 0;
     loop {
       match self.expr.apply( &final_result.parse_state ) {
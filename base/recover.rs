@@ -0,0 +1,100 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::{Expression, ParseState, ParseResult, Node, NodeContents};
+
+macro_rules! recover( ( $ex:expr, $sync:expr ) => (
+    &base::Recover::new( $ex, $sync ) ); );
+
+/// Applies an expression, and if it fails, skips the input the sync expression
+/// matches instead of failing. The skipped input becomes a node with `Error`
+/// contents, so the parse can go on and still produce a tree for broken input.
+pub struct Recover<'a> {
+  expr: &'a ( dyn Expression + 'a ),
+  sync: &'a ( dyn Expression + 'a )
+}
+
+
+impl<'a> Recover<'a> {
+  pub fn new( expr: &'a dyn Expression, sync: &'a dyn Expression )
+      -> Recover<'a> {
+    Recover { expr: expr, sync: sync }
+  }
+}
+
+
+impl<'b> Expression for Recover<'b> {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    match self.expr.apply( parse_state ) {
+      Some( result ) => return Some( result ),
+      _ => ()
+    }
+
+    // The failure of the expression is the error; what the sync expression
+    // fails to match while skipping isn't.
+    let expected = parse_state.context.expectedFrom( parse_state.offset );
+    parse_state.context.quietly( || self.sync.apply( parse_state ) )
+      .map( |result| {
+        let end = result.parse_state.offset;
+        ParseResult::oneNode(
+          Node::withoutName( parse_state.offset,
+                             end,
+                             NodeContents::Error( expected ) ),
+          result.parse_state )
+      } )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base;
+  use base::{Node, NodeContents, ParseResult, Expression, Expected};
+
+  #[test]
+  fn Recover_Match() {
+    let orig_state = input_state!( "ab" );
+    match recover!( lit!( "a" ), lit!( "x" ) ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes, vec!( Node::withoutName( 0, 1,
+                                                    base::Data( b"a" ) ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 1 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Recover_SkipsToSync() {
+    let orig_state = input_state!( "a+b;c" );
+    match recover!( seq!( lit!( "a" ), lit!( ";" ) ),
+                    star!( seq!( not!( lit!( ";" ) ), &base::Dot ) ) )
+        .apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes, vec!( Node::withoutName(
+          0, 3, NodeContents::Error( vec!( Expected::Literal( b";" ) ) ) ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Recover_SyncFails() {
+    assert!( recover!( lit!( "a" ), lit!( ";" ) )
+               .apply( &input_state!( "b" ) ).is_none() );
+  }
+}
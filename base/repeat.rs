@@ -0,0 +1,129 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::{Expression, ParseState, ParseResult};
+
+macro_rules! repeat( ( $ex:expr, $min:expr, $max:expr ) => (
+    &base::Repeat::new( $ex, $min, $max ) ); );
+
+/// Matches its expression at least `min` times and, if `max` is given, at most
+/// `max` times; this is what `X{2,5}` in a grammar becomes. Like `Star` and
+/// `Plus`, it's greedy and never gives back a match.
+pub struct Repeat<'a> {
+  expr: &'a ( dyn Expression + 'a ),
+  min: usize,
+  max: Option<usize>
+}
+
+
+impl<'b> Repeat<'b> {
+  pub fn new( expr: &'b dyn Expression, min: usize, max: Option<usize> )
+      -> Repeat<'b> {
+    Repeat { expr: expr, min: min, max: max }
+  }
+}
+
+
+impl<'b> Expression for Repeat<'b> {
+  fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
+      Option< ParseResult<'a> > {
+    let mut final_result = ParseResult::fromParseState( parse_state.clone() );
+    let mut num_matches = 0;
+    while self.max.map_or( true, |max| num_matches < max ) {
+      match self.expr.apply( &final_result.parse_state ) {
+        Some( result ) => {
+          final_result.parse_state = result.parse_state;
+          final_result.nodes.extend( result.nodes.into_iter() );
+          num_matches += 1;
+        }
+        _ => break
+      }
+    }
+
+    if num_matches >= self.min {
+      Some( final_result )
+    } else {
+      None
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use base;
+  use base::{Node, ParseResult, Expression, Data};
+
+  #[test]
+  fn Repeat_Match_Exactly() {
+    let orig_state = input_state!( "aaaa" );
+    match repeat!( lit!( "a" ), 3, Some( 3 ) ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes.len(), 3 );
+        assert_eq!( nodes[ 2 ],
+                    Node::withoutName( 2, 3, Data( b"a" ) ) );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Repeat_Match_Between() {
+    let orig_state = input_state!( "aaab" );
+    match repeat!( lit!( "a" ), 2, Some( 5 ) ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes.len(), 3 );
+        assert_eq!( parse_state, orig_state.advanceTo( 3 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Repeat_Match_Unbounded() {
+    let orig_state = input_state!( "aaaaaa" );
+    match repeat!( lit!( "a" ), 2, None ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert_eq!( nodes.len(), 6 );
+        assert_eq!( parse_state, orig_state.advanceTo( 6 ) );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Repeat_Match_Zero() {
+    let orig_state = input_state!( "aa" );
+    match repeat!( lit!( "a" ), 0, Some( 0 ) ).apply( &orig_state ) {
+      Some( ParseResult{ nodes, parse_state } ) => {
+        assert!( nodes.is_empty() );
+        assert_eq!( parse_state, orig_state );
+      }
+      _ => panic!( "No match." )
+    }
+  }
+
+
+  #[test]
+  fn Repeat_NoMatch_TooFew() {
+    let orig_state = input_state!( "aab" );
+    match repeat!( lit!( "a" ), 3, Some( 4 ) ).apply( &orig_state ) {
+      None => (),
+      _ => panic!( "Should not match." ),
+    }
+  }
+}
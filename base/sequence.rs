@@ -17,12 +17,12 @@ macro_rules! seq( ( $( $ex:expr ),* ) => (
     &base::Sequence::new( &[ $( $ex ),* ] ) ); );
 
 pub struct Sequence<'a> {
-  exprs: &'a [&'a (Expression + 'a)]
+  exprs: &'a [&'a (dyn Expression + 'a)]
 }
 
 
 impl<'b> Sequence<'b> {
-  pub fn new<'a>( exprs: &'a [&Expression] ) -> Sequence<'a> {
+  pub fn new<'a>( exprs: &'a [&dyn Expression] ) -> Sequence<'a> {
     Sequence { exprs: exprs }
   }
 }
@@ -31,7 +31,7 @@ impl<'b> Sequence<'b> {
 impl<'b> Expression for Sequence<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    let mut final_result = ParseResult::fromParseState( *parse_state );
+    let mut final_result = ParseResult::fromParseState( parse_state.clone() );
     for expr in self.exprs.iter() {
       match expr.apply( &final_result.parse_state ) {
         Some( result ) => {
@@ -16,12 +16,12 @@ use super::{Expression, ParseState, ParseResult};
 macro_rules! star( ( $ex:expr ) => ( &base::Star::new( $ex ) ); );
 
 pub struct Star<'a> {
-  expr: &'a ( Expression + 'a )
+  expr: &'a ( dyn Expression + 'a )
 }
 
 
 impl<'b> Star<'b> {
-  pub fn new( expr: &Expression ) -> Star {
+  pub fn new( expr: &'b dyn Expression ) -> Star<'b> {
     Star { expr: expr }
   }
 }
@@ -30,7 +30,7 @@ impl<'b> Star<'b> {
 impl<'b> Expression for Star<'b> {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    let mut final_result = ParseResult::fromParseState( *parse_state );
+    let mut final_result = ParseResult::fromParseState( parse_state.clone() );
     loop {
       match self.expr.apply( &final_result.parse_state ) {
         Some( result ) => {
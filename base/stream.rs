@@ -0,0 +1,381 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+use super::{Node, ParseState, ParseContext, ParseError, Rule};
+
+// The number of bytes asked from the reader at a time.
+const CHUNK_SIZE: usize = 8192;
+
+
+/// Why parsing a stream failed.
+#[derive(Debug)]
+pub enum StreamError {
+  /// Reading from the stream failed.
+  Io( io::Error ),
+
+  /// The input doesn't match. The error's position is counted from the start
+  /// of the stream.
+  Parse( ParseError ),
+
+  /// The match starting at the given stream offset needed to look further
+  /// ahead than the window allows.
+  WindowExceeded( usize )
+}
+
+
+impl From<io::Error> for StreamError {
+  fn from( error: io::Error ) -> StreamError {
+    StreamError::Io( error )
+  }
+}
+
+
+impl fmt::Display for StreamError {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    match *self {
+      StreamError::Io( ref error ) => write!( formatter, "{}", error ),
+      StreamError::Parse( ref error ) => write!( formatter, "{}", error ),
+      StreamError::WindowExceeded( offset ) => {
+        write!( formatter,
+                "the match at offset {} doesn't fit in the lookahead window",
+                offset )
+      }
+    }
+  }
+}
+
+
+/// Input read from a `Read` as it's needed, for inputs too large to hold in
+/// memory at once, like logs or network streams. The stream is parsed as a
+/// series of matches of one rule, say one for every log record.
+///
+/// The expressions still parse a `&[u8]`, so the input can't grow while a
+/// rule is being applied. Instead, a match that looked past the input read so
+/// far is parsed again once more input is in. The buffer at least doubles
+/// between attempts, unless the reader has no more input ready, so a match is
+/// parsed a number of times that's logarithmic in its size.
+///
+/// Only the input of the current match is kept in memory. A match may look at
+/// no more than `window` bytes past its start; if it needs more, it fails with
+/// `StreamError::WindowExceeded`. Input is released once a whole match is done,
+/// not as soon as nothing can backtrack into it, so a grammar that isn't a
+/// series of matches has to fit in the window as a whole.
+pub struct StreamInput<R> {
+  reader: R,
+
+  // Bytes read from `reader` but not consumed by a match yet.
+  buffer: Vec<u8>,
+
+  // The stream offset of the first byte in `buffer`.
+  offset: usize,
+
+  // The 1-based line and column of the first byte in `buffer`.
+  line: usize,
+  column: usize,
+
+  window: usize,
+
+  // True once `reader` has no more input.
+  at_end: bool
+}
+
+
+impl<R: Read> StreamInput<R> {
+  /// `window` must be at least one byte.
+  pub fn new( reader: R, window: usize ) -> StreamInput<R> {
+    assert!( window > 0 );
+    StreamInput { reader: reader,
+                  buffer: vec!(),
+                  offset: 0,
+                  line: 1,
+                  column: 1,
+                  window: window,
+                  at_end: false }
+  }
+
+  /// Returns the stream offset of the first byte not consumed by a match.
+  pub fn offset( &self ) -> usize {
+    self.offset
+  }
+
+  /// Applies `rule` to the input following the previous match and calls
+  /// `callback` with the nodes the rule produced; a rule made of combinators
+  /// may produce none or several. Returns what `callback` returned, or nothing
+  /// if the stream has ended. The offsets of the nodes are counted from the
+  /// start of the stream, but the nodes can't outlive `callback` since their
+  /// input is released afterwards.
+  pub fn parseNext<T, F>( &mut self, rule: Rule, callback: F )
+      -> Result< Option<T>, StreamError >
+      where F: FnOnce( &[Node] ) -> T {
+    let mut callback = Some( callback );
+    loop {
+      if self.buffer.is_empty() {
+        if self.at_end {
+          return Ok( None );
+        }
+        try!( self.fill() );
+        continue;
+      }
+
+      let matched = {
+        let parse_state = ParseState { input: &self.buffer,
+                                       offset: self.offset,
+                                       context: Rc::new( ParseContext::new() ) };
+        let result = rule( &parse_state );
+
+        // If the parse looked past the input read so far, reading more of it
+        // could change the result.
+        if !self.at_end && parse_state.context.lookahead() >
+                           self.offset + self.buffer.len() {
+          None
+        } else {
+          Some( match result {
+            Some( ref result ) if result.parse_state.offset > self.offset => {
+              let value = callback.take().unwrap()( &result.nodes );
+              Ok( ( result.parse_state.offset, value ) )
+            }
+            _ => Err( self.streamError(
+                        parse_state.context.parseErrorFrom( &self.buffer,
+                                                            self.offset ) ) )
+          } )
+        }
+      };
+
+      match matched {
+        Some( Ok( ( end, value ) ) ) => {
+          self.release( end );
+          return Ok( Some( value ) );
+        }
+        Some( Err( error ) ) => return Err( StreamError::Parse( error ) ),
+        None if self.buffer.len() >= self.window => {
+          return Err( StreamError::WindowExceeded( self.offset ) );
+        }
+        None => try!( self.fill() )
+      }
+    }
+  }
+
+  // Reads more of the stream into the buffer, without growing the buffer past
+  // the window. Reading stops once the buffer has doubled, or early if the
+  // reader returns less than was asked for, since that's all it has ready.
+  fn fill( &mut self ) -> io::Result<()> {
+    let target = cmp::min( self.window,
+                           cmp::max( CHUNK_SIZE, 2 * self.buffer.len() ) );
+    while self.buffer.len() < target {
+      let size = cmp::min( target - self.buffer.len(), CHUNK_SIZE );
+      let num_read = try!( self.read( size ) );
+      if num_read == 0 {
+        self.at_end = true;
+      }
+      if num_read < size {
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  // Appends up to `size` bytes from the reader to the buffer and returns how
+  // many there were.
+  fn read( &mut self, size: usize ) -> io::Result<usize> {
+    let start = self.buffer.len();
+    self.buffer.resize( start + size, 0 );
+    loop {
+      match self.reader.read( &mut self.buffer[ start .. ] ) {
+        Ok( num_read ) => {
+          self.buffer.truncate( start + num_read );
+          return Ok( num_read );
+        }
+        Err( ref error ) if error.kind() == io::ErrorKind::Interrupted => (),
+        Err( error ) => {
+          self.buffer.truncate( start );
+          return Err( error );
+        }
+      }
+    }
+  }
+
+  // Drops the input before the stream offset `end` from the buffer.
+  fn release( &mut self, end: usize ) {
+    let consumed = end - self.offset;
+    {
+      let released = &self.buffer[ .. consumed ];
+      match released.iter().rposition( |byte| *byte == b'\n' ) {
+        Some( index ) => {
+          self.line += released.iter().filter( |byte| **byte == b'\n' ).count();
+          self.column = 1;
+          self.column += String::from_utf8_lossy( &released[ index + 1 .. ] )
+            .chars().count();
+        }
+        _ => {
+          self.column += String::from_utf8_lossy( released ).chars().count();
+        }
+      }
+    }
+    self.buffer.drain( .. consumed );
+    self.offset = end;
+  }
+
+  // Moves `error`, positioned relative to the buffer, to its place in the
+  // stream.
+  fn streamError( &self, mut error: ParseError ) -> ParseError {
+    if error.line == 1 {
+      error.column += self.column - 1;
+    }
+    error.line += self.line - 1;
+    error.offset += self.offset;
+    error
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::cell::Cell;
+  use std::cmp;
+  use std::io;
+  use std::io::Read;
+  use base;
+  use base::{Expression, Node, ParseState, ParseResult};
+  use super::{StreamInput, StreamError};
+
+  // A reader that returns at most `chunk` bytes at a time.
+  struct Trickle<'a> {
+    data: &'a [u8],
+    chunk: usize
+  }
+
+  impl<'a> Read for Trickle<'a> {
+    fn read( &mut self, buf: &mut [u8] ) -> io::Result<usize> {
+      let size = cmp::min( cmp::min( self.chunk, buf.len() ), self.data.len() );
+      buf[ .. size ].copy_from_slice( &self.data[ .. size ] );
+      self.data = &self.data[ size .. ];
+      Ok( size )
+    }
+  }
+
+  fn Line<'a>( parse_state: &ParseState<'a> ) -> Option< ParseResult<'a> > {
+    seq!( star!( seq!( not!( lit!( "\n" ) ), &base::Dot ) ), lit!( "\n" ) )
+      .apply( parse_state )
+      .map( |result| {
+        ParseResult::oneNode( Node::withChildren( "Line", result.nodes ),
+                              result.parse_state )
+      } )
+  }
+
+  thread_local!( static NUM_LINE_ATTEMPTS: Cell<usize> = Cell::new( 0 ) );
+
+  fn CountedLine<'a>( parse_state: &ParseState<'a> )
+      -> Option< ParseResult<'a> > {
+    NUM_LINE_ATTEMPTS.with( |num| num.set( num.get() + 1 ) );
+    Line( parse_state )
+  }
+
+  // Matches "ab" without producing a node, like a bare `seq!` would for
+  // silent input.
+  fn Skipped<'a>( parse_state: &ParseState<'a> )
+      -> Option< ParseResult<'a> > {
+    lit!( "ab" ).apply( parse_state ).map( |result| {
+      ParseResult::fromParseState( result.parse_state )
+    } )
+  }
+
+  fn lines( data: &[u8], chunk: usize, window: usize )
+      -> Result< Vec<( String, usize )>, StreamError > {
+    let mut input = StreamInput::new( Trickle { data: data, chunk: chunk },
+                                      window );
+    let mut lines = vec!();
+    while let Some( line ) = try!( input.parseNext( Line, |nodes| {
+      ( nodes[ 0 ].matchedText(), nodes[ 0 ].start )
+    } ) ) {
+      assert_eq!( input.offset(), line.1 + line.0.len() );
+      lines.push( line );
+    }
+    Ok( lines )
+  }
+
+
+  #[test]
+  fn StreamInput_ParsesEachMatch() {
+    let expected = vec!( ( "ab\n".to_string(), 0 ),
+                         ( "\n".to_string(), 3 ),
+                         ( "葉c\n".to_string(), 4 ) );
+    for chunk in 1 .. 12 {
+      assert_eq!( lines( "ab\n\n葉c\n".as_bytes(), chunk, 16 ).unwrap(),
+                  expected );
+    }
+  }
+
+
+  #[test]
+  fn StreamInput_ReleasesConsumedInput() {
+    let mut input = StreamInput::new( &b"ab\ncd\nef\n"[..], 4 );
+    for _ in 0 .. 3 {
+      assert!( input.parseNext( Line, |_| () ).unwrap().is_some() );
+      assert!( input.buffer.len() <= 4 );
+    }
+    assert!( input.parseNext( Line, |_| () ).unwrap().is_none() );
+  }
+
+
+  #[test]
+  fn StreamInput_RetriesLongMatchesFewTimes() {
+    let mut data = vec!( b'a'; 1 << 18 );
+    data.push( b'\n' );
+    let mut input = StreamInput::new( &data[..], 1 << 19 );
+    NUM_LINE_ATTEMPTS.with( |num| num.set( 0 ) );
+    assert_eq!( input.parseNext( CountedLine, |nodes| nodes[ 0 ].end ).unwrap(),
+                Some( data.len() ) );
+
+    // The buffer grows from 8 KiB to 256 KiB and then by the last byte.
+    assert_eq!( NUM_LINE_ATTEMPTS.with( |num| num.get() ), 7 );
+  }
+
+
+  #[test]
+  fn StreamInput_RuleWithoutNodes() {
+    let mut input = StreamInput::new( &b"abab"[..], 4 );
+    for _ in 0 .. 2 {
+      assert_eq!( input.parseNext( Skipped, |nodes| nodes.len() ).unwrap(),
+                  Some( 0 ) );
+    }
+    assert_eq!( input.offset(), 4 );
+    assert!( input.parseNext( Skipped, |_| () ).unwrap().is_none() );
+  }
+
+
+  #[test]
+  fn StreamInput_WindowExceeded() {
+    match lines( b"ab\nabcdef\n", 2, 4 ) {
+      Err( StreamError::WindowExceeded( offset ) ) => assert_eq!( offset, 3 ),
+      _ => panic!( "Should exceed the window." )
+    }
+  }
+
+
+  #[test]
+  fn StreamInput_ParseErrorPositionInStream() {
+    match lines( b"ab\ncd\nef", 1, 8 ) {
+      Err( StreamError::Parse( error ) ) => {
+        assert_eq!( error.offset, 8 );
+        assert_eq!( error.line, 3 );
+        assert_eq!( error.column, 3 );
+      }
+      _ => panic!( "Should fail to parse." )
+    }
+  }
+}
@@ -11,13 +11,13 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use base::ParseState;
+use super::ParseState;
 
 pub fn ToParseState<'a>( bytes: &'a [u8] ) -> ParseState<'a> {
-  ParseState { input: bytes, offset: 0 }
+  ParseState::new( bytes )
 }
 
 macro_rules! input_state( ( $ex:expr ) => ( {
       use base::ParseState;
-      ParseState { input: $ex.as_bytes(), offset: 0 }
+      ParseState::new( $ex.as_bytes() )
     } ) );
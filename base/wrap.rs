@@ -13,17 +13,31 @@
 // limitations under the License.
 use super::{Expression, ParseState, ParseResult, Rule};
 
-macro_rules! ex( ( $ex:expr ) => ( &base::WrapEx{ rule: $ex } ); );
+macro_rules! ex( ( $ex:expr ) => (
+    &base::WrapEx{ rule: $ex, name: stringify!( $ex ) } ); );
 
 pub struct WrapEx {
-  pub rule: Rule
+  pub rule: Rule,
+  pub name: &'static str
 }
 
 
 impl Expression for WrapEx {
   fn apply<'a>( &self, parse_state: &ParseState<'a> ) ->
       Option< ParseResult<'a> > {
-    (self.rule)( parse_state )
+    let checkpoint = parse_state.context.failureCheckpoint();
+    // The cuts in a rule only commit the choices in that rule.
+    let ( result, _ ) =
+      parse_state.context.cutScope( || (self.rule)( parse_state ) );
+    match result {
+      None => {
+        parse_state.context.ruleFailed( checkpoint,
+                                        parse_state.offset,
+                                        self.name );
+        None
+      }
+      result => result
+    }
   }
 }
 
@@ -31,7 +45,7 @@ impl Expression for WrapEx {
 #[cfg(test)]
 mod tests {
   use base;
-  use base::{ParseResult, Expression, ParseState};
+  use base::{ParseResult, Expression, ParseState, Expected};
 
   fn advancesToOne<'a>( parse_state: &ParseState<'a> )
       -> Option< ParseResult<'a> > {
@@ -42,6 +56,20 @@ mod tests {
   fn WrapEx_ReturnsSome() {
     assert!( ex!( advancesToOne ).apply( &input_state!( "foo" ) ).is_some() );
   }
-}
 
 
+  fn needsX<'a>( parse_state: &ParseState<'a> )
+      -> Option< ParseResult<'a> > {
+    lit!( "x" ).apply( parse_state )
+  }
+
+  #[test]
+  fn WrapEx_NoMatch_RecordsRuleName() {
+    let orig_state = input_state!( "foo" );
+    assert!( ex!( needsX ).apply( &orig_state ).is_none() );
+
+    let error = orig_state.context.parseError( b"foo" );
+    assert_eq!( error.offset, 0 );
+    assert_eq!( error.expected, vec!( Expected::Rule( "needsX" ) ) );
+  }
+}
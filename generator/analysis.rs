@@ -0,0 +1,893 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, HashSet};
+use std::str;
+use std::usize;
+use inlined_parser::{Node, Children, Data};
+
+/// A rule definition in a parsed grammar.
+#[derive(Clone)]
+pub struct Definition<'n, 'a: 'n> {
+  /// The name of the defined rule.
+  pub name: String,
+
+  /// The `Definition` node.
+  pub node: &'n Node<'a>,
+
+  /// The `Expression` node of the rule body.
+  pub expression: &'n Node<'a>,
+
+  /// The Rust type of the value the rule's action returns, if the rule has a
+  /// return type.
+  pub value_type: Option<String>,
+
+  /// The code of the rule's action, without the surrounding braces.
+  pub action: Option<String>,
+
+  /// The names of the annotations on the rule, like `silent` for `@silent`.
+  pub annotations: Vec<String>,
+
+  /// The names of the rule's parameters, so `X` and `Sep` for
+  /// `List(X, Sep) <- X (Sep X)*`. Rules with parameters are only templates;
+  /// the generated parser has a rule for each set of arguments they're called
+  /// with.
+  pub parameters: Vec<String>
+}
+
+
+impl<'n, 'a> Definition<'n, 'a> {
+  pub fn isAnnotated( &self, annotation: &str ) -> bool {
+    self.annotations.iter().any( |x| x == annotation )
+  }
+
+  /// Returns true if the rule produces no nodes, either because it's marked
+  /// `@silent` or because it's the `@skip` rule.
+  pub fn isSilent( &self ) -> bool {
+    self.isAnnotated( "silent" ) || self.isAnnotated( "skip" )
+  }
+
+  pub fn isParameterized( &self ) -> bool {
+    !self.parameters.is_empty()
+  }
+
+  /// Returns true if the rule produces a single node holding the text it
+  /// matched, either because it's defined with `<~` or marked `@token`.
+  pub fn isToken( &self ) -> bool {
+    self.isAnnotated( "token" ) ||
+      child( self.node, "ARROW" )
+        .map_or( false, |arrow| hasChild( arrow, "FUSEARROW" ) )
+  }
+}
+
+
+/// The rules that can call themselves without consuming input.
+pub struct LeftRecursion {
+  /// All the rules on a left-recursive cycle.
+  pub involved: HashSet<String>,
+
+  /// The rules chosen to grow a seed so that every left-recursive cycle goes
+  /// through at least one of them. These are the rules that get generated as
+  /// `leftrec` rules.
+  pub leaders: HashSet<String>
+}
+
+
+/// Returns the children of `node`, or nothing if it's a leaf.
+pub fn children<'n, 'a>( node: &'n Node<'a> ) -> &'n [Node<'a>] {
+  match node.contents {
+    Children( ref nodes ) => nodes,
+    _ => &[]
+  }
+}
+
+
+pub fn hasChild( node: &Node, name: &str ) -> bool {
+  children( node ).iter().any( |child| child.name == name )
+}
+
+
+/// Returns the first child of `node` with the given name.
+pub fn child<'n, 'a>( node: &'n Node<'a>, name: &str ) -> Option<&'n Node<'a>> {
+  children( node ).iter().find( |child| child.name == name )
+}
+
+
+/// Returns the input matched by `node`, without surrounding whitespace.
+pub fn matchedString( node: &Node ) -> String {
+  String::from_utf8_lossy( &node.matchedData() ).trim().to_string()
+}
+
+
+/// Returns the name in an `Identifier` node, without the trailing spacing.
+pub fn identifierName( node: &Node ) -> String {
+  match children( node ).first() {
+    Some( &Node { contents: Data( data ), .. } ) => {
+      str::from_utf8( data ).unwrap().to_string()
+    }
+    _ => panic!( "Identifier node without data." )
+  }
+}
+
+
+/// Returns the name of the rule called by a `Call` node.
+pub fn callName( node: &Node ) -> String {
+  identifierName( &children( node )[ 0 ] )
+}
+
+
+/// Returns the `Expression` nodes of the arguments of a `Call` node.
+pub fn callArguments<'n, 'a>( node: &'n Node<'a> ) -> Vec<&'n Node<'a>> {
+  children( node ).iter()
+    .filter( |child| child.name == "Expression" )
+    .collect()
+}
+
+
+/// Returns the name in a `\p{Name}` `Property` node of a class.
+pub fn propertyName( node: &Node ) -> String {
+  child( node, "PropertyName" ).map_or( String::new(), matchedString )
+}
+
+
+/// Returns the minimum and maximum counts of a `{min,max}` `Repetition` node;
+/// there's no maximum for `{min,}`. Counts too large for a `usize` (which
+/// validation reports) are taken to be `usize::MAX`.
+pub fn repetitionBounds( node: &Node ) -> ( usize, Option<usize> ) {
+  let counts: Vec<usize> = children( node ).iter()
+    .filter( |child| child.name == "Count" )
+    .map( |count| matchedString( count ).parse().unwrap_or( usize::MAX ) )
+    .collect();
+  let min = counts[ 0 ];
+  if !hasChild( node, "COMMA" ) {
+    ( min, Some( min ) )
+  } else {
+    ( min, counts.get( 1 ).cloned() )
+  }
+}
+
+
+/// Returns the definitions in the `Grammar` node, in grammar order.
+pub fn definitions<'n, 'a>( grammar: &'n Node<'a> ) -> Vec<Definition<'n, 'a>> {
+  children( grammar ).iter()
+    .filter( |child| child.name == "Definition" )
+    .map( |definition| {
+      let name = child( definition, "Identifier" ).unwrap();
+      Definition {
+        name: identifierName( name ),
+        node: definition,
+        expression: child( definition, "Expression" ).unwrap(),
+        value_type: child( definition, "ReturnType" )
+          .and_then( |node| child( node, "RustType" ) )
+          .map( matchedString ),
+        action: child( definition, "Action" ).map( |node| {
+          child( node, "ActionCode" ).map_or( String::new(), matchedString )
+        } ),
+        annotations: children( definition ).iter()
+          .filter( |child| child.name == "Annotation" )
+          .map( |annotation| {
+            identifierName( child( annotation, "Identifier" ).unwrap() )
+          } )
+          .collect(),
+        parameters: child( definition, "Parameters" ).map_or( vec!(), |node| {
+          children( node ).iter()
+            .filter( |child| child.name == "Identifier" )
+            .map( identifierName )
+            .collect()
+        } )
+      }
+    } )
+    .collect()
+}
+
+
+/// Returns true if the `Prefix` node is a cut, `%`.
+pub fn isCut( node: &Node ) -> bool {
+  child( node, "Suffix" )
+    .and_then( |suffix| child( suffix, "Primary" ) )
+    .map_or( false, |primary| hasChild( primary, "CUT" ) )
+}
+
+
+/// Returns true if `node` (some part of a rule body) can match without
+/// consuming input, given the set of rules known to be able to do so.
+/// Parameters count as consuming input, so this misses calls that can only
+/// match nothing because of their arguments; see `isNullableIn`.
+pub fn isNullable( node: &Node, nullable_rules: &HashSet<String> ) -> bool {
+  isNullableIn( node, None, None, nullable_rules )
+}
+
+
+/// Like `isNullable`, but for a part of a rule body in `scope` of `expansion`.
+/// A parameter can match without consuming input if its argument can, and a
+/// call if the instance it calls can, so `nullable_rules` should have the
+/// names of instances as well; see `nullableRulesIn`.
+pub fn isNullableIn( node: &Node,
+                     scope: Scope,
+                     expansion: Option<&Expansion>,
+                     nullable_rules: &HashSet<String> ) -> bool {
+  let nullable = |node: &Node| {
+    isNullableIn( node, scope, expansion, nullable_rules )
+  };
+  let nodes = children( node );
+  match node.name {
+    "Expression" => nodes.iter()
+      .filter( |child| child.name == "Sequence" )
+      .any( |child| nullable( child ) ),
+    "Sequence" => nodes.iter().all( |child| nullable( child ) ),
+    "Prefix" => {
+      hasChild( node, "AND" ) || hasChild( node, "NOT" ) ||
+        nullable( child( node, "Suffix" ).unwrap() ) ||
+        child( node, "Recovery" ).map_or( false, |recovery| {
+          nullable( &children( recovery )[ 1 ] )
+        } )
+    }
+    "Suffix" => {
+      hasChild( node, "QUESTION" ) || hasChild( node, "STAR" ) ||
+        child( node, "Repetition" )
+          .map_or( false, |node| repetitionBounds( node ).0 == 0 ) ||
+        nullable( &nodes[ 0 ] )
+    }
+    "Primary" => match nodes[ 0 ].name {
+      "Identifier" => {
+        let name = identifierName( &nodes[ 0 ] );
+        match expansion.and_then( |expansion| {
+          expansion.argument( &name, scope )
+        } ) {
+          Some( argument ) => isNullableIn( argument.expression,
+                                            argument.scope,
+                                            expansion,
+                                            nullable_rules ),
+          _ => nullable_rules.contains( &name )
+        }
+      }
+      "Call" => {
+        let name = expansion
+          .and_then( |expansion| expansion.findInstance( &nodes[ 0 ], scope ) )
+          .map_or_else( || callName( &nodes[ 0 ] ),
+                        |instance| instance.name.clone() );
+        nullable_rules.contains( &name )
+      }
+      "OPEN" => nullable( &nodes[ 1 ] ),
+      "CUT" => true,
+      "Literal" => match children( &nodes[ 0 ] ).first() {
+        // Just the two quotes.
+        Some( &Node { contents: Data( data ), .. } ) => data.len() == 2,
+        _ => false
+      },
+      _ => false
+    },
+    _ => false
+  }
+}
+
+
+/// Returns the `Identifier` nodes referring to other rules in `expression`,
+/// including the names of called rules. In the body of a parameterized rule,
+/// these include the uses of its parameters.
+pub fn references<'n, 'a>( expression: &'n Node<'a> ) -> Vec<&'n Node<'a>> {
+  expression.preOrder()
+    .filter( |node| node.name == "Primary" )
+    .map( |node| &children( node )[ 0 ] )
+    .filter_map( |node| match node.name {
+      "Identifier" => Some( node ),
+      "Call" => Some( &children( node )[ 0 ] ),
+      _ => None
+    } )
+    .collect()
+}
+
+
+/// Returns the name of the `@skip` rule, if the grammar has one.
+pub fn skipRule( definitions: &[Definition] ) -> Option<String> {
+  definitions.iter()
+    .find( |definition| definition.isAnnotated( "skip" ) )
+    .map( |definition| definition.name.clone() )
+}
+
+
+/// Returns the names of the lexical rules, which match their input as written
+/// and don't get the skip rule between their elements. These are the token
+/// rules, the skip rule and every rule they refer to, directly or not.
+pub fn lexicalRules( definitions: &[Definition] ) -> HashSet<String> {
+  let by_name: HashMap<&str, &Definition> = definitions.iter()
+    .map( |definition| ( &definition.name[..], definition ) )
+    .collect();
+  let mut stack: Vec<&Definition> = definitions.iter()
+    .filter( |definition| definition.isToken() ||
+                          definition.isAnnotated( "skip" ) )
+    .collect();
+  let mut lexical_rules: HashSet<String> = stack.iter()
+    .map( |definition| definition.name.clone() )
+    .collect();
+
+  while let Some( definition ) = stack.pop() {
+    for reference in references( definition.expression ) {
+      let name = identifierName( reference );
+      match by_name.get( &name[..] ) {
+        Some( next ) if lexical_rules.insert( name.clone() ) => {
+          stack.push( next )
+        }
+        _ => ()
+      }
+    }
+  }
+  lexical_rules
+}
+
+
+/// Returns the names of the rules that can match without consuming input.
+pub fn nullableRules( definitions: &[Definition] ) -> HashSet<String> {
+  let rules: Vec<( Definition, Scope )> = definitions.iter()
+    .map( |definition| ( definition.clone(), None ) )
+    .collect();
+  nullableRulesIn( &rules, None )
+}
+
+
+/// Like `nullableRules`, but for rules with scopes in `expansion`, as
+/// `Expansion::rules` returns them. Instances are named by their own names.
+pub fn nullableRulesIn( rules: &[( Definition, Scope )],
+                        expansion: Option<&Expansion> ) -> HashSet<String> {
+  let mut nullable_rules = HashSet::new();
+  loop {
+    let num_nullable = nullable_rules.len();
+    for &( ref definition, scope ) in rules {
+      if isNullableIn( definition.expression, scope, expansion,
+                       &nullable_rules ) {
+        nullable_rules.insert( definition.name.clone() );
+      }
+    }
+
+    if nullable_rules.len() == num_nullable {
+      return nullable_rules;
+    }
+  }
+}
+
+
+// Adds to `calls` the rules `node` can call at the offset it is applied at.
+// `left_parameters` has, for each parameterized rule, whether it can call each
+// of its parameters at that offset; the arguments for those parameters are
+// called there too.
+fn leftCalls( node: &Node,
+              nullable_rules: &HashSet<String>,
+              left_parameters: &HashMap<String, Vec<bool>>,
+              calls: &mut Vec<String> ) {
+  let nodes = children( node );
+  match node.name {
+    "Expression" => {
+      for child in nodes.iter().filter( |child| child.name == "Sequence" ) {
+        leftCalls( child, nullable_rules, left_parameters, calls );
+      }
+    }
+    "Sequence" => {
+      for child in nodes {
+        leftCalls( child, nullable_rules, left_parameters, calls );
+        if !isNullable( child, nullable_rules ) {
+          break;
+        }
+      }
+    }
+    "Prefix" => {
+      // The sync expression of a recovery starts where the expression it
+      // recovers from failed to.
+      leftCalls( child( node, "Suffix" ).unwrap(),
+                 nullable_rules, left_parameters, calls );
+      match child( node, "Recovery" ) {
+        Some( recovery ) => {
+          leftCalls( &children( recovery )[ 1 ],
+                     nullable_rules, left_parameters, calls )
+        }
+        _ => ()
+      }
+    }
+    "Suffix" => {
+      leftCalls( &nodes[ 0 ], nullable_rules, left_parameters, calls )
+    }
+    "Primary" => match nodes[ 0 ].name {
+      "Identifier" => calls.push( identifierName( &nodes[ 0 ] ) ),
+      "Call" => {
+        let name = callName( &nodes[ 0 ] );
+        if let Some( left ) = left_parameters.get( &name ) {
+          for ( argument, _ ) in callArguments( &nodes[ 0 ] ).into_iter()
+                                   .zip( left.iter() )
+                                   .filter( |&( _, left )| *left ) {
+            leftCalls( argument, nullable_rules, left_parameters, calls );
+          }
+        }
+        calls.push( name );
+      }
+      "OPEN" => {
+        leftCalls( &nodes[ 1 ], nullable_rules, left_parameters, calls )
+      }
+      _ => ()
+    },
+    _ => ()
+  }
+}
+
+
+// Returns true if there's a path from `from` to `to` that doesn't go through
+// any of the rules in `avoid`.
+fn reaches( graph: &HashMap<String, Vec<String>>,
+            from: &str,
+            to: &str,
+            avoid: &HashSet<String> ) -> bool {
+  let mut visited: HashSet<&str> = HashSet::new();
+  let mut stack: Vec<&str> = vec!( from );
+  while let Some( rule ) = stack.pop() {
+    for next in graph.get( rule ).map_or( &[][..], |calls| &calls[..] ) {
+      if next == to {
+        return true;
+      }
+      if !avoid.contains( next ) && visited.insert( next ) {
+        stack.push( next );
+      }
+    }
+  }
+  false
+}
+
+
+/// Finds the left-recursive rules in the grammar. The leaders are chosen in
+/// grammar order, so for `A <- B 'x' / 'y'` and `B <- A 'z'`, `A` is the leader.
+pub fn leftRecursion( definitions: &[Definition] ) -> LeftRecursion {
+  let nullable_rules = nullableRules( definitions );
+  let mut left_parameters: HashMap<String, Vec<bool>> = HashMap::new();
+  for definition in definitions.iter()
+                      .filter( |definition| definition.isParameterized() ) {
+    let mut calls = vec!();
+    leftCalls( definition.expression, &nullable_rules, &HashMap::new(),
+               &mut calls );
+    left_parameters.insert( definition.name.clone(),
+                            definition.parameters.iter()
+                              .map( |parameter| calls.contains( parameter ) )
+                              .collect() );
+  }
+
+  let mut graph: HashMap<String, Vec<String>> = HashMap::new();
+  for definition in definitions {
+    let mut calls = vec!();
+    leftCalls( definition.expression, &nullable_rules, &left_parameters,
+               &mut calls );
+    graph.insert( definition.name.clone(), calls );
+  }
+
+  let mut left_recursion = LeftRecursion { involved: HashSet::new(),
+                                           leaders: HashSet::new() };
+  let nothing = HashSet::new();
+  for definition in definitions {
+    let name = &definition.name;
+    if !reaches( &graph, name, name, &nothing ) {
+      continue;
+    }
+
+    left_recursion.involved.insert( name.clone() );
+    if reaches( &graph, name, name, &left_recursion.leaders ) {
+      left_recursion.leaders.insert( name.clone() );
+    }
+  }
+  left_recursion
+}
+
+
+/// Where a part of a rule body is: in a plain rule, or in the body of the
+/// instance with the given index, where the parameters stand for the instance's
+/// arguments.
+pub type Scope = Option<usize>;
+
+
+/// An argument of a call, with the scope it was written in.
+#[derive(Clone, Copy)]
+pub struct Argument<'n, 'a: 'n> {
+  /// The `Expression` node of the argument.
+  pub expression: &'n Node<'a>,
+  pub scope: Scope
+}
+
+
+/// A parameterized rule called with particular arguments. Every instance
+/// becomes a rule of its own in the generated parser.
+pub struct Instance<'n, 'a: 'n> {
+  /// The name of the generated rule.
+  pub name: String,
+
+  /// The name of the parameterized rule.
+  pub rule: String,
+
+  /// The text of the call the instance was first found for, as written.
+  pub call: String,
+
+  pub parameters: Vec<String>,
+  pub arguments: Vec<Argument<'n, 'a>>
+}
+
+
+/// The instances of the parameterized rules called in a grammar.
+pub struct Expansion<'n, 'a: 'n> {
+  pub instances: Vec<Instance<'n, 'a>>,
+
+  // The index of the instance for each call, by the call's expanded text.
+  by_call: HashMap<String, usize>
+}
+
+
+impl<'n, 'a> Expansion<'n, 'a> {
+  /// Returns the argument that `name` stands for, if it's a parameter in
+  /// `scope`.
+  pub fn argument( &self, name: &str, scope: Scope )
+      -> Option<Argument<'n, 'a>> {
+    scope.and_then( |index| {
+      let instance = &self.instances[ index ];
+      instance.parameters.iter()
+        .position( |parameter| parameter == name )
+        .map( |position| instance.arguments[ position ] )
+    } )
+  }
+
+  /// Returns the index of the instance a `Call` node in `scope` calls.
+  pub fn instanceIndex( &self, call: &Node, scope: Scope ) -> usize {
+    self.by_call[ &self.callText( call, scope ) ]
+  }
+
+  /// Returns the instance a `Call` node in `scope` calls, if the call was
+  /// expanded; calls of undefined rules or with the wrong number of arguments
+  /// aren't.
+  pub fn findInstance( &self, call: &Node, scope: Scope )
+      -> Option<&Instance<'n, 'a>> {
+    self.by_call.get( &self.callText( call, scope ) )
+      .map( |&index| &self.instances[ index ] )
+  }
+
+  /// Returns the instance a `Call` node in `scope` calls.
+  pub fn instance( &self, call: &Node, scope: Scope ) -> &Instance<'n, 'a> {
+    &self.instances[ self.instanceIndex( call, scope ) ]
+  }
+
+  /// Returns the rules of the generated parser: the plain rules, then the
+  /// instances, each as its parameterized rule under the instance's name and
+  /// with the instance's scope.
+  pub fn rules( &self, definitions: &[Definition<'n, 'a>] )
+      -> Vec<( Definition<'n, 'a>, Scope )> {
+    let plain_rules = definitions.iter()
+      .filter( |definition| !definition.isParameterized() )
+      .map( |definition| ( definition.clone(), None ) );
+    let instances = self.instances.iter().enumerate()
+      .map( |( index, instance )| {
+        let rule = definitions.iter()
+          .find( |definition| definition.name == instance.rule )
+          .unwrap();
+        ( Definition { name: instance.name.clone(), .. rule.clone() },
+          Some( index ) )
+      } );
+    plain_rules.chain( instances ).collect()
+  }
+
+  // Returns the text of the call with the parameters in its arguments replaced
+  // by what they stand for, so that calls that expand to the same rule have
+  // the same text.
+  fn callText( &self, call: &Node, scope: Scope ) -> String {
+    let arguments: Vec<String> = callArguments( call ).iter()
+      .map( |argument| self.argumentText( argument, scope ) )
+      .collect();
+    format!( "{}({})", callName( call ), arguments.join( ", " ) )
+  }
+
+  // A parameter passed on as a whole argument needs no parentheses, so that
+  // `List(X, Sep)` calling `List(X, Sep)` is the same call.
+  fn argumentText( &self, argument: &Node, scope: Scope ) -> String {
+    match self.argument( &matchedString( argument ), scope ) {
+      Some( passed ) => self.argumentText( passed.expression, passed.scope ),
+      _ => self.expandedText( argument, scope ).trim().to_string()
+    }
+  }
+
+  fn expandedText( &self, node: &Node, scope: Scope ) -> String {
+    match node.name {
+      "Spacing" => return " ".to_string(),
+      "Call" => return self.callText( node, scope ),
+      "Primary" => {
+        let first = &children( node )[ 0 ];
+        let argument = if first.name == "Identifier" {
+          self.argument( &identifierName( first ), scope )
+        } else {
+          None
+        };
+        if let Some( argument ) = argument {
+          return format!( "({})",
+                          self.expandedText( argument.expression,
+                                             argument.scope ).trim() );
+        }
+      }
+      _ => ()
+    }
+
+    match node.contents {
+      Data( data ) => String::from_utf8_lossy( data ).into_owned(),
+      Children( ref nodes ) => {
+        nodes.iter()
+          .map( |child| self.expandedText( child, scope ) )
+          .collect::<Vec<String>>()
+          .concat()
+      }
+      _ => String::new()
+    }
+  }
+}
+
+
+// How deep instances can be nested, counting an instance called from the body
+// of another as one level deeper. Only a rule that keeps calling itself with
+// new arguments nests this deep.
+const MAX_DEPTH: usize = 32;
+
+
+// Returns a name for an instance of `rule` that isn't `taken`. Instances with
+// rule names for arguments are named after them, like `List_Item_Comma` for
+// `List(Item, Comma)`; others are numbered.
+fn instanceName( rule: &str,
+                 argument_texts: &[String],
+                 taken: &mut HashSet<String> ) -> String {
+  let names: Vec<&str> = argument_texts.iter()
+    .map( |text| text.trim().trim_matches( |ch| ch == '(' || ch == ')' ) )
+    .take_while( |text| {
+      text.chars().next().map_or( false, |ch| !ch.is_digit( 10 ) ) &&
+        text.chars().all( |ch| ch.is_alphanumeric() || ch == '_' )
+    } )
+    .collect();
+  let base = if names.len() == argument_texts.len() {
+    format!( "{}_{}", rule, names.join( "_" ) )
+  } else {
+    format!( "{}_1", rule )
+  };
+
+  let mut name = base.clone();
+  let mut number = 2;
+  while !taken.insert( name.clone() ) {
+    name = if names.len() == argument_texts.len() {
+      format!( "{}_{}", base, number )
+    } else {
+      format!( "{}_{}", rule, number )
+    };
+    number += 1;
+  }
+  name
+}
+
+
+/// Finds the instances of the parameterized rules called in the grammar,
+/// starting with the calls in the plain rules. Calls of undefined rules or
+/// with the wrong number of arguments are left out. Fails with the call that
+/// would nest instances more than `MAX_DEPTH` deep, which happens when a rule
+/// keeps calling itself with new arguments.
+pub fn expand<'n, 'a>( definitions: &[Definition<'n, 'a>] )
+    -> Result<Expansion<'n, 'a>, &'n Node<'a>> {
+  let by_name: HashMap<&str, &Definition<'n, 'a>> = definitions.iter()
+    .map( |definition| ( &definition.name[..], definition ) )
+    .collect();
+  let mut taken: HashSet<String> = definitions.iter()
+    .map( |definition| definition.name.clone() )
+    .collect();
+  let mut expansion = Expansion { instances: vec!(), by_call: HashMap::new() };
+  let mut depths: Vec<usize> = vec!();
+
+  // The rule bodies to look for calls in, with their scopes.
+  let mut bodies: Vec<( &'n Node<'a>, Scope )> = definitions.iter()
+    .filter( |definition| !definition.isParameterized() )
+    .map( |definition| ( definition.expression, None ) )
+    .collect();
+  let mut next = 0;
+  while next < bodies.len() {
+    let ( body, scope ) = bodies[ next ];
+    next += 1;
+    for call in body.preOrder().filter( |node| node.name == "Call" ) {
+      let text = expansion.callText( call, scope );
+      let arguments = callArguments( call );
+      let definition = match by_name.get( &callName( call )[..] ) {
+        Some( definition ) if !expansion.by_call.contains_key( &text ) &&
+                              definition.parameters.len() == arguments.len() &&
+                              definition.isParameterized() => definition,
+        _ => continue
+      };
+      let depth = scope.map_or( 0, |index| depths[ index ] ) + 1;
+      if depth > MAX_DEPTH {
+        return Err( call );
+      }
+
+      let argument_texts: Vec<String> = arguments.iter()
+        .map( |argument| expansion.argumentText( argument, scope ) )
+        .collect();
+      let index = expansion.instances.len();
+      depths.push( depth );
+      expansion.by_call.insert( text, index );
+      expansion.instances.push( Instance {
+        name: instanceName( &definition.name, &argument_texts, &mut taken ),
+        rule: definition.name.clone(),
+        call: matchedString( call ).trim().to_string(),
+        parameters: definition.parameters.clone(),
+        arguments: arguments.into_iter()
+          .map( |argument| Argument { expression: argument, scope: scope } )
+          .collect()
+      } );
+      bodies.push( ( definition.expression, Some( index ) ) );
+    }
+  }
+  Ok( expansion )
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+  use inlined_parser::parse;
+  use super::{definitions, nullableRules, nullableRulesIn, leftRecursion,
+              lexicalRules, repetitionBounds, expand};
+
+  fn names( names: &[&str] ) -> HashSet<String> {
+    names.iter().map( |name| name.to_string() ).collect()
+  }
+
+  #[test]
+  fn definitions_InGrammarOrder() {
+    let grammar = parse( b"A <- B\nB <- 'b'  # comment\nC <- A" ).unwrap();
+    let names = definitions( &grammar ).iter()
+      .map( |definition| definition.name.clone() ).collect::<Vec<_>>();
+    assert_eq!( names, vec!( "A", "B", "C" ) );
+  }
+
+
+  #[test]
+  fn definitions_TypedRule() {
+    let grammar = parse( b"A -> Vec<u8> <- 'a'* { |n| n.matchedData() }\nB <- A" )
+      .unwrap();
+    let definitions = definitions( &grammar );
+    assert_eq!( definitions[ 0 ].value_type, Some( "Vec<u8>".to_string() ) );
+    assert_eq!( definitions[ 0 ].action,
+                Some( "|n| n.matchedData()".to_string() ) );
+    assert_eq!( definitions[ 1 ].value_type, None );
+    assert_eq!( definitions[ 1 ].action, None );
+  }
+
+
+  #[test]
+  fn repetitionBounds_Works() {
+    let grammar = parse( b"A <- 'a'{3} 'b'{ 2, } 'c'{0,4}" ).unwrap();
+    let bounds: Vec<( usize, Option<usize> )> = grammar.preOrder()
+      .filter( |node| node.name == "Repetition" )
+      .map( repetitionBounds )
+      .collect();
+    assert_eq!( bounds,
+                vec!( ( 3, Some( 3 ) ), ( 2, None ), ( 0, Some( 4 ) ) ) );
+  }
+
+
+  #[test]
+  fn nullableRules_Works() {
+    let grammar = parse( br#"
+      A <- 'a'*
+      B <- A C?
+      C <- 'c'
+      D <- ''
+      E <- !C &C
+      F <- (A / C) B
+      G <- A C
+      H <- C{0,2}
+      I <- C{1,}"# ).unwrap();
+    assert_eq!( nullableRules( &definitions( &grammar ) ),
+                names( &[ "A", "B", "D", "E", "F", "H" ] ) );
+  }
+
+
+  #[test]
+  fn nullableRulesIn_SubstitutesArguments() {
+    let grammar = parse( b"A <- List('') B\nB <- List('b')\n\
+                           List(X) <- X Wrap(X)\nWrap(Y) <- Y" ).unwrap();
+    let definitions = definitions( &grammar );
+    let expansion = expand( &definitions ).unwrap();
+    let rules = expansion.rules( &definitions );
+    assert_eq!( nullableRulesIn( &rules, Some( &expansion ) ),
+                names( &[ "List_1", "Wrap_1" ] ) );
+    assert!( nullableRules( &definitions ).is_empty() );
+  }
+
+
+  #[test]
+  fn lexicalRules_Works() {
+    let grammar = parse( br#"
+      A <- B C
+      B <~ D 'b'
+      C <- D E
+      D <- 'd'
+      @skip E <- F*
+      F <- ' '
+      @token G <- H
+      H <- 'h'"# ).unwrap();
+    assert_eq!( lexicalRules( &definitions( &grammar ) ),
+                names( &[ "B", "D", "E", "F", "G", "H" ] ) );
+  }
+
+
+  #[test]
+  fn leftRecursion_Direct() {
+    let grammar = parse( br#"
+      Expr <- Expr '+' Term / Term
+      Term <- Term '*' Num / Num
+      Num  <- [0-9]+"# ).unwrap();
+    let left_recursion = leftRecursion( &definitions( &grammar ) );
+    assert_eq!( left_recursion.involved, names( &[ "Expr", "Term" ] ) );
+    assert_eq!( left_recursion.leaders, names( &[ "Expr", "Term" ] ) );
+  }
+
+
+  #[test]
+  fn leftRecursion_Indirect() {
+    let grammar = parse( br#"
+      A <- B 'x' / 'y'
+      B <- C A 'z'
+      C <- 'c'?
+      D <- A"# ).unwrap();
+    let left_recursion = leftRecursion( &definitions( &grammar ) );
+    assert_eq!( left_recursion.involved, names( &[ "A", "B" ] ) );
+    assert_eq!( left_recursion.leaders, names( &[ "A" ] ) );
+  }
+
+
+  #[test]
+  fn leftRecursion_NotLeftRecursive() {
+    let grammar = parse( br#"
+      A <- 'a' A / B
+      B <- '(' A ')'"# ).unwrap();
+    let left_recursion = leftRecursion( &definitions( &grammar ) );
+    assert!( left_recursion.involved.is_empty() );
+    assert!( left_recursion.leaders.is_empty() );
+  }
+
+
+  #[test]
+  fn expand_NamesInstances() {
+    let grammar = parse( b"A <- List(B, ',') List(B, C) Pair(C, C) Pair(C, C)\n\
+                           List(X, Sep) <- X (Sep X)* Pair(X, X)\n\
+                           Pair(X, Y) <- X Y\n\
+                           B <- 'b'\nC <- 'c'" ).unwrap();
+    let definitions = definitions( &grammar );
+    let expansion = expand( &definitions ).ok().unwrap();
+    let instances: Vec<( &str, &str )> = expansion.instances.iter()
+      .map( |instance| ( &instance.name[..], &instance.rule[..] ) )
+      .collect();
+    assert_eq!( instances, vec!( ( "List_1", "List" ),
+                                 ( "List_B_C", "List" ),
+                                 ( "Pair_C_C", "Pair" ),
+                                 ( "Pair_B_B", "Pair" ) ) );
+  }
+
+
+  #[test]
+  fn expand_PassedParametersAreTheSameCall() {
+    let grammar = parse( b"A <- R('a')\nR(X) <- X R(X)? R((X))?" ).unwrap();
+    assert!( expand( &definitions( &grammar ) ).is_err() );
+
+    let grammar = parse( b"A <- R('a')\nR(X) <- X R(X)?" ).unwrap();
+    let rules = definitions( &grammar );
+    assert_eq!( expand( &rules ).ok().unwrap().instances.len(), 1 );
+  }
+
+
+  #[test]
+  fn leftRecursion_ThroughArguments() {
+    let grammar = parse( b"A <- Wrap(A 'a') / 'b'\nWrap(X) <- X\n\
+                           B <- Wrap('b' B) / 'b'" ).unwrap();
+    let left_recursion = leftRecursion( &definitions( &grammar ) );
+    assert_eq!( left_recursion.involved, names( &[ "A" ] ) );
+  }
+}
@@ -0,0 +1,772 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, HashSet};
+use std::str;
+use inlined_parser::Node;
+use super::analysis::{Definition, Expansion, Scope, child, children,
+                      definitions, expand, hasChild, identifierName,
+                      repetitionBounds};
+use super::{Options, literalBytes, repeatBoundsOutput, startRule,
+            stringContent};
+
+// The parts of a rule body that determine the type of its AST node and how the
+// node's children are matched up with it.
+enum Shape {
+  Rule( String ),
+
+  // Something that produces a single unnamed node; holds the contents of the
+  // string literal for literals.
+  Token( Option<String> ),
+
+  // A fused expression; its value is the text it matched.
+  Fused,
+
+  // A labeled expression; the label names its value.
+  Labeled( String, Box<Shape> ),
+
+  // A predicate or a cut; it produces no nodes.
+  Lookahead,
+  Sequence( Vec<Shape> ),
+  Choice( Vec<Shape> ),
+
+  // A repetition with the minimum and maximum number of matches.
+  Repeat( Box<Shape>, usize, Option<usize> ),
+  Optional( Box<Shape> ),
+
+  // An expression with error recovery; it has no value if it was recovered
+  // from.
+  Recovered( Box<Shape> )
+}
+
+
+// The rules of the grammar by name, with the instances of its parameterized
+// rules.
+struct Rules<'r, 'n: 'r, 'a: 'n> {
+  by_name: HashMap<&'r str, &'r Definition<'n, 'a>>,
+  expansion: &'r Expansion<'n, 'a>
+}
+
+
+// The shape of a reference to `rule` (or an instance of it) under `name`; the
+// body of an `@inline` rule is in `scope`.
+fn referenceShape( rule: Option<&&Definition>,
+                   name: String,
+                   rules: &Rules,
+                   scope: Scope ) -> Shape {
+  match rule {
+    Some( rule ) if rule.isSilent() => Shape::Lookahead,
+    Some( rule ) if rule.isAnnotated( "inline" ) => {
+      shapeOf( rule.expression, rules, scope )
+    }
+    _ => Shape::Rule( name )
+  }
+}
+
+
+// References to `@silent` rules match nothing and references to `@inline` rules
+// match the rule's body, since that's what those rules produce. Parameters
+// have the shape of their arguments.
+fn shapeOf( node: &Node, rules: &Rules, scope: Scope ) -> Shape {
+  let nodes = children( node );
+  match node.name {
+    "Expression" => {
+      let mut alternatives: Vec<Shape> = nodes.iter()
+        .filter( |child| child.name == "Sequence" )
+        .map( |child| shapeOf( child, rules, scope ) )
+        .collect();
+      if alternatives.len() == 1 {
+        alternatives.pop().unwrap()
+      } else {
+        Shape::Choice( alternatives )
+      }
+    }
+    "Sequence" => {
+      // Nested sequences, from groups or inlined rules, are spliced in.
+      let mut items = vec!();
+      for child in nodes {
+        match shapeOf( child, rules, scope ) {
+          Shape::Sequence( nested ) => items.extend( nested.into_iter() ),
+          shape => items.push( shape )
+        }
+      }
+      if items.len() == 1 {
+        items.pop().unwrap()
+      } else {
+        Shape::Sequence( items )
+      }
+    }
+    "Prefix" => {
+      let shape = if hasChild( node, "AND" ) || hasChild( node, "NOT" ) {
+        Shape::Lookahead
+      } else if hasChild( node, "FUSE" ) {
+        Shape::Fused
+      } else {
+        shapeOf( child( node, "Suffix" ).unwrap(), rules, scope )
+      };
+      let shape = if hasChild( node, "Recovery" ) {
+        Shape::Recovered( Box::new( shape ) )
+      } else {
+        shape
+      };
+      match child( node, "Label" ) {
+        Some( label ) => {
+          let name = identifierName( &children( label )[ 0 ] );
+          Shape::Labeled( name, Box::new( shape ) )
+        }
+        _ => shape
+      }
+    }
+    "Suffix" => {
+      let primary = Box::new( shapeOf( &nodes[ 0 ], rules, scope ) );
+      if hasChild( node, "QUESTION" ) {
+        Shape::Optional( primary )
+      } else if hasChild( node, "STAR" ) {
+        Shape::Repeat( primary, 0, None )
+      } else if hasChild( node, "PLUS" ) {
+        Shape::Repeat( primary, 1, None )
+      } else if let Some( repetition ) = child( node, "Repetition" ) {
+        let ( min, max ) = repetitionBounds( repetition );
+        Shape::Repeat( primary, min, max )
+      } else {
+        *primary
+      }
+    }
+    "Primary" => match nodes[ 0 ].name {
+      "Identifier" => {
+        let name = identifierName( &nodes[ 0 ] );
+        match rules.expansion.argument( &name, scope ) {
+          Some( argument ) => {
+            shapeOf( argument.expression, rules, argument.scope )
+          }
+          _ => {
+            let rule = rules.by_name.get( &name[..] );
+            referenceShape( rule, name, rules, None )
+          }
+        }
+      }
+      "Call" => {
+        let index = rules.expansion.instanceIndex( &nodes[ 0 ], scope );
+        let instance = &rules.expansion.instances[ index ];
+        referenceShape( rules.by_name.get( &instance.rule[..] ),
+                        instance.name.clone(),
+                        rules,
+                        Some( index ) )
+      }
+      "OPEN" => shapeOf( &nodes[ 1 ], rules, scope ),
+      "CUT" => Shape::Lookahead,
+      "Literal" if !hasChild( &nodes[ 0 ], "IgnoreCase" ) &&
+                   str::from_utf8( &literalBytes( &nodes[ 0 ] ) ).is_ok() => {
+        Shape::Token( Some( stringContent( &nodes[ 0 ] ) ) )
+      }
+      _ => Shape::Token( None )
+    },
+    _ => panic!( "Unexpected node in rule body: {}", node.name )
+  }
+}
+
+
+// Returns true if `shape` references a rule or has fused text. Without those,
+// all there is to a match is the text it matched.
+fn hasRuleValue( shape: &Shape ) -> bool {
+  match *shape {
+    Shape::Rule( _ ) | Shape::Fused => true,
+    Shape::Token( _ ) | Shape::Lookahead => false,
+    Shape::Sequence( ref items ) | Shape::Choice( ref items ) => {
+      items.iter().any( hasRuleValue )
+    }
+    Shape::Repeat( ref shape, _, _ ) | Shape::Optional( ref shape ) |
+    Shape::Recovered( ref shape ) | Shape::Labeled( _, ref shape ) => {
+      hasRuleValue( shape )
+    }
+  }
+}
+
+
+// Like `hasRuleValue`, but a choice always has a value: which alternative
+// matched, or the text it matched if its alternatives have no values.
+fn hasValue( shape: &Shape ) -> bool {
+  match *shape {
+    Shape::Rule( _ ) | Shape::Fused | Shape::Choice( _ ) => true,
+    Shape::Token( _ ) | Shape::Lookahead => false,
+    Shape::Sequence( ref items ) => items.iter().any( hasValue ),
+    Shape::Repeat( ref shape, _, _ ) | Shape::Optional( ref shape ) |
+    Shape::Recovered( ref shape ) | Shape::Labeled( _, ref shape ) => {
+      hasValue( shape )
+    }
+  }
+}
+
+
+// The name for the value of `shape`: its label, the first rule it references,
+// or `Text` for fused text and choices between alternatives without values.
+fn valueName( shape: &Shape ) -> Option<&str> {
+  match *shape {
+    Shape::Rule( ref name ) | Shape::Labeled( ref name, _ ) => Some( name ),
+    Shape::Fused => Some( "Text" ),
+    Shape::Token( _ ) | Shape::Lookahead => None,
+    Shape::Sequence( ref items ) => items.iter().filter_map( valueName ).next(),
+    Shape::Choice( ref items ) => {
+      items.iter().filter_map( valueName ).next().or( Some( "Text" ) )
+    }
+    Shape::Repeat( ref shape, _, _ ) | Shape::Optional( ref shape ) |
+    Shape::Recovered( ref shape ) => valueName( shape )
+  }
+}
+
+
+const KEYWORDS: &'static [&'static str] = &[
+  "abstract", "alignof", "as", "async", "await", "become", "box", "break",
+  "const", "continue", "crate", "do", "dyn", "else", "enum", "extern", "false",
+  "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match",
+  "mod", "move", "mut", "offsetof", "override", "priv", "proc", "pub", "pure",
+  "ref", "return", "self", "sizeof", "static", "struct", "super", "trait",
+  "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+  "where", "while", "yield" ];
+
+
+// Turns a rule name like `EndOfFile` into a field name like `end_of_file`.
+fn fieldName( rule_name: &str ) -> String {
+  let chars: Vec<char> = rule_name.chars().collect();
+  let mut name = String::new();
+  for ( index, &current ) in chars.iter().enumerate() {
+    if current.is_uppercase() && index > 0 {
+      let previous = chars[ index - 1 ];
+      let next_is_lower = chars.get( index + 1 )
+        .map_or( false, |next| next.is_lowercase() );
+      if previous.is_lowercase() || previous.is_digit( 10 ) ||
+         ( previous.is_uppercase() && next_is_lower ) {
+        name.push( '_' );
+      }
+    }
+    name.extend( current.to_lowercase() );
+  }
+
+  if KEYWORDS.contains( &&name[..] ) {
+    name.push( '_' );
+  }
+  name
+}
+
+
+// Appends a number to `name` if it's already taken.
+fn uniqueName( name: String, taken: &mut HashSet<String> ) -> String {
+  let mut unique = name.clone();
+  let mut number = 2;
+  while taken.contains( &unique ) {
+    unique = format!( "{}{}", name, number );
+    number += 1;
+  }
+  taken.insert( unique.clone() );
+  unique
+}
+
+
+fn tupleOf( items: &[String] ) -> String {
+  if items.len() == 1 {
+    items[ 0 ].clone()
+  } else {
+    format!( "( {} )", items.join( ", " ) )
+  }
+}
+
+
+// The code converting a shape; `code` is an expression that evaluates to an
+// `Option` of the value, of type `tupleOf( types )`, or of `()` if `types` is
+// empty. It uses an `AstCursor` bound to `cursor`.
+struct Conversion {
+  types: Vec<String>,
+  code: String
+}
+
+
+struct AstBuilder<'r, 'n: 'r, 'a: 'n> {
+  rules: &'r Rules<'r, 'n, 'a>,
+  rule_name: &'r str,
+  num_choices: usize,
+  declarations: Vec<String>
+}
+
+
+impl<'r, 'n, 'a> AstBuilder<'r, 'n, 'a> {
+  // Rule values are boxed unless `boxed` is false, which repetitions use since
+  // `Vec` already puts them on the heap.
+  fn convert( &mut self, shape: &Shape, boxed: bool ) -> Conversion {
+    match *shape {
+      Shape::Rule( ref name ) => {
+        if boxed {
+          Conversion {
+            types: vec!( format!( "::std::boxed::Box<{}>", name ) ),
+            code: format!( "cursor.rule( \"{0}\" ).map( |node| \
+                            ::std::boxed::Box::new( {0}::fromNode( node ) ) )",
+                           name ) }
+        } else {
+          Conversion {
+            types: vec!( name.clone() ),
+            code: format!( "cursor.rule( \"{0}\" ).map( {0}::fromNode )",
+                           name ) }
+        }
+      }
+      Shape::Token( Some( ref literal ) ) => Conversion {
+        types: vec!(),
+        code: format!( "cursor.token( Some( \"{}\".as_bytes() ) )", literal ) },
+      Shape::Token( None ) => Conversion {
+        types: vec!(),
+        code: "cursor.token( None )".to_string() },
+      Shape::Fused => Conversion {
+        types: vec!( "::std::string::String".to_string() ),
+        code: "cursor.text()".to_string() },
+      Shape::Lookahead => Conversion {
+        types: vec!(),
+        code: "Some( () )".to_string() },
+      Shape::Repeat( ref shape, min, max ) => {
+        let inner = self.convert( shape, false );
+        let code = format!( "cursor.repeat( {}, |cursor| {} )",
+                            repeatBoundsOutput( min, max ), inner.code );
+        if inner.types.is_empty() {
+          Conversion { types: vec!(), code: code + ".map( |_| () )" }
+        } else {
+          Conversion {
+            types: vec!( format!( "::std::vec::Vec<{}>",
+                                  tupleOf( &inner.types ) ) ),
+            code: code }
+        }
+      }
+      Shape::Optional( ref shape ) => {
+        let inner = self.convert( shape, true );
+        if inner.types.is_empty() {
+          Conversion { types: vec!(),
+                       code: inner.code + ".or( Some( () ) )" }
+        } else {
+          Conversion {
+            types: vec!( format!( "::std::option::Option<{}>",
+                                  tupleOf( &inner.types ) ) ),
+            code: format!( "Some( {} )", inner.code ) }
+        }
+      }
+      Shape::Recovered( ref shape ) => {
+        let inner = self.convert( shape, true );
+        if inner.types.is_empty() {
+          Conversion { types: vec!(),
+                       code: format!( "cursor.error().or_else( || {} )",
+                                      inner.code ) }
+        } else {
+          Conversion {
+            types: vec!( format!( "::std::option::Option<{}>",
+                                  tupleOf( &inner.types ) ) ),
+            code: format!( "cursor.error().map( |_| None )\
+                            .or_else( || ( {} ).map( Some ) )",
+                           inner.code ) }
+        }
+      }
+      Shape::Labeled( _, ref shape ) => self.convert( shape, boxed ),
+      Shape::Sequence( ref items ) => self.convertSequence( items, boxed ),
+      Shape::Choice( ref alternatives ) => {
+        // Which alternative matched is then only told apart by its text.
+        if !hasRuleValue( shape ) {
+          let codes: Vec<String> = alternatives.iter()
+            .map( |alternative| self.convert( alternative, true ).code )
+            .collect();
+          return Conversion {
+            types: vec!( "::std::string::String".to_string() ),
+            code: format!( "cursor.matched( |cursor| {} )",
+                           orElseChain( &codes ) ) };
+        }
+
+        self.num_choices += 1;
+        let enum_name = format!( "{}Choice{}", self.rule_name, self.num_choices );
+        let code = self.declareEnum( &enum_name, alternatives );
+        let enum_type = if boxed {
+          format!( "::std::boxed::Box<{}>", enum_name )
+        } else {
+          enum_name
+        };
+        let code = if boxed {
+          code + ".map( ::std::boxed::Box::new )"
+        } else {
+          code
+        };
+        Conversion { types: vec!( enum_type ), code: code }
+      }
+    }
+  }
+
+  fn convertSequence( &mut self, items: &[Shape], boxed: bool ) -> Conversion {
+    let num_values = items.iter().filter( |item| hasValue( item ) ).count();
+    let conversions: Vec<Conversion> = items.iter()
+      .map( |item| self.convert( item, boxed || num_values > 1 ) )
+      .collect();
+
+    let mut types = vec!();
+    let mut values = vec!();
+    for ( index, conversion ) in conversions.iter().enumerate() {
+      if !conversion.types.is_empty() {
+        types.push( tupleOf( &conversion.types ) );
+        values.push( format!( "value{}", index ) );
+      }
+    }
+
+    let mut code = if values.is_empty() {
+      "Some( () )".to_string()
+    } else {
+      format!( "Some( {} )", tupleOf( &values ) )
+    };
+    for ( index, conversion ) in conversions.iter().enumerate().rev() {
+      let binding = if conversion.types.is_empty() {
+        "_".to_string()
+      } else {
+        format!( "value{}", index )
+      };
+      code = format!( "{}.and_then( |{}| {} )", conversion.code, binding, code );
+    }
+
+    Conversion { types: types,
+                 code: format!( "cursor.attempt( |cursor| {} )", code ) }
+  }
+
+  // Declares an enum with a variant for every alternative and returns the code
+  // converting to it. Alternatives without values hold the text they matched.
+  fn declareEnum( &mut self, enum_name: &str, alternatives: &[Shape] )
+      -> String {
+    let mut taken = HashSet::new();
+    let mut variants = vec!();
+    let mut codes = vec!();
+    for ( index, alternative ) in alternatives.iter().enumerate() {
+      let name = match valueName( alternative ) {
+        Some( rule ) if hasRuleValue( alternative ) => rule.to_string(),
+        _ => format!( "Alternative{}", index + 1 )
+      };
+      let variant = uniqueName( name, &mut taken );
+
+      let conversion = if hasRuleValue( alternative ) {
+        self.convert( alternative, true )
+      } else {
+        let inner = self.convert( alternative, true );
+        Conversion {
+          types: vec!( "::std::string::String".to_string() ),
+          code: format!( "cursor.matched( |cursor| {} )", inner.code ) }
+      };
+      let num_values = conversion.types.len();
+      let constructor = match num_values {
+        1 => format!( "{}::{}", enum_name, variant ),
+        _ => {
+          let values: Vec<String> =
+            ( 0 .. num_values ).map( |x| format!( "value{}", x ) ).collect();
+          format!( "|( {0} )| {1}::{2}( {0} )",
+                   values.join( ", " ), enum_name, variant )
+        }
+      };
+
+      variants.push( format!( "{}( {} )",
+                              variant, conversion.types.join( ", " ) ) );
+      codes.push( format!( "{}.map( {} )", conversion.code, constructor ) );
+    }
+
+    self.declarations.push( format!(
+      "#[derive(Debug, Clone, PartialEq)]\npub enum {} {{\n  {}\n}}\n",
+      enum_name,
+      variants.join( ",\n  " ) ) );
+    orElseChain( &codes )
+  }
+
+  fn declareRule( &mut self, definition: &Definition, scope: Scope ) {
+    let name = &definition.name;
+    let shape = shapeOf( definition.expression, self.rules, scope );
+    if definition.isToken() || !hasRuleValue( &shape ) {
+      self.declarations.push( format!(
+        "#[derive(Debug, Clone, PartialEq)]\n\
+         pub struct {0} {{\n  pub text: ::std::string::String\n}}\n\n\
+         impl {0} {{\n  \
+           pub fn fromNode( node: &base::Node ) -> {0} {{\n    \
+             {0} {{ text: node.matchedText() }}\n  \
+           }}\n\
+         }}\n",
+        name ) );
+      return;
+    }
+
+    let ( conversion, construction ) = match shape {
+      Shape::Choice( ref alternatives ) => {
+        let code = self.declareEnum( name, alternatives );
+        ( code, "value".to_string() )
+      }
+      _ => {
+        let items = match shape {
+          Shape::Sequence( items ) => items,
+          shape => vec!( shape )
+        };
+        let conversion = self.convertSequence( &items, true );
+
+        let mut taken = HashSet::new();
+        let fields: Vec<String> = items.iter()
+          .filter( |item| hasValue( item ) )
+          .map( |item| uniqueName( fieldName( valueName( item ).unwrap() ),
+                                   &mut taken ) )
+          .collect();
+        let values: Vec<String> =
+          ( 0 .. fields.len() ).map( |x| format!( "value.{}", x ) ).collect();
+        let values = if fields.len() == 1 {
+          vec!( "value".to_string() )
+        } else {
+          values
+        };
+
+        self.declarations.push( format!(
+          "#[derive(Debug, Clone, PartialEq)]\npub struct {} {{\n  {}\n}}\n",
+          name,
+          fields.iter().zip( conversion.types.iter() )
+            .map( |( field, field_type )| {
+              format!( "pub {}: {}", field, field_type )
+            } )
+            .collect::<Vec<String>>().join( ",\n  " ) ) );
+
+        let construction = format!(
+          "{} {{ {} }}",
+          name,
+          fields.iter().zip( values.iter() )
+            .map( |( field, value )| format!( "{}: {}", field, value ) )
+            .collect::<Vec<String>>().join( ", " ) );
+        ( conversion.code, construction )
+      }
+    };
+
+    self.declarations.push( format!(
+      "impl {0} {{\n  \
+         pub fn fromNode( node: &base::Node ) -> {0} {{\n    \
+           let mut cursor = base::AstCursor::new( node );\n    \
+           match {1} {{\n      \
+             Some( value ) => {2},\n      \
+             None => panic!( \"`{0}` node doesn't match its rule.\" )\n    \
+           }}\n  \
+         }}\n\
+       }}\n",
+      name, conversion, construction ) );
+  }
+}
+
+
+fn orElseChain( codes: &[String] ) -> String {
+  let mut chain = codes[ 0 ].clone();
+  for code in &codes[ 1.. ] {
+    chain = format!( "{}.or_else( || {} )", chain, code );
+  }
+  chain
+}
+
+
+/// Returns an `ast` module with a type for every rule in the `Grammar` node
+/// and a `parseAst` function that parses its input into the type of the start
+/// rule. It goes after the `rules` module.
+///
+/// A sequence becomes a struct with a field for every rule it references, an
+/// ordered choice becomes an enum, `*` and `+` become a `Vec`, `?` an `Option`
+/// and so does `^`, which is `None` if the parser recovered from an error there.
+/// Fused expressions become a `String` of the text they matched, and so do
+/// choices between alternatives that reference no rules, like `'+' / '-'`; in
+/// an enum, such alternatives hold their text. Rules that neither reference
+/// other rules nor have fused text, and token rules, become a struct holding
+/// the matched text. `@silent` and `@inline` rules don't produce nodes of
+/// their own, so they get no type.
+pub fn codeForAst( grammar: &Node, options: &Options ) -> String {
+  let definitions = definitions( grammar );
+  let expansion = expand( &definitions ).unwrap();
+  let by_name = definitions.iter()
+    .map( |definition| ( &definition.name[..], definition ) )
+    .collect();
+  let rules = Rules { by_name: by_name, expansion: &expansion };
+  let mut declarations = vec!();
+  for &( ref definition, scope ) in expansion.rules( &definitions ).iter() {
+    if definition.isSilent() ||
+       definition.isAnnotated( "inline" ) {
+      continue;
+    }
+
+    let mut builder = AstBuilder { rules: &rules,
+                                   rule_name: &definition.name,
+                                   num_choices: 0,
+                                   declarations: vec!() };
+    builder.declareRule( definition, scope );
+    declarations.extend( builder.declarations.into_iter() );
+  }
+
+  let start_rule = startRule( &definitions, options );
+  let declarations: Vec<String> = declarations.iter()
+    .map( |declaration| {
+      declaration.lines()
+        .map( |line| if line.is_empty() {
+          String::new()
+        } else {
+          "  ".to_string() + line
+        } )
+        .collect::<Vec<String>>().join( "\n" )
+    } )
+    .collect();
+  format!( "\n\npub mod ast {{\n  use super::base;\n\n{}\n}}\n\n\
+            pub fn parseAst( input: &[u8] ) -> Result< ast::{1}, ParseError > {{\n  \
+              parse( input ).map( |node| ast::{1}::fromNode( &node ) )\n\
+            }}\n",
+           declarations.join( "\n\n" ),
+           start_rule )
+}
+
+
+#[cfg(test)]
+mod tests {
+  use inlined_parser::parse;
+  use generator::Options;
+  use super::{codeForAst, fieldName};
+
+  fn astFor( input: &[u8] ) -> String {
+    codeForAst( &parse( input ).unwrap(), &Options::new() )
+  }
+
+  #[test]
+  fn fieldName_Works() {
+    assert_eq!( fieldName( "EndOfFile" ), "end_of_file" );
+    assert_eq!( fieldName( "HTTPServer" ), "http_server" );
+    assert_eq!( fieldName( "dec_octet2" ), "dec_octet2" );
+    assert_eq!( fieldName( "Type" ), "type_" );
+  }
+
+
+  #[test]
+  fn codeForAst_Sequence() {
+    let code = astFor( b"A <- B 'x' C* D? B\nB <- 'b'\nC <- 'c'\nD <- 'd'" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub b: ::std::boxed::Box<B>,\n    \
+                             pub c: ::std::vec::Vec<C>,\n    \
+                             pub d: ::std::option::Option<\
+                             ::std::boxed::Box<D>>,\n    \
+                             pub b2: ::std::boxed::Box<B>\n  }" ) );
+    assert!( code.contains( "  pub struct B {\n    \
+                             pub text: ::std::string::String\n  }" ) );
+    assert!( code.contains(
+      "pub fn parseAst( input: &[u8] ) -> Result< ast::A, ParseError >" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_CountedRepetition() {
+    let code = astFor( b"A <- first:B{2} rest:B{0,}\nB <- 'b'" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub first: ::std::vec::Vec<B>,\n    \
+                             pub rest: ::std::vec::Vec<B>\n  }" ) );
+    assert!( code.contains(
+      "cursor.repeat( 2, Some( 2 ), |cursor| cursor.rule( \"B\" )" ) );
+    assert!( code.contains(
+      "cursor.repeat( 0, None, |cursor| cursor.rule( \"B\" )" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_ParameterizedRules() {
+    let code = astFor( b"A <- items:List(B, ',') Opt(B)\n\
+                         List(X, Sep) <- X (Sep X)*\n\
+                         @inline Opt(X) <- X?\n\
+                         B <- 'b'" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub items: ::std::boxed::Box<List_1>,\n    \
+                             pub b: ::std::option::Option<\
+                             ::std::boxed::Box<B>>\n  }" ) );
+    assert!( code.contains( "  pub struct List_1 {\n    \
+                             pub b: ::std::boxed::Box<B>,\n    \
+                             pub b2: ::std::vec::Vec<B>\n  }" ) );
+    assert!( !code.contains( "pub struct List {" ) );
+    assert!( !code.contains( "pub struct Opt" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_LabelsNameFields() {
+    let code = astFor( b"A <- lhs:B '+' rhs:B ops:(B / C)*\nB <- 'b'\nC <- 'c'" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub lhs: ::std::boxed::Box<B>,\n    \
+                             pub rhs: ::std::boxed::Box<B>,\n    \
+                             pub ops: ::std::vec::Vec<AChoice1>\n  }" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_SilentAndInlineRules() {
+    let code = astFor( b"A <- B S C\n@silent S <- ' '\n@inline C <- B B?\n\
+                         B <- 'b'" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub b: ::std::boxed::Box<B>,\n    \
+                             pub b2: ::std::boxed::Box<B>,\n    \
+                             pub b3: ::std::option::Option<\
+                             ::std::boxed::Box<B>>\n  }" ) );
+    assert!( !code.contains( "pub struct S" ) );
+    assert!( !code.contains( "pub struct C" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_Choice() {
+    let code = astFor( b"A <- B / 'x' C / 'y'\nB <- 'b'\nC <- 'c'" );
+    assert!( code.contains( "  pub enum A {\n    \
+                             B( ::std::boxed::Box<B> ),\n    \
+                             C( ::std::boxed::Box<C> ),\n    \
+                             Alternative3( ::std::string::String )\n  }" ) );
+    assert!( code.contains(
+      "cursor.matched( |cursor| cursor.token( Some( \"y\".as_bytes() ) ) )\
+       .map( A::Alternative3 )" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_TokenChoiceKeepsText() {
+    let code = astFor( b"Sum <- Sum ('+' / '-') Value / Value\n\
+                         Value <- [0-9]+ / Name\n\
+                         Name <- [a-z]+\n\
+                         Op <- '*' / '/'\n\
+                         Pair <- Value (',' / ';') Value" );
+    assert!( code.contains( "  pub enum Sum {\n    \
+                             Sum( ::std::boxed::Box<Sum>, \
+                             ::std::string::String, \
+                             ::std::boxed::Box<Value> ),\n    \
+                             Value( ::std::boxed::Box<Value> )\n  }" ) );
+    assert!( code.contains(
+      "cursor.matched( |cursor| cursor.token( Some( \"+\".as_bytes() ) )\
+       .or_else( || cursor.token( Some( \"-\".as_bytes() ) ) ) )" ) );
+    assert!( code.contains( "  pub enum Value {\n    \
+                             Alternative1( ::std::string::String ),\n    \
+                             Name( ::std::boxed::Box<Name> )\n  }" ) );
+    assert!( code.contains( "  pub struct Pair {\n    \
+                             pub value: ::std::boxed::Box<Value>,\n    \
+                             pub text: ::std::string::String,\n    \
+                             pub value2: ::std::boxed::Box<Value>\n  }" ) );
+    assert!( code.contains( "  pub struct Op {\n    \
+                             pub text: ::std::string::String\n  }" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_NestedChoiceAndFusedText() {
+    let code = astFor( b"A <- ~[a-z]+ (B / C 'c')+ ![x]\nB <- 'b'\nC <- 'c'" );
+    assert!( code.contains( "  pub enum AChoice1 {\n    \
+                             B( ::std::boxed::Box<B> ),\n    \
+                             C( ::std::boxed::Box<C> )\n  }" ) );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub text: ::std::string::String,\n    \
+                             pub b: ::std::vec::Vec<AChoice1>\n  }" ) );
+  }
+
+
+  #[test]
+  fn codeForAst_RecoveredIsOptional() {
+    let code = astFor( b"A <- B^C ';'^C\nB <- 'b'\nC <- (!';' .)*" );
+    assert!( code.contains( "  pub struct A {\n    \
+                             pub b: ::std::option::Option<\
+                             ::std::boxed::Box<B>>\n  }" ) );
+  }
+}
@@ -14,7 +14,10 @@
 use std::str;
 use std::ascii::AsciiExt;
 use inlined_parser::{Node, Children, Data};
-use self::unescape::unescapeString;
+use self::unescape::{unescape, unescapeString};
+pub use self::validation::{validate, Diagnostic, Severity};
+pub use self::ast::codeForAst;
+pub use self::sources::merge;
 
 mod unescape;
 
@@ -26,152 +29,496 @@ macro_rules! node_children( ( $node:expr ) => ( {
     _ => panic!( "No children in node." )
   } } ) );
 
+mod analysis;
+mod ast;
+mod sources;
+mod validation;
 
-pub fn codeForNode( node: &Node ) -> String {
+
+/// Options controlling the code emitted for a grammar.
+pub struct Options {
+  /// Emit packrat rules that memoize their result for every input offset.
+  pub memoize: bool,
+
+  /// Emit an `ast` module with a type for every rule, and a `parseAst` function
+  /// that builds those types from the parse tree.
+  pub ast: bool,
+
+  /// Parse every rule in byte mode, as if it were annotated with `@bytes`.
+  pub bytes: bool,
+
+  /// The rule `parse` starts with. The first rule of the grammar if not given.
+  pub start: Option<String>,
+
+  /// Emit a `parse_<rule>` function for every rule that can have one, as if
+  /// they were all annotated with `@public`.
+  pub public: bool,
+
+  /// Make `parse` and the `parse_<rule>` functions fail unless their rule
+  /// matches all of the input, not just a prefix of it.
+  pub full_input: bool
+}
+
+
+impl Options {
+  pub fn new() -> Options {
+    Options { memoize: false,
+              ast: false,
+              bytes: false,
+              start: None,
+              public: false,
+              full_input: false }
+  }
+}
+
+
+/// Returns the `rule!` definitions for all the rules in the `Grammar` node
+/// produced by parsing a PEG grammar.
+pub fn codeForGrammar( node: &Node, options: &Options ) -> String {
+  let definitions = analysis::definitions( node );
+  let expansion = analysis::expand( &definitions ).unwrap();
+  let left_recursion = analysis::leftRecursion( &definitions );
+  let skip_rule = analysis::skipRule( &definitions );
+  let lexical_rules = analysis::lexicalRules( &definitions );
+
+  expansion.rules( &definitions ).iter().map( |&( ref definition, scope )| {
+    // The analyses only know the parameterized rule an instance comes from.
+    let rule_name = match scope {
+      Some( index ) => &expansion.instances[ index ].rule,
+      _ => &definition.name
+    };
+
+    // Rules on a left-recursive cycle that don't grow the seed themselves
+    // must see the latest seed, so they can't be memoized. Instances can be on
+    // such a cycle through their arguments, so they're never memoized.
+    let memoization = if left_recursion.leaders.contains( rule_name ) {
+      "leftrec "
+    } else if options.memoize && scope.is_none() &&
+              !left_recursion.involved.contains( rule_name ) {
+      "memo "
+    } else {
+      ""
+    };
+    let mode = if definition.isSilent() {
+      "silent "
+    } else if definition.isAnnotated( "inline" ) {
+      "inline "
+    } else {
+      ""
+    };
+    let skip = if lexical_rules.contains( rule_name ) {
+      None
+    } else {
+      skip_rule.as_ref().map( |name| &name[..] )
+    };
+    let context = RuleContext { skip: skip,
+                                bytes: isByteMode( definition, options ),
+                                scope: scope,
+                                expansion: &expansion };
+    definitionOutput( definition,
+                      &format!( "rule!( {}{}", memoization, mode ),
+                      context )
+  } ).collect::<Vec<String>>().concat()
+}
+
+
+/// Returns the name of the rule parsing starts with: the `start` rule of the
+/// options, or else the first rule of the grammar.
+pub fn startRule( definitions: &[analysis::Definition],
+                  options: &Options ) -> String {
+  match options.start {
+    Some( ref start ) => start.clone(),
+    _ => definitions[ 0 ].name.clone()
+  }
+}
+
+
+/// Returns the name of the rule in the `Grammar` node that parsing starts with.
+pub fn nameOfStartRule( node: &Node, options: &Options ) -> String {
+  startRule( &analysis::definitions( node ), options )
+}
+
+
+/// Returns the name of the `@skip` rule in the `Grammar` node, if it has one.
+pub fn nameOfSkipRule( node: &Node ) -> Option<String> {
+  analysis::skipRule( &analysis::definitions( node ) )
+}
+
+
+/// Returns true if the rule gets a `parse_<rule>` function, either because
+/// it's annotated with `@public` or because the options make every rule that
+/// produces a node of its own public.
+pub fn isPublic( definition: &analysis::Definition,
+                 options: &Options ) -> bool {
+  definition.isAnnotated( "public" ) ||
+    ( options.public &&
+      !definition.isParameterized() &&
+      !definition.isSilent() &&
+      !definition.isAnnotated( "inline" ) )
+}
+
+
+/// Returns a `parseValue` function that parses its input and returns the value
+/// built by the action of the start rule in the `Grammar` node, or nothing if
+/// that rule has no action. It goes after the `rules` module.
+pub fn codeForValueParser( node: &Node, options: &Options ) -> String {
+  let definitions = analysis::definitions( node );
+  let start_rule = startRule( &definitions, options );
+  match definitions.iter().find( |definition| definition.name == start_rule ) {
+    Some( &analysis::Definition { ref name,
+                                  value_type: Some( ref value_type ),
+                                  .. } ) => {
+      format!( "\n\npub fn parseValue<'a>( input: &'a [u8] ) \
+                -> Result< {}, ParseError > {{\n  \
+                parse( input ).map( |node| rules::{}( &node ) )\n}}\n",
+               value_type,
+               valueFunctionName( name ) )
+    }
+    _ => String::new()
+  }
+}
+
+
+/// Returns a `parse_<rule>` function for every public rule in the `Grammar`
+/// node, which parses its input starting with that rule. They go after the
+/// `rules` module.
+pub fn codeForEntryPoints( node: &Node, options: &Options ) -> String {
+  analysis::definitions( node ).iter()
+    .filter( |definition| isPublic( definition, options ) )
+    .map( |definition| {
+      format!( "\n\npub fn parse_{0}<'a>( input: &'a [u8] ) \
+                -> Result< Node<'a>, ParseError > {{\n  \
+                parseFrom( rules::{0}, ParseState::new( input ), input )\n}}\n",
+               definition.name )
+    } )
+    .collect::<Vec<String>>()
+    .concat()
+}
+
+
+fn valueFunctionName( rule_name: &str ) -> String {
+  rule_name.to_string() + "_value"
+}
+
+
+/// Returns true if the rule is parsed in byte mode, where `.` matches a single
+/// byte, literals are byte strings and classes hold bytes.
+pub fn isByteMode( definition: &analysis::Definition,
+                   options: &Options ) -> bool {
+  options.bytes || definition.isAnnotated( "bytes" )
+}
+
+
+// What the code for a part of a rule body depends on besides the part itself.
+#[derive(Clone, Copy)]
+struct RuleContext<'s> {
+  // The name of the skip rule to match between the elements of the part, or
+  // nothing if the part is in a lexical rule or fused.
+  skip: Option<&'s str>,
+
+  // Whether the rule is parsed in byte mode.
+  bytes: bool,
+
+  // The scope of the parameters the part can use.
+  scope: analysis::Scope,
+
+  // The instances of the parameterized rules that calls refer to.
+  expansion: &'s analysis::Expansion<'s, 's>
+}
+
+
+fn codeForNode( node: &Node, context: RuleContext ) -> String {
   match node.name {
-    "Definition" => definitionOutput( node ),
-    "Expression" => expressionOutput( node ),
-    "Sequence" => sequenceOutput( node ),
-    "Literal" => literalOutput( node ),
-    "Class" => classOutput( node ),
-    "Suffix" => suffixOutput( node ),
-    "Prefix" => prefixOutput( node ),
-    "Primary" => primaryOutput( node ),
+    "Expression" => expressionOutput( node, context ),
+    "Sequence" => sequenceOutput( node, context ),
+    "Literal" => literalOutput( node, context ),
+    "Class" => classOutput( node, context ),
+    "Suffix" => suffixOutput( node, context ),
+    "Prefix" => prefixOutput( node, context ),
+    "Primary" => primaryOutput( node, context ),
+    "DOT" if context.bytes => String::from( "&base::AnyByte" ),
     "DOT" => String::from( "&base::Dot" ),
+    "CUT" => String::from( "&base::Cut" ),
     "ARROW" => String::from( " <- " ),
     "SLASH" => String::from( ", " ),
-    "Spacing" | "EndOfLine" | "OPEN" | "CLOSE" => String::new(),
-    _ => codeForNodeContents( node )
+    "Spacing" | "EndOfLine" | "OPEN" | "CLOSE" | "IgnoreCase" |
+      "Negation" => {
+      String::new()
+    }
+    _ => codeForNodeContents( node, context )
   }
 }
 
 
-fn codeForNodeContents( node: &Node ) -> String {
+fn codeForNodeContents( node: &Node, context: RuleContext ) -> String {
   match node.contents {
     Children( ref children ) => {
-      children.iter().map( codeForNode ).collect::<Vec<String>>().concat()
+      children.iter()
+        .map( |child| codeForNode( child, context ) )
+        .collect::<Vec<String>>()
+        .concat()
     }
     Data( data ) => str::from_utf8( data ).unwrap().to_string(),
+    _ => String::new()
   }
 }
 
 
-fn wrapChildrenOutput( before: &str, node: &Node, after: &str ) -> String {
-  before.to_string() + &codeForNodeContents( node ) + after
+fn wrapChildrenOutput( before: &str,
+                       node: &Node,
+                       after: &str,
+                       context: RuleContext ) -> String {
+  before.to_string() + &codeForNodeContents( node, context ) + after
 }
 
 
-fn wrapNodeOutput( before: &str, node: &Node, after: &str ) -> String {
-  before.to_string() + &codeForNode( node ) + after
+fn wrapNodeOutput( before: &str,
+                   node: &Node,
+                   after: &str,
+                   context: RuleContext ) -> String {
+  before.to_string() + &codeForNode( node, context ) + after
 }
 
 
-fn definitionOutput( node: &Node ) -> String {
-  fn arrowName( node: &Node ) -> &'static str {
-    match node.contents {
-      Children( ref nodes ) => {
-        match nodes[ 1 ].contents {
-          Children( ref nodes2 ) => {
-            nodes2[ 0 ].name
-          },
-          _ => panic!( "No children in node.")
-        }
-      },
-      _ => panic!( "No children in node.")
-    }
-  }
-
-  let children = node_children!( node );
-  let inner_code = match arrowName( node ) {
-    "FUSEARROW" => {
-      codeForNode( &children[ 0 ] ) +
-      &codeForNode( &children[ 1 ] ) +
-      "fuse!( " +
-      &codeForNode( &children[ 2 ] ) +
-      " )"
-    },
-    _ => codeForNodeContents( node )
+fn definitionOutput( definition: &analysis::Definition,
+                     rule_macro: &str,
+                     context: RuleContext ) -> String {
+  let body = if definition.isToken() {
+    wrapNodeOutput( "fuse!( ", definition.expression, " )", context )
+  } else {
+    codeForNode( definition.expression, context )
   };
 
-  "rule!( ".to_string() + &inner_code + " );\n"
+  let mut output = rule_macro.to_string() + &definition.name + " <- " + &body;
+  match ( &definition.value_type, &definition.action ) {
+    ( &Some( ref value_type ), &Some( ref action ) ) => {
+      output = output + " => " + &valueFunctionName( &definition.name ) +
+               " -> " + value_type + " = " + action;
+    }
+    _ => ()
+  }
+  output + " );\n"
 }
 
 
-fn expressionOutput( node: &Node ) -> String {
+fn expressionOutput( node: &Node, context: RuleContext ) -> String {
   let children = node_children!( node );
   if children.len() > 1 {
-    wrapChildrenOutput( "or!( ", node , " )" )
+    wrapChildrenOutput( "or!( ", node , " )", context )
   } else {
-    codeForNodeContents( node )
+    codeForNodeContents( node, context )
   }
 }
 
 
-fn sequenceOutput( node: &Node ) -> String {
+fn skipOutput( skip: &str ) -> String {
+  format!( "ex!( {} )", skip )
+}
+
+
+// Returns true if the `Prefix` node never consumes input: it's a predicate or
+// a cut.
+fn consumesNothing( node: &Node ) -> bool {
+  analysis::hasChild( node, "AND" ) || analysis::hasChild( node, "NOT" ) ||
+    analysis::isCut( node )
+}
+
+
+fn sequenceOutput( node: &Node, context: RuleContext ) -> String {
   let children = node_children!( node );
   if children.len() > 1 {
     let mut output = String::from( "seq!( " );
     for i in 0 .. children.len() {
-      output.push_str( &codeForNode( &children[ i ] ) );
+      output.push_str( &codeForNode( &children[ i ], context ) );
       if i != children.len() -1 {
         output.push_str( ", " );
+
+        // A predicate doesn't consume input, so the skip rule goes before it
+        // instead of after it; that way the predicate looks at the same text
+        // as the element following it. The same goes for cuts.
+        match context.skip {
+          Some( skip ) if !consumesNothing( &children[ i ] ) => {
+            output.push_str( &skipOutput( skip ) );
+            output.push_str( ", " );
+          }
+          _ => ()
+        }
       }
     }
     output.push_str( " )" );
     output
   } else {
-    codeForNodeContents( node )
+    codeForNodeContents( node, context )
   }
 }
 
 
-fn suffixOutput( node: &Node ) -> String {
+// Returns the bounds of a repetition as the arguments of `repeat!` and
+// `AstCursor::repeat`.
+fn repeatBoundsOutput( min: usize, max: Option<usize> ) -> String {
+  match max {
+    Some( max ) => format!( "{}, Some( {} )", min, max ),
+    _ => format!( "{}, None", min )
+  }
+}
+
+
+fn suffixOutput( node: &Node, context: RuleContext ) -> String {
   let children = node_children!( node );
   if children.len() == 2 {
     let macro_name = match children[ 1 ].name {
       "QUESTION" => "opt",
       "STAR" => "star",
       "PLUS" => "plus",
+      "Repetition" => "repeat",
       _ => panic!( "Bad second child." )
     };
 
-    macro_name.to_string() + "!( " + &codeForNode( &children[ 0 ] ) + " )"
+    let element = codeForNode( &children[ 0 ], context );
+    if macro_name == "repeat" {
+      let ( min, max ) = analysis::repetitionBounds( &children[ 1 ] );
+      return match context.skip {
+        // Like below, the skip rule goes between the repetitions; the first one
+        // is matched on its own.
+        Some( skip ) if max != Some( 0 ) => {
+          let rest = format!( "repeat!( seq!( {}, {} ), {} )",
+                              skipOutput( skip ), element,
+                              repeatBoundsOutput( min.saturating_sub( 1 ),
+                                                  max.map( |max| max - 1 ) ) );
+          let repetitions = format!( "seq!( {}, {} )", element, rest );
+          if min == 0 {
+            format!( "opt!( {} )", repetitions )
+          } else {
+            repetitions
+          }
+        }
+        _ => format!( "repeat!( {}, {} )",
+                      element, repeatBoundsOutput( min, max ) )
+      };
+    }
+
+    match context.skip {
+      // The skip rule goes between the repetitions, but not before the first
+      // one or after the last one.
+      Some( skip ) if macro_name != "opt" => {
+        let repetitions = format!( "seq!( {}, star!( seq!( {}, {} ) ) )",
+                                   element, skipOutput( skip ), element );
+        if macro_name == "star" {
+          format!( "opt!( {} )", repetitions )
+        } else {
+          repetitions
+        }
+      }
+      _ => macro_name.to_string() + "!( " + &element + " )"
+    }
   } else {
-    codeForNodeContents( node )
+    codeForNodeContents( node, context )
   }
 }
 
 
-fn prefixOutput( node: &Node ) -> String {
+fn prefixOutput( node: &Node, context: RuleContext ) -> String {
   let children = node_children!( node );
-  if children.len() == 2 {
-    let macro_name = children[ 0 ].name.to_ascii_lowercase();
-    macro_name + "!( " + &codeForNode( &children[ 1 ] ) + " )"
-  } else {
-    codeForNodeContents( node )
+  let suffix = analysis::child( node, "Suffix" ).unwrap();
+  let mut inner = match children.iter().find( |child| {
+    child.name == "AND" || child.name == "NOT" || child.name == "FUSE"
+  } ) {
+    Some( operator ) => {
+      // Fused text is matched as written.
+      let suffix = if operator.name == "FUSE" {
+        codeForNode( suffix, RuleContext { skip: None, .. context } )
+      } else {
+        codeForNode( suffix, context )
+      };
+      operator.name.to_ascii_lowercase() + "!( " + &suffix + " )"
+    }
+    _ => codeForNode( suffix, context )
+  };
+
+  match analysis::child( node, "Recovery" ) {
+    Some( recovery ) => {
+      let recovery = codeForNode( &node_children!( recovery )[ 1 ], context );
+      inner = format!( "recover!( {}, {} )", inner, recovery );
+    }
+    _ => ()
+  }
+
+  match analysis::child( node, "Label" ) {
+    Some( label ) => {
+      let name = analysis::identifierName( &node_children!( label )[ 0 ] );
+      format!( "label!( {}, {} )", name, inner )
+    }
+    _ => inner
   }
 }
 
 
-fn primaryOutput( node: &Node ) -> String {
+fn primaryOutput( node: &Node, context: RuleContext ) -> String {
   let children = node_children!( node );
   if children.len() == 1 && children[ 0 ].name == "Identifier" {
-    wrapNodeOutput( "ex!( ", &children[ 0 ], " )" )
+    let name = analysis::identifierName( &children[ 0 ] );
+    match context.expansion.argument( &name, context.scope ) {
+      // A parameter is replaced by its argument, with the parameters in the
+      // argument resolved where it was written.
+      Some( argument ) => {
+        codeForNode( argument.expression,
+                     RuleContext { scope: argument.scope, .. context } )
+      }
+      _ => wrapNodeOutput( "ex!( ", &children[ 0 ], " )", context )
+    }
+  } else if children[ 0 ].name == "Call" {
+    let instance = context.expansion.instance( &children[ 0 ], context.scope );
+    format!( "ex!( {} )", instance.name )
   } else {
-    codeForNodeContents( node )
+    codeForNodeContents( node, context )
   }
 }
 
 
-fn literalOutput( node: &Node ) -> String {
-  stringBasedRule( node, "lit" )
+fn literalOutput( node: &Node, context: RuleContext ) -> String {
+  let ignore_case = analysis::hasChild( node, "IgnoreCase" );
+  if context.bytes {
+    format!( "{}!( b\"{}\" )",
+             if ignore_case { "iblit" } else { "blit" },
+             escapeBytes( &literalBytes( node ) ).replace( "\"", r#"\""# ) )
+  } else {
+    stringBasedRule( node, if ignore_case { "ilit" } else { "lit" } )
+  }
 }
 
 
-fn classOutput( node: &Node ) -> String {
-  stringBasedRule( node, "class" )
-    .replace( r"\\]", r"]" )
-    .replace( r"\\[", r"[" )
+fn classOutput( node: &Node, context: RuleContext ) -> String {
+  let macro_name = match ( context.bytes,
+                           analysis::hasChild( node, "IgnoreCase" ) ) {
+    ( false, false ) => "class",
+    ( false, true ) => "iclass",
+    ( true, false ) => "bclass",
+    ( true, true ) => "ibclass"
+  };
+  let caret = if analysis::hasChild( node, "Negation" ) { "^ " } else { "" };
+
+  // CharClass takes properties as `\p{Name}`, so literal backslashes in the
+  // class need to be escaped. In byte mode the bytes that aren't printable
+  // ASCII become `\xNN` escapes.
+  let contents = analysis::children( node ).iter()
+    .map( |child| match child.name {
+      "Range" if context.bytes => {
+        let text = codeForNodeContents( child, context );
+        escapeBytes( &unescape( text.as_bytes() ) )
+      }
+      "Range" => {
+        unescapeString( &codeForNodeContents( child, context ) )
+          .replace( r"\", r"\\" )
+      }
+      "Property" => {
+        format!( r"\p{{{}}}", analysis::propertyName( child ) )
+      }
+      _ => String::new()
+    } )
+    .collect::<Vec<String>>()
+    .concat();
+  format!( "{}!( {}\"{}\" )",
+           macro_name, caret, escapeToRustLiteral( &contents ) )
 }
 
 
@@ -185,9 +532,39 @@ fn escapeToRustLiteral( input: &str ) -> String {
 }
 
 
+// Escapes the bytes that aren't printable ASCII as `\xNN`, and backslashes as
+// `\\`. That's how CharClass takes bytes, and also the inside of a Rust byte
+// string literal once quotes are escaped.
+fn escapeBytes( input: &[u8] ) -> String {
+  input.iter()
+    .map( |&byte| match byte {
+      b'\\' => String::from( r"\\" ),
+      _ if byte >= b' ' && byte <= b'~' => ( byte as char ).to_string(),
+      _ => format!( r"\x{:02X}", byte )
+    } )
+    .collect::<Vec<String>>()
+    .concat()
+}
+
+
+// Returns the bytes a `Literal` node matches, with its escapes unescaped.
+fn literalBytes( node: &Node ) -> Vec<u8> {
+  match analysis::children( node ).first() {
+    Some( &Node { contents: Data( data ), .. } ) => {
+      unescape( &data[ 1 .. data.len() - 1 ] )
+    }
+    _ => panic!( "Literal node without data." )
+  }
+}
+
+
+// Returns the contents of a `Literal` node as the inside of a Rust string
+// literal.
+fn stringContent( node: &Node ) -> String {
+  escapeToRustLiteral( str::from_utf8( &literalBytes( node ) ).unwrap() )
+}
+
+
 fn stringBasedRule( node: &Node, rule_name: &str ) -> String {
-  let full = codeForNodeContents( node );
-  let content = escapeToRustLiteral(
-    &unescapeString( &full[ 1 .. full.len() - 1 ] ) );
-  format!( "{}!( \"{}\" )", rule_name, content )
+  format!( "{}!( \"{}\" )", rule_name, stringContent( node ) )
 }
@@ -0,0 +1,414 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashSet;
+use std::cmp;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use inlined_parser::{Node, parse};
+use super::analysis::{child, children, definitions, identifierName, references};
+use super::validation::{Diagnostic, Severity};
+use super::literalBytes;
+
+// A grammar file, or a grammar given as text when there's no path.
+struct Source {
+  path: Option<PathBuf>,
+  text: Vec<u8>
+}
+
+
+// A part of the merged text that comes from `source`, starting at `offset` in
+// it. Text copied from the source has its length in `len`; text inserted in
+// front of `offset`, like a namespace prefix, has a `len` of 0.
+struct Span {
+  start: usize,
+  source: usize,
+  offset: usize,
+  len: usize
+}
+
+
+/// The text of a grammar with the grammar files it imports merged in, which is
+/// what the generator works on. Keeps track of which file every part of the
+/// text comes from.
+pub struct Sources {
+  /// The merged grammar. The grammar that imports the others comes first, so
+  /// its first rule is the first rule of the merged grammar.
+  pub text: Vec<u8>,
+
+  sources: Vec<Source>,
+  spans: Vec<Span>
+}
+
+
+impl Sources {
+  /// Returns the sources of a grammar given as text that imports nothing.
+  #[cfg(test)]
+  pub fn new( input: &[u8] ) -> Sources {
+    Sources { text: input.to_vec(),
+              sources: vec!( Source { path: None, text: input.to_vec() } ),
+              spans: vec!( Span { start: 0,
+                                  source: 0,
+                                  offset: 0,
+                                  len: input.len() } ) }
+  }
+
+  /// Returns the paths of the grammar files read, in the order they were
+  /// merged.
+  pub fn files( &self ) -> Vec<PathBuf> {
+    self.sources.iter()
+      .filter_map( |source| source.path.clone() )
+      .collect()
+  }
+
+  /// Returns the path and the text of the source that `offset` in the merged
+  /// text comes from, and the offset in that text.
+  pub fn locate( &self, offset: usize ) -> ( Option<&Path>, &[u8], usize ) {
+    let index = self.spans.iter().rposition( |span| span.start <= offset );
+    let span = match index {
+      Some( index ) => &self.spans[ index ],
+      _ => return ( None, &[], 0 )
+    };
+    let source = &self.sources[ span.source ];
+    ( source.path.as_ref().map( |path| path.as_path() ),
+      &source.text,
+      span.offset + cmp::min( offset - span.start, span.len ) )
+  }
+
+  fn copy( &mut self, source: usize, text: &[u8], start: usize, end: usize ) {
+    if start < end {
+      self.spans.push( Span { start: self.text.len(),
+                              source: source,
+                              offset: start,
+                              len: end - start } );
+      self.text.extend( text[ start .. end ].iter().cloned() );
+    }
+  }
+
+  fn insert( &mut self, source: usize, offset: usize, inserted: &str ) {
+    if !inserted.is_empty() {
+      self.spans.push( Span { start: self.text.len(),
+                              source: source,
+                              offset: offset,
+                              len: 0 } );
+      self.text.extend( inserted.bytes() );
+    }
+  }
+}
+
+
+// A change to the text of a source: the bytes from `start` to `end` are
+// replaced with `replacement`.
+struct Rewrite {
+  start: usize,
+  end: usize,
+  replacement: String
+}
+
+
+struct Merger {
+  sources: Sources,
+
+  // The files merged so far, with the prefix of the names of their rules.
+  merged: HashSet<( PathBuf, String )>,
+
+  // The files being merged, the importing ones first.
+  importing: Vec<PathBuf>,
+
+  diagnostics: Vec<Diagnostic>
+}
+
+
+impl Merger {
+  fn report( &mut self, source: &Source, offset: usize, message: String ) {
+    let path = source.path.as_ref().map( |path| path.as_path() );
+    self.diagnostics.push(
+      Diagnostic::new( Severity::Error, path, &source.text, offset, message ) );
+  }
+
+  // Adds the rules of `source`, parsed into `grammar`, to the merged text with
+  // their names prefixed by `prefix`, then the files it imports.
+  fn add( &mut self, source: Source, grammar: &Node, prefix: &str ) {
+    let index = self.sources.sources.len();
+    if index > 0 {
+      self.sources.insert( index, 0, "\n" );
+    }
+
+    let imports: Vec<&Node> = children( grammar ).iter()
+      .filter( |child| child.name == "Import" )
+      .collect();
+    let namespaces: HashSet<String> = imports.iter()
+      .filter_map( |import| child( import, "Identifier" ) )
+      .map( identifierName )
+      .collect();
+    let mut rewrites: Vec<Rewrite> = imports.iter()
+      .map( |import| Rewrite { start: import.start,
+                               end: import.end,
+                               replacement: String::new() } )
+      .collect();
+    for definition in definitions( grammar ) {
+      let name = child( definition.node, "Identifier" ).unwrap();
+      if !prefix.is_empty() && !definition.name.contains( "::" ) {
+        rewrites.push( rename( name, prefix, &definition.name ) );
+      }
+
+      for reference in references( definition.expression ) {
+        let name = identifierName( reference );
+        if definition.parameters.contains( &name ) {
+          continue;
+        }
+        let qualified = match name.find( "::" ) {
+          Some( end ) if namespaces.contains( &name[ .. end ] ) => {
+            name.replace( "::", "_" )
+          }
+          Some( _ ) => continue,
+          _ if prefix.is_empty() => continue,
+          _ => name
+        };
+        rewrites.push( rename( reference, prefix, &qualified ) );
+      }
+    }
+    rewrites.sort_by( |a, b| a.start.cmp( &b.start ) );
+
+    let mut position = 0;
+    for rewrite in rewrites.iter() {
+      self.sources.copy( index, &source.text, position, rewrite.start );
+      self.sources.insert( index, rewrite.start, &rewrite.replacement );
+      position = rewrite.end;
+    }
+    self.sources.copy( index, &source.text, position, source.text.len() );
+
+    let directory = source.path.as_ref()
+      .and_then( |path| path.parent() )
+      .map_or( PathBuf::new(), |directory| directory.to_path_buf() );
+    self.sources.sources.push( Source { path: source.path.clone(),
+                                        text: source.text.clone() } );
+    for import in imports {
+      let literal = child( import, "Literal" ).unwrap();
+      let import_prefix = match child( import, "Identifier" ) {
+        Some( namespace ) => {
+          format!( "{}{}_", prefix, identifierName( namespace ) )
+        }
+        _ => prefix.to_string()
+      };
+      let relative = String::from_utf8_lossy( &literalBytes( literal ) )
+        .into_owned();
+      self.addImport( &source, import, directory.join( &relative ), &relative,
+                      import_prefix );
+    }
+  }
+
+  fn addImport( &mut self,
+                importer: &Source,
+                import: &Node,
+                path: PathBuf,
+                relative: &str,
+                prefix: String ) {
+    let canonical = fs::canonicalize( &path ).unwrap_or( path.clone() );
+    if self.merged.contains( &( canonical.clone(), prefix.clone() ) ) {
+      return;
+    }
+    if self.importing.contains( &canonical ) {
+      self.report( importer,
+                   import.start,
+                   format!( "`{}` imports this file under another namespace, \
+                             so the imports never end",
+                            relative ) );
+      return;
+    }
+
+    let mut text: Vec<u8> = vec!();
+    match File::open( &path ).and_then( |mut file| {
+      file.read_to_end( &mut text )
+    } ) {
+      Err( error ) => {
+        self.report( importer,
+                     import.start,
+                     format!( "can't read `{}`: {}", relative, error ) );
+        return;
+      }
+      _ => ()
+    }
+    let grammar = match parse( &text ) {
+      Ok( grammar ) => grammar,
+      Err( error ) => {
+        self.report( importer,
+                     import.start,
+                     format!( "`{}` isn't a valid grammar: {}",
+                              relative, error ) );
+        return;
+      }
+    };
+
+    self.merged.insert( ( canonical.clone(), prefix.clone() ) );
+    self.importing.push( canonical );
+    self.add( Source { path: Some( path ), text: text.clone() },
+              &grammar,
+              &prefix );
+    self.importing.pop();
+  }
+}
+
+
+// Replaces the name in the `Identifier` node with `name` behind `prefix`,
+// keeping the spacing after it.
+fn rename( identifier: &Node, prefix: &str, name: &str ) -> Rewrite {
+  Rewrite { start: identifier.start,
+            end: identifier.start + identifierName( identifier ).len(),
+            replacement: format!( "{}{}", prefix, name ) }
+}
+
+
+/// Merges the grammar in `input`, parsed into `grammar`, with the grammar
+/// files it imports, and the files those import. An imported file is looked up
+/// relative to the file importing it; `input` is the file at `path`, or text
+/// importing files relative to the current directory. The rules of a file
+/// imported with `as namespace` get `namespace_` in front of their names, which
+/// is what `namespace::Rule` refers to. Fails with the imports that can't be
+/// merged.
+pub fn merge( input: &[u8], grammar: &Node, path: Option<&Path> )
+    -> Result<Sources, Vec<Diagnostic>> {
+  let mut merger = Merger {
+    sources: Sources { text: vec!(), sources: vec!(), spans: vec!() },
+    merged: HashSet::new(),
+    importing: vec!(),
+    diagnostics: vec!()
+  };
+  if let Some( path ) = path {
+    let canonical = fs::canonicalize( path )
+      .unwrap_or( path.to_path_buf() );
+    merger.merged.insert( ( canonical.clone(), String::new() ) );
+    merger.importing.push( canonical );
+  }
+  merger.add( Source { path: path.map( |path| path.to_path_buf() ),
+                       text: input.to_vec() },
+              grammar,
+              "" );
+
+  if merger.diagnostics.is_empty() {
+    Ok( merger.sources )
+  } else {
+    Err( merger.diagnostics )
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::{Read, Write};
+  use std::path::Path;
+  use tempdir::TempDir;
+  use inlined_parser::parse;
+  use super::{Sources, merge};
+
+  fn writeFile( path: &Path, contents: &[u8] ) {
+    File::create( path ).and_then( |mut file| file.write_all( contents ) )
+      .unwrap();
+  }
+
+  fn mergeFile( path: &Path ) -> Result<Sources, Vec<String>> {
+    let mut input = vec!();
+    File::open( path ).and_then( |mut file| file.read_to_end( &mut input ) )
+      .unwrap();
+    merge( &input, &parse( &input ).unwrap(), Some( path ) )
+      .map_err( |diagnostics| {
+        diagnostics.iter().map( |diagnostic| diagnostic.to_string() ).collect()
+      } )
+  }
+
+  #[test]
+  fn merge_WithoutImports() {
+    let input = b"A <- B\nB <- 'b'";
+    let sources = merge( input, &parse( input ).unwrap(), None ).ok().unwrap();
+    assert_eq!( sources.text, input.to_vec() );
+    assert!( sources.files().is_empty() );
+    assert_eq!( sources.locate( 5 ), ( None, &input[..], 5 ) );
+  }
+
+
+  #[test]
+  fn merge_PrefixesNamespacedRules() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let root = temp_dir.path().join( "root.peg" );
+    writeFile( &root, b"import \"lexer.peg\" as lex\nimport 'common.peg'\n\
+                        A <- lex::Number Space lex::digits::Digit" );
+    writeFile( &temp_dir.path().join( "lexer.peg" ),
+               b"import \"digits.peg\" as digits\n\
+                 Number <- digits::Digit+ List(Number)\n\
+                 List(X) <- X*" );
+    writeFile( &temp_dir.path().join( "digits.peg" ), b"Digit <- [0-9]" );
+    writeFile( &temp_dir.path().join( "common.peg" ),
+               b"import 'root.peg'\nSpace <- ' '" );
+
+    let sources = mergeFile( &root ).ok().unwrap();
+    assert_eq!( String::from_utf8( sources.text.clone() ).unwrap(),
+                "A <- lex_Number Space lex_digits_Digit\n\
+                 lex_Number <- lex_digits_Digit+ lex_List(lex_Number)\n\
+                 lex_List(X) <- X*\n\
+                 lex_digits_Digit <- [0-9]\n\
+                 Space <- ' '" );
+    let files: Vec<String> = sources.files().iter()
+      .map( |file| file.file_name().unwrap().to_string_lossy().into_owned() )
+      .collect();
+    assert_eq!( files, vec!( "root.peg", "lexer.peg", "digits.peg",
+                             "common.peg" ) );
+  }
+
+
+  #[test]
+  fn locate_MapsBackToFiles() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let root = temp_dir.path().join( "root.peg" );
+    let lexer = temp_dir.path().join( "lexer.peg" );
+    writeFile( &root, b"import 'lexer.peg' as lex\nA <- lex::B" );
+    writeFile( &lexer, b"B <- 'b'\n  C <- B" );
+
+    let sources = mergeFile( &root ).ok().unwrap();
+    let text = String::from_utf8( sources.text.clone() ).unwrap();
+    assert_eq!( text, "A <- lex_B\nlex_B <- 'b'\n  lex_C <- lex_B" );
+
+    let ( file, _, offset ) = sources.locate( text.find( "lex_C" ).unwrap() );
+    assert_eq!( ( file, offset ), ( Some( lexer.as_path() ), 11 ) );
+    let ( file, _, offset ) = sources.locate( text.find( "'b'" ).unwrap() );
+    assert_eq!( ( file, offset ), ( Some( lexer.as_path() ), 5 ) );
+    let ( file, _, offset ) = sources.locate( 5 );
+    assert_eq!( ( file, offset ), ( Some( root.as_path() ), 31 ) );
+  }
+
+
+  #[test]
+  fn merge_BadImports() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let root = temp_dir.path().join( "root.peg" );
+    writeFile( &root, b"import 'missing.peg'\nimport 'bad.peg'\n\
+                        import 'cycle.peg' as c\nA <- 'a'" );
+    writeFile( &temp_dir.path().join( "bad.peg" ), b"B <- (" );
+    writeFile( &temp_dir.path().join( "cycle.peg" ), b"import 'root.peg'\n\
+                                                       C <- 'c'" );
+
+    let messages = mergeFile( &root ).err().unwrap();
+    assert_eq!( messages.len(), 3 );
+    assert!( messages[ 0 ].ends_with( "root.peg: line 1, column 1: error: \
+                                       can't read `missing.peg`: No such \
+                                       file or directory (os error 2)" ) );
+    assert!( messages[ 1 ].contains( "root.peg: line 2, column 1: error: \
+                                      `bad.peg` isn't a valid grammar: \
+                                      line 1, column 7: expected one of " ) );
+    assert!( messages[ 2 ].ends_with( "cycle.peg: line 1, column 1: error: \
+                                       `root.peg` imports this file under \
+                                       another namespace, so the imports \
+                                       never end" ) );
+  }
+}
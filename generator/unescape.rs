@@ -159,6 +159,9 @@ fn addEscapedByte( mut input: Vec<u8>, byte: u8 ) -> Vec<u8> {
     b'\'' => Some( b'\''   ),
     b'"'  => Some( b'"'    ),
     b'\\' => Some( b'\\'   ),
+    b'['  => Some( b'['    ),
+    b']'  => Some( b']'    ),
+    b'^'  => Some( b'^'    ),
     _     => None
   };
 
@@ -246,12 +249,16 @@ mod tests {
     assert_eq!( unescape( b"\\'"  ), vecBytes( "\'" )   );
     assert_eq!( unescape( b"\\\"" ), vecBytes( "\"" )   );
     assert_eq!( unescape( b"\\\\" ), vecBytes( "\\" )   );
+    assert_eq!( unescape( b"\\["  ), vecBytes( "[" )    );
+    assert_eq!( unescape( b"\\]"  ), vecBytes( "]" )    );
+    assert_eq!( unescape( b"\\^"  ), vecBytes( "^" )    );
   }
 
   #[test]
   fn unescape_MultipleSingleCharEscapeCodes() {
     assert_eq!( unescape( b"\\r\\n" ), vecBytes( "\r\n" ) );
     assert_eq!( unescape( b"\\\\\\\\" ), vecBytes( "\\\\" ) );
+    assert_eq!( unescape( b"\\\\^" ), vecBytes( "\\^" ) );
   }
 
   #[test]
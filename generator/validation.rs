@@ -0,0 +1,1066 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str;
+use inlined_parser::{Node, unicodeProperty};
+use super::analysis::{Definition, child, children, definitions, hasChild,
+                      identifierName, isNullableIn, nullableRules,
+                      nullableRulesIn, leftRecursion, references, skipRule,
+                      isCut, propertyName, matchedString, repetitionBounds,
+                      callName, callArguments, expand, Scope};
+use super::unescape::unescape;
+use super::sources::Sources;
+use super::{Options, isByteMode, isPublic, literalBytes, startRule,
+            valueFunctionName};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+  /// The grammar can't be turned into a working parser.
+  Error,
+
+  /// The grammar works, but probably not the way the author intended.
+  Warning
+}
+
+
+/// A problem found in a grammar by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+
+  /// The grammar file the problem is in, or nothing if the grammar was given
+  /// as text.
+  pub file: Option<PathBuf>,
+
+  /// The byte offset in the grammar file the problem is at.
+  pub offset: usize,
+
+  /// The 1-based line of `offset`.
+  pub line: usize,
+
+  /// The 1-based column of `offset`, counted in chars.
+  pub column: usize,
+
+  pub message: String
+}
+
+
+impl Diagnostic {
+  /// Returns a diagnostic for the problem at `offset` in `input`, the text of
+  /// the grammar file at `file`.
+  pub fn new( severity: Severity,
+              file: Option<&Path>,
+              input: &[u8],
+              offset: usize,
+              message: String ) -> Diagnostic {
+    let ( line, column ) = lineAndColumn( input, offset );
+    Diagnostic { severity: severity,
+                 file: file.map( |file| file.to_path_buf() ),
+                 offset: offset,
+                 line: line,
+                 column: column,
+                 message: message }
+  }
+}
+
+
+impl fmt::Display for Diagnostic {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    let severity = match self.severity {
+      Severity::Error => "error",
+      Severity::Warning => "warning"
+    };
+    if let Some( ref file ) = self.file {
+      try!( write!( formatter, "{}: ", file.display() ) );
+    }
+    write!( formatter, "line {}, column {}: {}: {}",
+            self.line, self.column, severity, self.message )
+  }
+}
+
+
+fn lineAndColumn( input: &[u8], offset: usize ) -> ( usize, usize ) {
+  let before = &input[ .. offset ];
+  let line_start = before.iter().rposition( |byte| *byte == b'\n' )
+    .map_or( 0, |index| index + 1 );
+  let line = before.iter().filter( |byte| **byte == b'\n' ).count() + 1;
+  let column = String::from_utf8_lossy( &before[ line_start .. ] )
+    .chars().count() + 1;
+  ( line, column )
+}
+
+
+struct Validator<'i> {
+  sources: &'i Sources,
+  options: &'i Options,
+
+  // The diagnostics with their offsets in the merged grammar.
+  diagnostics: Vec<( usize, Diagnostic )>
+}
+
+
+impl<'i> Validator<'i> {
+  fn report( &mut self, severity: Severity, offset: usize, message: String ) {
+    let diagnostic = {
+      let ( file, input, file_offset ) = self.sources.locate( offset );
+      Diagnostic::new( severity, file, input, file_offset, message )
+    };
+    self.diagnostics.push( ( offset, diagnostic ) );
+  }
+
+  fn hasErrors( &self ) -> bool {
+    self.diagnostics.iter()
+      .any( |&( _, ref diagnostic )| diagnostic.severity == Severity::Error )
+  }
+
+  fn checkDuplicates( &mut self, definitions: &[Definition] ) {
+    let mut first_definitions: HashMap<&str, usize> = HashMap::new();
+    for definition in definitions {
+      match first_definitions.get( &definition.name[..] ) {
+        Some( &offset ) => {
+          let place = {
+            let ( file, input, file_offset ) = self.sources.locate( offset );
+            let ( line, _ ) = lineAndColumn( input, file_offset );
+            let ( this_file, _, _ ) =
+              self.sources.locate( definition.node.start );
+            match file {
+              Some( file ) if this_file != Some( file ) => {
+                format!( "on line {} of `{}`", line, file.display() )
+              }
+              _ => format!( "on line {}", line )
+            }
+          };
+          self.report( Severity::Error,
+                       definition.node.start,
+                       format!( "rule `{}` is already defined {}",
+                                definition.name, place ) );
+          continue;
+        }
+        _ => ()
+      }
+      first_definitions.insert( &definition.name, definition.node.start );
+    }
+  }
+
+  fn checkReferences( &mut self, definitions: &[Definition] ) {
+    let defined: HashSet<&str> =
+      definitions.iter().map( |definition| &definition.name[..] ).collect();
+    for definition in definitions {
+      for reference in references( definition.expression ) {
+        let name = identifierName( reference );
+        if !defined.contains( &name[..] ) &&
+           !definition.parameters.contains( &name ) {
+          self.report( Severity::Error,
+                       reference.start,
+                       format!( "rule `{}` is not defined", name ) );
+        }
+      }
+    }
+  }
+
+  fn checkReachability( &mut self, definitions: &[Definition] ) {
+    // The skip rule is used between the elements of the other rules even
+    // though no rule refers to it.
+    let mut reachable: HashSet<String> = HashSet::new();
+    let mut stack: Vec<String> = definitions.iter()
+      .filter( |definition| isPublic( definition, self.options ) )
+      .map( |definition| definition.name.clone() )
+      .chain( Some( startRule( definitions, self.options ) ).into_iter() )
+      .chain( skipRule( definitions ).into_iter() )
+      .collect();
+    while let Some( name ) = stack.pop() {
+      if !reachable.insert( name.clone() ) {
+        continue;
+      }
+      for definition in definitions.iter().filter( |x| x.name == name ) {
+        for reference in references( definition.expression ) {
+          stack.push( identifierName( reference ) );
+        }
+      }
+    }
+
+    for definition in definitions {
+      if !reachable.contains( &definition.name ) {
+        self.report( Severity::Warning,
+                     definition.node.start,
+                     format!( "rule `{}` is never used", definition.name ) );
+      }
+    }
+  }
+
+  fn checkLeftRecursion( &mut self, definitions: &[Definition] ) {
+    let left_recursion = leftRecursion( definitions );
+    let consequence = if self.options.memoize {
+      "; rules on its cycle can't be memoized"
+    } else {
+      ""
+    };
+    for definition in definitions {
+      if left_recursion.involved.contains( &definition.name ) {
+        self.report( Severity::Warning,
+                     definition.node.start,
+                     format!( "rule `{}` is left-recursive{}",
+                              definition.name, consequence ) );
+      }
+    }
+  }
+
+  // Only references can be qualified, like `lexer::Number`; the names of
+  // rules, parameters and labels can't. Annotations are checked on their own.
+  fn checkNames( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      let parameters = child( definition.node, "Parameters" )
+        .map_or( &[][..], children );
+      let labels = definition.expression.preOrder()
+        .filter( |node| node.name == "Label" )
+        .map( |label| &children( label )[ 0 ] );
+      let names = child( definition.node, "Identifier" ).into_iter()
+        .chain( parameters.iter()
+                  .filter( |child| child.name == "Identifier" ) )
+        .chain( labels );
+      for identifier in names {
+        let name = identifierName( identifier );
+        if name.contains( "::" ) {
+          self.report( Severity::Error,
+                       identifier.start,
+                       format!( "the name `{}` can't have a namespace; only \
+                                 references to rules can",
+                                name ) );
+        }
+      }
+    }
+  }
+
+  fn checkParameters( &mut self, definitions: &[Definition] ) {
+    let defined: HashSet<&str> =
+      definitions.iter().map( |definition| &definition.name[..] ).collect();
+    let start_rule = startRule( definitions, self.options );
+    for definition in definitions {
+      let parameters = match child( definition.node, "Parameters" ) {
+        Some( parameters ) => parameters,
+        _ => continue
+      };
+      if definition.name == start_rule {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is where parsing starts, so it can't \
+                               have parameters",
+                              definition.name ) );
+      }
+      if definition.isAnnotated( "public" ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is `@public`, so it can't have \
+                               parameters",
+                              definition.name ) );
+      }
+      if definition.isAnnotated( "skip" ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "skip rule `{}` can't have parameters",
+                              definition.name ) );
+      }
+
+      let mut seen: HashSet<String> = HashSet::new();
+      for parameter in children( parameters ).iter()
+                         .filter( |child| child.name == "Identifier" ) {
+        let name = identifierName( parameter );
+        if !seen.insert( name.clone() ) {
+          self.report( Severity::Error,
+                       parameter.start,
+                       format!( "rule `{}` has more than one parameter named \
+                                 `{}`",
+                                definition.name, name ) );
+        } else if defined.contains( &name[..] ) {
+          self.report( Severity::Error,
+                       parameter.start,
+                       format!( "parameter `{}` of rule `{}` has the name of \
+                                 a rule",
+                                name, definition.name ) );
+        }
+      }
+    }
+  }
+
+  fn checkArguments( &mut self, definitions: &[Definition] ) {
+    let by_name: HashMap<&str, &Definition> = definitions.iter()
+      .map( |definition| ( &definition.name[..], definition ) )
+      .collect();
+    for definition in definitions {
+      for primary in definition.expression.preOrder()
+                       .filter( |node| node.name == "Primary" ) {
+        let first = &children( primary )[ 0 ];
+        let ( name, num_arguments ) = match first.name {
+          "Identifier" => ( identifierName( first ), 0 ),
+          "Call" => ( callName( first ), callArguments( first ).len() ),
+          _ => continue
+        };
+        if definition.parameters.contains( &name ) {
+          if num_arguments > 0 {
+            self.report( Severity::Error,
+                         first.start,
+                         format!( "parameter `{}` can't take arguments",
+                                  name ) );
+          }
+          continue;
+        }
+
+        let num_parameters = match by_name.get( &name[..] ) {
+          Some( rule ) => rule.parameters.len(),
+          _ => continue
+        };
+        let message = if num_parameters == num_arguments {
+          continue
+        } else if num_parameters == 0 {
+          format!( "rule `{}` doesn't take arguments", name )
+        } else if num_arguments == 0 {
+          format!( "rule `{}` takes {}, but is used without any",
+                   name, argumentCount( num_parameters ) )
+        } else {
+          format!( "rule `{}` takes {}, but was given {}",
+                   name, argumentCount( num_parameters ), num_arguments )
+        };
+        self.report( Severity::Error, first.start, message );
+      }
+    }
+  }
+
+  fn checkExpansion( &mut self, definitions: &[Definition] ) {
+    if let Err( call ) = expand( definitions ) {
+      self.report( Severity::Error,
+                   call.start,
+                   format!( "expanding `{}` never ends; rule `{}` keeps \
+                             calling itself with new arguments",
+                            matchedString( call ), callName( call ) ) );
+    }
+  }
+
+  fn checkStartRule( &mut self, definitions: &[Definition] ) {
+    let start_rule = startRule( definitions, self.options );
+    if !definitions.iter().any( |definition| definition.name == start_rule ) {
+      self.report( Severity::Error,
+                   0,
+                   format!( "the start rule `{}` is not defined",
+                            start_rule ) );
+    }
+  }
+
+  fn checkActions( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      let message = match ( &definition.value_type, &definition.action ) {
+        ( &Some( _ ), &None ) => "has a return type but no action",
+        ( &None, &Some( _ ) ) => "has an action but no return type",
+        _ => continue
+      };
+      self.report( Severity::Error,
+                   definition.node.start,
+                   format!( "rule `{}` {}", definition.name, message ) );
+    }
+  }
+
+  // The `parse_<rule>` functions of public rules are next to the functions
+  // every parser has, so they can't have the name of one of those.
+  fn checkEntryPoints( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      let entry_point = format!( "parse_{}", definition.name );
+      if isPublic( definition, self.options ) &&
+         PARSER_FUNCTIONS.contains( &&entry_point[..] ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is public, but its `{}` function \
+                               would clash with the one every parser has",
+                              definition.name, entry_point ) );
+      }
+    }
+  }
+
+  // A rule with a value type gets a function returning its value next to the
+  // functions of the rules, so no rule can have the name of that function.
+  fn checkValueFunctions( &mut self, definitions: &[Definition] ) {
+    let value_functions: HashMap<String, &str> = definitions.iter()
+      .filter( |definition| definition.value_type.is_some() )
+      .map( |definition| ( valueFunctionName( &definition.name ),
+                           &definition.name[..] ) )
+      .collect();
+    for definition in definitions {
+      if let Some( rule ) = value_functions.get( &definition.name ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` has the name of the function that \
+                               returns the value of rule `{}`",
+                              definition.name, rule ) );
+      }
+    }
+  }
+
+  fn checkAnnotations( &mut self, definitions: &[Definition] ) {
+    let inline_rules: HashMap<&str, &Definition> = definitions.iter()
+      .filter( |definition| definition.isAnnotated( "inline" ) )
+      .map( |definition| ( &definition.name[..], definition ) )
+      .collect();
+
+    let start_rule = startRule( definitions, self.options );
+    for definition in definitions {
+      for annotation in definition.annotations.iter() {
+        if !ANNOTATIONS.contains( &&annotation[..] ) {
+          self.report( Severity::Error,
+                       definition.node.start,
+                       format!( "unknown annotation `@{}`", annotation ) );
+        }
+      }
+
+      let modes: Vec<&str> = [ "silent", "inline", "token", "skip" ].iter()
+        .cloned()
+        .filter( |mode| definition.isAnnotated( mode ) )
+        .collect();
+      if modes.len() > 1 {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` can't be both `@{}` and `@{}`",
+                              definition.name, modes[ 0 ], modes[ 1 ] ) );
+        continue;
+      }
+
+      let mode = match modes.first() {
+        Some( &mode ) if mode != "token" => mode,
+        _ => continue
+      };
+      if definition.name == start_rule {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is where parsing starts, so it can't \
+                               be `@{}`",
+                              definition.name, mode ) );
+      }
+      if definition.isAnnotated( "public" ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is `@public`, so it can't be `@{}`",
+                              definition.name, mode ) );
+      }
+      if definition.action.is_some() {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is `@{}`, so it has no node for its \
+                               action",
+                              definition.name, mode ) );
+      }
+      if mode == "inline" && inlinesItself( definition, &inline_rules ) {
+        self.report( Severity::Error,
+                     definition.node.start,
+                     format!( "rule `{}` is `@inline` but refers to itself",
+                              definition.name ) );
+      }
+    }
+  }
+
+  fn checkSkipRule( &mut self, definitions: &[Definition] ) {
+    let nullable_rules = nullableRules( definitions );
+    let mut skip_rules = definitions.iter()
+      .filter( |definition| definition.isAnnotated( "skip" ) );
+    let skip_rule = match skip_rules.next() {
+      Some( skip_rule ) => skip_rule,
+      _ => return
+    };
+
+    for definition in skip_rules {
+      self.report( Severity::Error,
+                   definition.node.start,
+                   format!( "rule `{}` is `@skip`, but `{}` already is; a \
+                             grammar can only have one skip rule",
+                            definition.name, skip_rule.name ) );
+    }
+    if !nullable_rules.contains( &skip_rule.name ) {
+      self.report( Severity::Warning,
+                   skip_rule.node.start,
+                   format!( "skip rule `{}` can't match the empty string, so \
+                             it will be required between all elements",
+                            skip_rule.name ) );
+    }
+  }
+
+  fn checkCuts( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      let mut cuts = vec!();
+      cutsOutsideChoices( definition.expression, &mut cuts );
+      for cut in cuts {
+        self.report( Severity::Warning,
+                     cut.start,
+                     format!( "the cut in rule `{}` isn't inside a choice, so \
+                               it has no effect",
+                              definition.name ) );
+      }
+    }
+  }
+
+  fn checkUnicodeProperties( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      for property in definition.expression.preOrder()
+                        .filter( |node| node.name == "Property" ) {
+        let name = propertyName( property );
+        if unicodeProperty( &name ).is_none() {
+          self.report( Severity::Error,
+                       property.start,
+                       format!( "unknown Unicode property `{}`", name ) );
+        }
+      }
+    }
+  }
+
+  fn checkByteMode( &mut self, definitions: &[Definition] ) {
+    for definition in definitions {
+      let bytes = isByteMode( definition, self.options );
+      for node in definition.expression.preOrder() {
+        let is_utf8 = match node.name {
+          "Literal" => str::from_utf8( &literalBytes( node ) ).is_ok(),
+          "Range" => {
+            str::from_utf8( &unescape( matchedString( node ).as_bytes() ) )
+              .is_ok()
+          }
+          "Property" if bytes => {
+            self.report( Severity::Error,
+                         node.start,
+                         format!( "rule `{}` is in byte mode, where Unicode \
+                                   properties never match",
+                                  definition.name ) );
+            continue;
+          }
+          _ => continue
+        };
+
+        if !bytes && !is_utf8 {
+          self.report( Severity::Error,
+                       node.start,
+                       format!( "the escapes in rule `{}` aren't valid UTF-8; \
+                                 bytes need byte mode (`@bytes`)",
+                                definition.name ) );
+        }
+      }
+    }
+  }
+
+  // Returns false if the bounds of the `Repetition` node are unusable.
+  fn checkRepetitionBounds( &mut self, repetition: &Node ) -> bool {
+    for count in children( repetition ).iter()
+                   .filter( |child| child.name == "Count" ) {
+      if matchedString( count ).parse::<usize>().is_err() {
+        self.report( Severity::Error,
+                     count.start,
+                     format!( "the count `{}` is too large",
+                              matchedString( count ) ) );
+        return false;
+      }
+    }
+
+    match repetitionBounds( repetition ) {
+      ( min, Some( max ) ) if min > max => {
+        self.report( Severity::Error,
+                     repetition.start,
+                     format!( "the repetition `{}` has a minimum above its \
+                               maximum",
+                              matchedString( repetition ) ) );
+        false
+      }
+      _ => true
+    }
+  }
+
+  fn checkRepetitions( &mut self, definitions: &[Definition] ) {
+    // Suffixes with unusable bounds are only reported for those.
+    let mut reported: HashSet<usize> = HashSet::new();
+    for definition in definitions {
+      for suffix in definition.expression.preOrder()
+                      .filter( |node| node.name == "Suffix" ) {
+        if let Some( repetition ) = child( suffix, "Repetition" ) {
+          if !self.checkRepetitionBounds( repetition ) {
+            reported.insert( suffix.start );
+          }
+        }
+      }
+    }
+
+    // Whether a repeated parameter can match the empty string depends on the
+    // argument, so the bodies of instances are checked with their arguments
+    // in place. The bodies of parameterized rules are checked as written
+    // first, so that what's wrong with every instance is reported plainly.
+    let expansion = expand( definitions ).ok();
+    let mut rules: Vec<( Definition, Scope )> = definitions.iter()
+      .filter( |definition| definition.isParameterized() )
+      .map( |definition| ( definition.clone(), None ) )
+      .collect();
+    match expansion {
+      Some( ref expansion ) => rules.extend( expansion.rules( definitions ) ),
+      _ => rules.extend( definitions.iter()
+                           .filter( |definition| {
+                             !definition.isParameterized()
+                           } )
+                           .map( |definition| ( definition.clone(), None ) ) )
+    }
+    let nullable_rules = nullableRulesIn( &rules, expansion.as_ref() );
+
+    for &( ref definition, scope ) in rules.iter() {
+      for suffix in definition.expression.preOrder()
+                      .filter( |node| node.name == "Suffix" ) {
+        let operator = match unboundedOperator( suffix ) {
+          Some( operator ) => operator,
+          _ => continue
+        };
+        if reported.contains( &suffix.start ) ||
+           !isNullableIn( &children( suffix )[ 0 ], scope, expansion.as_ref(),
+                          &nullable_rules ) {
+          continue;
+        }
+
+        let call = match ( scope, expansion.as_ref() ) {
+          ( Some( index ), Some( expansion ) ) => {
+            format!( " when called as `{}`", expansion.instances[ index ].call )
+          }
+          _ => String::new()
+        };
+        reported.insert( suffix.start );
+        self.report( Severity::Error,
+                     suffix.start,
+                     format!( "the expression under `{}` can match the \
+                               empty string{}, so it would repeat forever",
+                              operator, call ) );
+      }
+    }
+  }
+}
+
+
+const ANNOTATIONS: &'static [&'static str] = &[ "silent", "inline", "token",
+                                                 "skip", "bytes", "public" ];
+
+// The functions of generated parsers named like the `parse_<rule>` functions.
+const PARSER_FUNCTIONS: &'static [&'static str] = &[ "parse_prefix" ];
+
+
+// Returns the operator that repeats the `Suffix` node with no upper bound, if
+// there is one.
+fn unboundedOperator( suffix: &Node ) -> Option<String> {
+  if hasChild( suffix, "STAR" ) {
+    Some( "*".to_string() )
+  } else if hasChild( suffix, "PLUS" ) {
+    Some( "+".to_string() )
+  } else {
+    child( suffix, "Repetition" )
+      .and_then( |repetition| match repetitionBounds( repetition ) {
+        ( _, None ) => Some( matchedString( repetition ) ),
+        _ => None
+      } )
+  }
+}
+
+
+fn argumentCount( count: usize ) -> String {
+  if count == 1 {
+    "1 argument".to_string()
+  } else {
+    format!( "{} arguments", count )
+  }
+}
+
+
+// Returns true if the `@inline` rule `definition` refers to itself, directly or
+// through other `@inline` rules.
+fn inlinesItself( definition: &Definition,
+                  inline_rules: &HashMap<&str, &Definition> ) -> bool {
+  let mut visited: HashSet<String> = HashSet::new();
+  let mut stack = vec!( definition );
+  while let Some( current ) = stack.pop() {
+    for reference in references( current.expression ) {
+      let name = identifierName( reference );
+      if name == definition.name {
+        return true;
+      }
+      match inline_rules.get( &name[..] ) {
+        Some( next ) if visited.insert( name.clone() ) => stack.push( next ),
+        _ => ()
+      }
+    }
+  }
+  false
+}
+
+
+// Adds the cuts in `node`, a part of a rule body, that aren't inside a choice
+// of the rule to `cuts`.
+fn cutsOutsideChoices<'n, 'a>( node: &'n Node<'a>,
+                               cuts: &mut Vec<&'n Node<'a>> ) {
+  if node.name == "Expression" &&
+     children( node ).iter().any( |child| child.name == "SLASH" ) {
+    return;
+  }
+  if node.name == "Prefix" && isCut( node ) {
+    cuts.push( node );
+    return;
+  }
+  for child in children( node ) {
+    cutsOutsideChoices( child, cuts );
+  }
+}
+
+
+/// Checks the `Grammar` node parsed from `input` for problems that would make
+/// the generated code fail to compile or the generated parser misbehave. The
+/// returned diagnostics are sorted by their position in the grammar.
+pub fn validate( sources: &Sources, grammar: &Node, options: &Options )
+    -> Vec<Diagnostic> {
+  let definitions = definitions( grammar );
+  let mut validator = Validator { sources: sources,
+                                  options: options,
+                                  diagnostics: vec!() };
+  validator.checkDuplicates( &definitions );
+  validator.checkStartRule( &definitions );
+  validator.checkNames( &definitions );
+  validator.checkReferences( &definitions );
+  validator.checkReachability( &definitions );
+  validator.checkLeftRecursion( &definitions );
+  validator.checkParameters( &definitions );
+  validator.checkArguments( &definitions );
+  validator.checkActions( &definitions );
+  validator.checkValueFunctions( &definitions );
+  validator.checkEntryPoints( &definitions );
+  validator.checkAnnotations( &definitions );
+  validator.checkSkipRule( &definitions );
+  validator.checkCuts( &definitions );
+  validator.checkUnicodeProperties( &definitions );
+  validator.checkByteMode( &definitions );
+  validator.checkRepetitions( &definitions );
+
+  // Expanding is only meaningful once every call has the right arguments.
+  if !validator.hasErrors() {
+    validator.checkExpansion( &definitions );
+  }
+
+  let mut diagnostics = validator.diagnostics;
+  diagnostics.sort_by( |a, b| a.0.cmp( &b.0 ) );
+  diagnostics.into_iter().map( |( _, diagnostic )| diagnostic ).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use inlined_parser::parse;
+  use super::{validate, Severity, Sources};
+  use generator::Options;
+
+  fn messagesWithOptions( input: &[u8], options: &Options ) -> Vec<String> {
+    validate( &Sources::new( input ), &parse( input ).unwrap(), options ).iter()
+      .map( |diagnostic| diagnostic.to_string() ).collect()
+  }
+
+  fn messages( input: &[u8] ) -> Vec<String> {
+    messagesWithOptions( input, &Options::new() )
+  }
+
+  #[test]
+  fn validate_ValidGrammar() {
+    assert!( messages( b"A <- B+ 'c'\nB <- [b] / 'x' A?" ).is_empty() );
+  }
+
+
+  #[test]
+  fn validate_UndefinedRule() {
+    assert_eq!( messages( b"A <- 'a' B\n  / C" ),
+                vec!( "line 1, column 10: error: rule `B` is not defined",
+                      "line 2, column 5: error: rule `C` is not defined" ) );
+  }
+
+
+  #[test]
+  fn validate_DuplicateRule() {
+    assert_eq!( messages( b"A <- B\nB <- 'b'\nB <- 'c'" ),
+                vec!( "line 3, column 1: error: rule `B` is already defined \
+                       on line 2" ) );
+  }
+
+
+  #[test]
+  fn validate_EntryPoints() {
+    assert_eq!( messages( b"A <- 'a'\n@public B <- C\nC <- 'c'\n\
+                            @public @silent D <- 'd'\n\
+                            @public E(X) <- X" ),
+                vec!( "line 4, column 1: error: rule `D` is `@public`, so it \
+                       can't be `@silent`",
+                      "line 5, column 1: error: rule `E` is `@public`, so it \
+                       can't have parameters" ) );
+
+    let options = Options { start: Some( "B".to_string() ),
+                            .. Options::new() };
+    assert_eq!( messagesWithOptions( b"A <- 'a'\n@inline B <- 'b'", &options ),
+                vec!( "line 1, column 1: warning: rule `A` is never used",
+                      "line 2, column 1: error: rule `B` is where parsing \
+                       starts, so it can't be `@inline`" ) );
+
+    let options = Options { start: Some( "Z".to_string() ),
+                            .. Options::new() };
+    assert_eq!( messagesWithOptions( b"A <- 'a'", &options ),
+                vec!( "line 1, column 1: error: the start rule `Z` is not \
+                       defined",
+                      "line 1, column 1: warning: rule `A` is never used" ) );
+  }
+
+
+  #[test]
+  fn validate_EntryPointName() {
+    assert_eq!( messages( b"A <- prefix\n@public prefix <- 'p'" ),
+                vec!( "line 2, column 1: error: rule `prefix` is public, but \
+                       its `parse_prefix` function would clash with the one \
+                       every parser has" ) );
+    assert!( messages( b"A <- prefix\nprefix <- 'p'" ).is_empty() );
+
+    let options = Options { public: true, .. Options::new() };
+    assert_eq!( messagesWithOptions( b"A <- prefix\nprefix <- 'p'", &options )
+                  .len(),
+                1 );
+  }
+
+
+  #[test]
+  fn validate_QualifiedNames() {
+    assert_eq!( messages( b"A <- x::y:B z::C\n@a::b B <- 'b'\n\
+                            D(p::q) <- 'd'\nl::E <- 'e'" ),
+                vec!( "line 1, column 6: error: the name `x::y` can't have a \
+                       namespace; only references to rules can",
+                      "line 1, column 13: error: rule `z::C` is not defined",
+                      "line 2, column 1: error: unknown annotation `@a::b`",
+                      "line 3, column 1: warning: rule `D` is never used",
+                      "line 3, column 3: error: the name `p::q` can't have a \
+                       namespace; only references to rules can",
+                      "line 4, column 1: error: the name `l::E` can't have a \
+                       namespace; only references to rules can",
+                      "line 4, column 1: warning: rule `l::E` is never \
+                       used" ) );
+  }
+
+
+  #[test]
+  fn validate_UnusedRule() {
+    assert_eq!( messages( b"A <- 'a'\nB <- A" ),
+                vec!( "line 2, column 1: warning: rule `B` is never used" ) );
+  }
+
+
+  #[test]
+  fn validate_LeftRecursion() {
+    let input = b"A <- A 'a' / 'a'";
+    let diagnostics = validate( &Sources::new( input ),
+                                &parse( input ).unwrap(),
+                                &Options::new() );
+    assert_eq!( diagnostics.len(), 1 );
+    assert_eq!( diagnostics[ 0 ].severity, Severity::Warning );
+    assert_eq!( diagnostics[ 0 ].line, 1 );
+    assert_eq!( diagnostics[ 0 ].column, 1 );
+    assert_eq!( diagnostics[ 0 ].to_string(),
+                "line 1, column 1: warning: rule `A` is left-recursive" );
+
+    let options = Options { memoize: true, .. Options::new() };
+    assert_eq!( messagesWithOptions( input, &options ),
+                vec!( "line 1, column 1: warning: rule `A` is left-recursive; \
+                       rules on its cycle can't be memoized" ) );
+  }
+
+
+  #[test]
+  fn validate_ActionWithoutType() {
+    assert_eq!( messages( b"A -> u8 <- B\nB <- 'b' { |n| 1 }" ),
+                vec!( "line 1, column 1: error: rule `A` has a return type \
+                       but no action",
+                      "line 2, column 1: error: rule `B` has an action but \
+                       no return type" ) );
+  }
+
+
+  #[test]
+  fn validate_ValueFunctionName() {
+    assert_eq!( messages( b"A -> u8 <- A_value { |_| 1 }\nA_value <- 'a'" ),
+                vec!( "line 2, column 1: error: rule `A_value` has the name \
+                       of the function that returns the value of rule \
+                       `A`" ) );
+    assert!( messages( b"A <- A_value\nA_value <- 'a'" ).is_empty() );
+  }
+
+
+  #[test]
+  fn validate_Annotations() {
+    assert_eq!( messages( b"@inline A <- B C D E\n@silent @token B <- 'b'\n\
+                            @fast C <- 'c'\n@inline D <- 'd' D?\n\
+                            E -> u8 <- 'e' { |_| 1 }" ),
+                vec!( "line 1, column 1: error: rule `A` is where parsing \
+                       starts, so it can't be `@inline`",
+                      "line 2, column 1: error: rule `B` can't be both \
+                       `@silent` and `@token`",
+                      "line 3, column 1: error: unknown annotation `@fast`",
+                      "line 4, column 1: error: rule `D` is `@inline` but \
+                       refers to itself" ) );
+    assert_eq!( messages( b"A <- B\n@silent B -> u8 <- 'b' { |_| 1 }" ),
+                vec!( "line 2, column 1: error: rule `B` is `@silent`, so it \
+                       has no node for its action" ) );
+  }
+
+
+  #[test]
+  fn validate_SkipRule() {
+    assert_eq!( messages( b"A <- B C\n@skip B <- ' '*\n@skip C <- '#'*" ),
+                vec!( "line 3, column 1: error: rule `C` is `@skip`, but `B` \
+                       already is; a grammar can only have one skip rule" ) );
+    assert_eq!( messages( b"A <- 'a' 'b'\n@skip B <- ' '" ),
+                vec!( "line 2, column 1: warning: skip rule `B` can't match \
+                       the empty string, so it will be required between all \
+                       elements" ) );
+  }
+
+
+  #[test]
+  fn validate_CutOutsideChoice() {
+    assert_eq!( messages( b"A <- 'a' % B / 'c' (B % 'b')\nB <- 'b' % 'c'" ),
+                vec!( "line 2, column 10: warning: the cut in rule `B` isn't \
+                       inside a choice, so it has no effect" ) );
+  }
+
+
+  #[test]
+  fn validate_UnknownUnicodeProperty() {
+    assert_eq!( messages( br"A <- [\p{Greek}\p{Klingon}_] [\p{lu}]" ),
+                vec!( "line 1, column 16: error: unknown Unicode property \
+                       `Klingon`",
+                      "line 1, column 31: error: unknown Unicode property \
+                       `lu`" ) );
+  }
+
+
+  #[test]
+  fn validate_RepeatedEmptyMatch() {
+    assert_eq!( messages( b"A <- ('a'^B)* ('c'^'c')+\nB <- 'b'?" ),
+                vec!( "line 1, column 6: error: the expression under `*` \
+                       can match the empty string, so it would repeat forever"
+                      ) );
+    assert_eq!( messages( b"A <- 'a'* B+ ('b' / '')* 'c'+\nB <- 'b'?" ),
+                vec!( "line 1, column 11: error: the expression under `+` \
+                       can match the empty string, so it would repeat forever",
+                      "line 1, column 14: error: the expression under `*` \
+                       can match the empty string, so it would repeat forever"
+                      ) );
+    assert_eq!( messages( b"A <- B{2,} B{0,3} 'a'{1,}\nB <- 'b'?" ),
+                vec!( "line 1, column 6: error: the expression under `{2,}` \
+                       can match the empty string, so it would repeat forever"
+                      ) );
+  }
+
+
+  #[test]
+  fn validate_RepeatedEmptyMatch_Arguments() {
+    assert_eq!( messages( b"A <- List('') 'x'\nList(X) <- X*" ),
+                vec!( "line 2, column 12: error: the expression under `*` \
+                       can match the empty string when called as \
+                       `List('')`, so it would repeat forever" ) );
+    assert_eq!( messages( b"A <- Wrap('')* 'x'\nWrap(X) <- X" ),
+                vec!( "line 1, column 6: error: the expression under `*` \
+                       can match the empty string, so it would repeat forever"
+                      ) );
+    assert_eq!( messages( b"A <- List('a') List('b'?)\nList(X) <- ''* X+" ),
+                vec!( "line 2, column 12: error: the expression under `*` \
+                       can match the empty string, so it would repeat forever",
+                      "line 2, column 16: error: the expression under `+` \
+                       can match the empty string when called as \
+                       `List('b'?)`, so it would repeat forever" ) );
+    assert!( messages( b"A <- List('a') 'x'\nList(X) <- X*" ).is_empty() );
+  }
+
+
+  #[test]
+  fn validate_RepetitionBounds() {
+    assert_eq!( messages( b"A <- 'a'{3,2} 'b'{2,2} 'c'{99999999999999999999}" ),
+                vec!( "line 1, column 9: error: the repetition `{3,2}` has a \
+                       minimum above its maximum",
+                      "line 1, column 28: error: the count \
+                       `99999999999999999999` is too large" ) );
+  }
+
+
+  #[test]
+  fn validate_ByteMode() {
+    let input = br"A <- '\x89PNG' B [\x80-\xFF]
+@bytes B <- '\x89' [\p{L}\x00] C
+C <- [\xe9\x41]";
+    assert_eq!( messages( input ),
+                vec!( "line 1, column 6: error: the escapes in rule `A` \
+                       aren't valid UTF-8; bytes need byte mode (`@bytes`)",
+                      "line 1, column 19: error: the escapes in rule `A` \
+                       aren't valid UTF-8; bytes need byte mode (`@bytes`)",
+                      "line 2, column 21: error: rule `B` is in byte mode, \
+                       where Unicode properties never match",
+                      "line 3, column 7: error: the escapes in rule `C` \
+                       aren't valid UTF-8; bytes need byte mode (`@bytes`)" ) );
+
+    let options = Options { bytes: true, .. Options::new() };
+    assert_eq!( messagesWithOptions( input, &options ),
+                vec!( "line 2, column 21: error: rule `B` is in byte mode, \
+                       where Unicode properties never match" ) );
+  }
+
+
+  #[test]
+  fn validate_Parameters() {
+    let input = b"A <- B(C, C) E(C)\n\
+                  B(X, X) <- X\n\
+                  @skip S(X) <- ' '*\n\
+                  E(C) <- C\n\
+                  C <- 'c'";
+    assert_eq!( messages( input ),
+                vec!( "line 2, column 6: error: rule `B` has more than one \
+                       parameter named `X`",
+                      "line 3, column 1: error: skip rule `S` can't have \
+                       parameters",
+                      "line 4, column 3: error: parameter `C` of rule `E` has \
+                       the name of a rule" ) );
+    assert_eq!( messages( b"A(X) <- X" ),
+                vec!( "line 1, column 1: error: rule `A` is where parsing \
+                       starts, so it can't have parameters" ) );
+  }
+
+
+  #[test]
+  fn validate_Arguments() {
+    let input = b"A <- List(B) List B(A) List(B, B, B) Pair(B, B)\n\
+                  List(X, Sep) <- X (Sep X)* X(B)\n\
+                  Pair(X, Y) <- X Y\n\
+                  B <- 'b'";
+    assert_eq!( messages( input ),
+                vec!( "line 1, column 6: error: rule `List` takes 2 \
+                       arguments, but was given 1",
+                      "line 1, column 14: error: rule `List` takes 2 \
+                       arguments, but is used without any",
+                      "line 1, column 19: error: rule `B` doesn't take \
+                       arguments",
+                      "line 1, column 24: error: rule `List` takes 2 \
+                       arguments, but was given 3",
+                      "line 2, column 28: error: parameter `X` can't take \
+                       arguments" ) );
+  }
+
+
+  #[test]
+  fn validate_EndlessExpansion() {
+    assert_eq!( messages( b"A <- B('a')\nB(X) <- 'b' B((X)) / X" ),
+                vec!( "line 2, column 13: error: expanding `B((X))` never \
+                       ends; rule `B` keeps calling itself with new \
+                       arguments" ) );
+    assert!( messages( b"A <- B('a')\nB(X) <- X B(X)?" ).is_empty() );
+  }
+}
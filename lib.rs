@@ -0,0 +1,803 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![allow(non_snake_case)]
+#![cfg_attr(test, allow(dead_code))]
+#![deny(deprecated)]
+
+extern crate inlined_parser;
+#[cfg(test)]
+extern crate tempdir;
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::iter::repeat;
+use std::path::{Path, PathBuf};
+use inlined_parser::parse;
+use self::prelude::{PRELUDE, CRATE_PRELUDE, RULES_PRELUDE};
+
+pub use generator::{Options, Diagnostic, Severity};
+pub use inlined_parser::ParseError;
+
+mod generator;
+mod prelude;
+
+static TOP_LEVEL_RULE : &'static str = "NGTOP_LEVEL_RULE";
+static FULL_INPUT : &'static str = "NGFULL_INPUT";
+static SKIP_RULE : &'static str = "NGSKIP_RULE";
+
+/// The errors that can occur while generating a parser for a PEG grammar.
+#[derive(Debug)]
+pub enum Error {
+  /// Reading the grammar or writing the generated code failed.
+  Io( io::Error ),
+
+  /// The grammar isn't valid PEG syntax.
+  Parse( ParseError ),
+
+  /// The grammar has semantic errors, like references to undefined rules. Holds
+  /// all the diagnostics found in the grammar, warnings included.
+  Invalid( Vec<Diagnostic> )
+}
+
+
+impl From<io::Error> for Error {
+  fn from( error: io::Error ) -> Error {
+    Error::Io( error )
+  }
+}
+
+
+impl fmt::Display for Error {
+  fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+    match *self {
+      Error::Io( ref error ) => write!( formatter, "{}", error ),
+      Error::Parse( ref error ) => {
+        write!( formatter, "Failed to parse PEG grammar at {}", error )
+      }
+      Error::Invalid( ref diagnostics ) => {
+        try!( write!( formatter, "Invalid PEG grammar:" ) );
+        for diagnostic in diagnostics.iter() {
+          try!( write!( formatter, "\n{}", diagnostic ) );
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+
+impl error::Error for Error {
+  fn description( &self ) -> &str {
+    match *self {
+      Error::Io( _ ) => "I/O error",
+      Error::Parse( _ ) => "failed to parse PEG grammar",
+      Error::Invalid( _ ) => "invalid PEG grammar"
+    }
+  }
+}
+
+
+/// The code generated for a grammar.
+pub struct GeneratedParser {
+  /// The Rust source of the parser. It's meant to be the root of its own crate.
+  pub code: String,
+
+  /// Problems found in the grammar that didn't prevent generating the code.
+  pub warnings: Vec<Diagnostic>,
+
+  /// The grammar files the parser was generated from, the imported ones
+  /// included.
+  pub files: Vec<PathBuf>
+}
+
+
+/// The code generated for a grammar as the source of a crate. Unlike the code
+/// of a `GeneratedParser`, it doesn't carry its own copy of the parsing
+/// runtime, so the crate has to depend on the `nailgun_runtime` crate.
+pub struct GeneratedCrate {
+  /// The Rust source of the crate root, with the functions that parse input.
+  pub lib: String,
+
+  /// The Rust source of the `rules` module the crate root declares, with a
+  /// function for every rule of the grammar.
+  pub rules: String,
+
+  /// Problems found in the grammar that didn't prevent generating the code.
+  pub warnings: Vec<Diagnostic>,
+
+  /// The grammar files the parser was generated from, the imported ones
+  /// included.
+  pub files: Vec<PathBuf>
+}
+
+
+// The version of the `nailgun_runtime` crate the generated code is meant for.
+const RUNTIME_VERSION: &'static str = "0.1.0";
+
+
+impl GeneratedCrate {
+  /// Writes the crate root to `lib.rs` and the rules to `rules.rs` in the
+  /// directory at `dir`, creating it first if it doesn't exist. Unless the
+  /// directory already has a `Cargo.toml`, the `manifest` of a crate named
+  /// after the directory is written too; an existing one is left alone.
+  pub fn write<P>( &self, dir: P ) -> io::Result<()> where P: AsRef<Path> {
+    let dir = dir.as_ref();
+    try!( fs::create_dir_all( dir ) );
+    let manifest_path = dir.join( "Cargo.toml" );
+    if !manifest_path.exists() {
+      let dir = try!( fs::canonicalize( dir ) );
+      let name = dir.file_name()
+        .and_then( |name| name.to_str() )
+        .unwrap_or( "parser" );
+      try!( writeSource( &manifest_path, &self.manifest( name ) ) );
+    }
+    try!( writeSource( &dir.join( "lib.rs" ), &self.lib ) );
+    writeSource( &dir.join( "rules.rs" ), &self.rules )
+  }
+
+  /// Returns the `Cargo.toml` of a crate named `name`, with `lib.rs` and
+  /// `rules.rs` next to it, that depends on the `nailgun_runtime` crate.
+  pub fn manifest( &self, name: &str ) -> String {
+    format!( "[package]\n\n\
+              name = {:?}\n\
+              version = \"0.1.0\"\n\n\
+              [lib]\n\n\
+              path = \"lib.rs\"\n\n\
+              [dependencies]\n\
+              nailgun_runtime = \"{}\"\n",
+             name, RUNTIME_VERSION )
+  }
+}
+
+
+fn writeSource( path: &Path, code: &str ) -> io::Result<()> {
+  File::create( path ).and_then( |mut file| file.write_all( code.as_bytes() ) )
+}
+
+
+fn indentLines( input: &str, num_spaces: usize ) -> String {
+  let indent: String = repeat( " " ).take( num_spaces ).collect();
+  input.split( '\n' ).map(
+    |x| indent.clone() + x + "\n" ).collect::<Vec<String>>().concat()
+}
+
+
+/// Generates the code of a parser for the PEG grammar in `input`. Grammar
+/// files imported with `import "file.peg"` are looked up relative to the
+/// current directory.
+pub fn codeForGrammar( input: &[u8], options: &Options )
+    -> Result<GeneratedParser, Error> {
+  codeForSource( input, None, options )
+}
+
+
+/// Generates the code of a parser for the PEG grammar in the file at `path`.
+/// Grammar files it imports are looked up relative to it.
+pub fn codeForGrammarFile<P>( path: P, options: &Options )
+    -> Result<GeneratedParser, Error> where P: AsRef<Path> {
+  let path = path.as_ref();
+  let input = try!( readGrammar( path ) );
+  codeForSource( &input, Some( path ), options )
+}
+
+
+fn readGrammar( path: &Path ) -> Result<Vec<u8>, Error> {
+  let mut input: Vec<u8> = vec!();
+  try!( File::open( path ).and_then( |mut file| {
+    file.read_to_end( &mut input )
+  } ).map_err( |error| {
+    io::Error::new( error.kind(),
+                    format!( "Couldn't read {}: {}", path.display(), error ) )
+  } ) );
+  Ok( input )
+}
+
+
+// The code generated for a grammar, without the prelude it goes with.
+struct Code {
+  start_rule: String,
+  skip_rule: Option<String>,
+  rules: String,
+  api: String,
+  warnings: Vec<Diagnostic>,
+  files: Vec<PathBuf>
+}
+
+
+fn generate( input: &[u8], path: Option<&Path>, options: &Options )
+    -> Result<Code, Error> {
+  let root = try!( parse( input ).map_err( Error::Parse ) );
+  let sources = try!( generator::merge( input, &root, path )
+                        .map_err( Error::Invalid ) );
+  let node = try!( parse( &sources.text ).map_err( Error::Parse ) );
+  let diagnostics = generator::validate( &sources, &node, options );
+  if diagnostics.iter().any( |x| x.severity == Severity::Error ) {
+    return Err( Error::Invalid( diagnostics ) );
+  }
+
+  let mut api = generator::codeForValueParser( &node, options ) +
+    &generator::codeForEntryPoints( &node, options );
+  if options.ast {
+    api.push_str( &generator::codeForAst( &node, options ) );
+  }
+  Ok( Code { start_rule: generator::nameOfStartRule( &node, options ),
+             skip_rule: generator::nameOfSkipRule( &node ),
+             rules: generator::codeForGrammar( &node, options ),
+             api: api,
+             warnings: diagnostics,
+             files: sources.files() } )
+}
+
+
+fn preparePrelude( prelude: &str, code: &Code, options: &Options ) -> String {
+  let skip_rule = match code.skip_rule {
+    Some( ref rule ) => format!( "Some( rules::{} )", rule ),
+    _ => "None".to_string()
+  };
+  prelude.replace( TOP_LEVEL_RULE, &code.start_rule ).replace(
+    FULL_INPUT,
+    if options.full_input { "true" } else { "false" } ).replace(
+    SKIP_RULE,
+    &skip_rule )
+}
+
+
+fn codeForSource( input: &[u8], path: Option<&Path>, options: &Options )
+    -> Result<GeneratedParser, Error> {
+  let code = try!( generate( input, path, options ) );
+  let prepared_prelude = preparePrelude( &PRELUDE[ .. PRELUDE.len() -1 ],
+                                         &code,
+                                         options );
+  Ok( GeneratedParser { code: prepared_prelude + "\n" +
+                              &indentLines( &code.rules, 2 ) + "}" +
+                              &code.api,
+                        warnings: code.warnings,
+                        files: code.files } )
+}
+
+
+/// Generates the code of a parser crate for the PEG grammar in `input`.
+/// Grammar files imported with `import "file.peg"` are looked up relative to
+/// the current directory.
+pub fn crateForGrammar( input: &[u8], options: &Options )
+    -> Result<GeneratedCrate, Error> {
+  crateForSource( input, None, options )
+}
+
+
+/// Generates the code of a parser crate for the PEG grammar in the file at
+/// `path`. Grammar files it imports are looked up relative to it.
+pub fn crateForGrammarFile<P>( path: P, options: &Options )
+    -> Result<GeneratedCrate, Error> where P: AsRef<Path> {
+  let path = path.as_ref();
+  let input = try!( readGrammar( path ) );
+  crateForSource( &input, Some( path ), options )
+}
+
+
+fn crateForSource( input: &[u8], path: Option<&Path>, options: &Options )
+    -> Result<GeneratedCrate, Error> {
+  let code = try!( generate( input, path, options ) );
+  Ok( GeneratedCrate { lib: preparePrelude( CRATE_PRELUDE, &code, options ) +
+                            &code.api,
+                       rules: RULES_PRELUDE.to_string() + "\n" + &code.rules +
+                              "\n",
+                       warnings: code.warnings,
+                       files: code.files } )
+}
+
+
+// Relative paths are taken to be relative to OUT_DIR when running under a
+// build script.
+fn outputPath( out_path: &Path ) -> PathBuf {
+  match env::var_os( "OUT_DIR" ) {
+    Some( out_dir ) => Path::new( &out_dir ).join( out_path ),
+    _ => out_path.to_path_buf()
+  }
+}
+
+
+fn moduleName( out_path: &Path ) -> String {
+  out_path.file_stem()
+    .map_or( "parser".to_string(),
+             |stem| stem.to_string_lossy().replace( "-", "_" ) )
+}
+
+
+/// Generates a parser for the PEG grammar in the file at `grammar_path` and
+/// writes it to `out_path`. Meant to be called from a build script, so a
+/// relative `out_path` is put in `OUT_DIR` and Cargo is told to rerun the
+/// build script when the grammar or a file it imports changes. Warnings about
+/// the grammar are passed on to Cargo.
+///
+/// The parser is written as a public module named after the file stem of
+/// `out_path`, so it can be included in the crate being built:
+///
+/// ```ignore
+/// // build.rs
+/// extern crate nailgun;
+///
+/// fn main() {
+///   nailgun::compileGrammar( "src/arithmetic.peg",
+///                            "arithmetic.rs",
+///                            &nailgun::Options::new() ).unwrap();
+/// }
+///
+/// // src/lib.rs
+/// include!( concat!( env!( "OUT_DIR" ), "/arithmetic.rs" ) );
+///
+/// fn parseExpression( input: &[u8] ) -> bool {
+///   arithmetic::parse( input ).is_ok()
+/// }
+/// ```
+pub fn compileGrammar<P, Q>( grammar_path: P, out_path: Q, options: &Options )
+    -> Result<(), Error> where P: AsRef<Path>, Q: AsRef<Path> {
+  let grammar_path = grammar_path.as_ref();
+  println!( "cargo:rerun-if-changed={}", grammar_path.display() );
+
+  let parser = try!( codeForGrammarFile( grammar_path, options ) );
+  for file in parser.files.iter().skip( 1 ) {
+    println!( "cargo:rerun-if-changed={}", file.display() );
+  }
+  for warning in parser.warnings.iter() {
+    println!( "cargo:warning={}", warning );
+  }
+
+  let code = format!( "pub mod {} {{\n{}}}\n",
+                      moduleName( out_path.as_ref() ),
+                      indentLines( &parser.code, 2 ) );
+  try!( writeSource( &outputPath( out_path.as_ref() ), &code ) );
+  Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::{Read, Write};
+  use std::path::Path;
+  use tempdir::TempDir;
+  use super::{codeForGrammar, codeForGrammarFile, compileGrammar,
+              crateForGrammar, Error, Options};
+
+  fn writeFile( path: &Path, contents: &[u8] ) {
+    File::create( path ).and_then( |mut file| file.write_all( contents ) )
+      .unwrap();
+  }
+
+  #[test]
+  fn codeForGrammar_Works() {
+    let parser = codeForGrammar( b"A <- 'a' B\nB <- 'b'\nC <- A",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains( "parseFrom( rules::A, " ) );
+    assert!( parser.code.contains( "rule!( B <- lit!( \"b\" ) );" ) );
+    assert_eq!( parser.warnings.len(), 1 );
+  }
+
+
+  #[test]
+  fn codeForGrammar_TypedRules() {
+    let parser = codeForGrammar(
+      b"A -> usize <- B* { |n| n.end }\nB <- 'b'", &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- star!( ex!( B ) ) => A_value -> usize = |n| n.end );" ) );
+    assert!( parser.code.contains(
+      "pub fn parseValue<'a>( input: &'a [u8] ) -> Result< usize, ParseError >" ) );
+
+    let untyped = codeForGrammar( b"A <- 'a'", &Options::new() ).unwrap();
+    assert!( !untyped.code.contains( "parseValue" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_Labels() {
+    let parser = codeForGrammar( b"A <- x:'a' y:!B\nB <- 'b'",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( label!( x, lit!( \"a\" ) ), \
+                         label!( y, not!( ex!( B ) ) ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_Recovery() {
+    let parser = codeForGrammar( b"A <- x:B^(!';' .)* ';'^''\nB <- 'b'",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( label!( x, recover!( ex!( B ), \
+       star!( seq!( not!( lit!( \";\" ) ), &base::Dot ) ) ) ), \
+       recover!( lit!( \";\" ), lit!( \"\" ) ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_Cut() {
+    let parser = codeForGrammar( b"A <- 'a' % 'b' / 'c'", &Options::new() )
+      .unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- or!( seq!( lit!( \"a\" ), &base::Cut, lit!( \"b\" ) ), \
+                        lit!( \"c\" ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_IgnoreCase() {
+    let parser = codeForGrammar( b"A <- 'select'i [a-z]i 'from' [a-z]",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( ilit!( \"select\" ), iclass!( \"a-z\" ), \
+                         lit!( \"from\" ), class!( \"a-z\" ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_NegatedClass() {
+    let parser = codeForGrammar( br#"A <- [^"\\] [^a-z]i [\^a] [\]]"#,
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( class!( ^ \"\\\"\\\\\\\\\" ), iclass!( ^ \"a-z\" ), \
+                         class!( \"^a\" ), class!( \"]\" ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_UnicodeProperties() {
+    let parser = codeForGrammar( br"A <- [\p{XID_Start}_] [^\p{Greek}\\]",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( class!( \"\\\\p{XID_Start}_\" ), \
+                         class!( ^ \"\\\\p{Greek}\\\\\\\\\" ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_ByteMode() {
+    let grammar = br#"A <- '\x50"\\' B .
+@bytes B <- 'ng'i [^\x00-\x1F\x80-\xff\\] ."#;
+    let parser = codeForGrammar( grammar, &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( lit!( \"P\\\"\\\\\" ), ex!( B ), &base::Dot ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( B <- seq!( iblit!( b\"ng\" ), \
+                         bclass!( ^ \"\\\\x00-\\\\x1F\\\\x80-\\\\xFF\\\\\\\\\" \
+                                  ), \
+                         &base::AnyByte ) );" ) );
+
+    let options = Options { bytes: true, .. Options::new() };
+    let parser = codeForGrammar( grammar, &options ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( blit!( b\"P\\\"\\\\\" ), ex!( B ), \
+                         &base::AnyByte ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_Annotations() {
+    let options = Options { memoize: true, .. Options::new() };
+    let parser = codeForGrammar(
+      b"A <- B C D\n@silent B <- ' '\n@inline C <- 'c'\n@token D <- 'd'+",
+      &options ).unwrap();
+    assert!( parser.code.contains( "rule!( memo silent B <- lit!( \" \" ) );" ) );
+    assert!( parser.code.contains( "rule!( memo inline C <- lit!( \"c\" ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( memo D <- fuse!( plus!( lit!( \"d\" ) ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_SkipRule() {
+    let parser = codeForGrammar(
+      b"A <- B* !'x' C+ ~('a' 'b')\nB <- 'b' 'c'?\nC <~ 'c' 'd'\n\
+        @skip S <- ' '*",
+      &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( opt!( seq!( ex!( B ), star!( seq!( ex!( S ), \
+       ex!( B ) ) ) ) ), ex!( S ), not!( lit!( \"x\" ) ), seq!( ex!( C ), \
+       star!( seq!( ex!( S ), ex!( C ) ) ) ), ex!( S ), \
+       fuse!( seq!( lit!( \"a\" ), lit!( \"b\" ) ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( B <- seq!( lit!( \"b\" ), ex!( S ), opt!( lit!( \"c\" ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( C <- fuse!( seq!( lit!( \"c\" ), lit!( \"d\" ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( silent S <- star!( lit!( \" \" ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_CountedRepetition() {
+    let parser = codeForGrammar( b"A <- [0-9]{4} 'x'{2,} B{0,3} B{0}\n\
+                                   B <- 'b'{ 1 , 2 }",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( repeat!( class!( \"0-9\" ), 4, Some( 4 ) ), \
+                         repeat!( lit!( \"x\" ), 2, None ), \
+                         repeat!( ex!( B ), 0, Some( 3 ) ), \
+                         repeat!( ex!( B ), 0, Some( 0 ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( B <- repeat!( lit!( \"b\" ), 1, Some( 2 ) ) );" ) );
+
+    let parser = codeForGrammar( b"A <- 'a'{2,4} 'b'{0,} 'c'{0}\n\
+                                   @skip S <- ' '*",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( seq!( lit!( \"a\" ), repeat!( seq!( ex!( S ), \
+       lit!( \"a\" ) ), 1, Some( 3 ) ) ), ex!( S ), \
+       opt!( seq!( lit!( \"b\" ), repeat!( seq!( ex!( S ), lit!( \"b\" ) ), \
+       0, None ) ) ), ex!( S ), \
+       repeat!( lit!( \"c\" ), 0, Some( 0 ) ) ) );" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_ParameterizedRules() {
+    let parser = codeForGrammar( b"A <- List(B, ',') List(B / 'x', C)\n\
+                                   List(X, Sep) <- X (Sep X)*\n\
+                                   B <- 'b'\nC <- 'c'",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( ex!( List_1 ), ex!( List_2 ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( List_1 <- seq!( ex!( B ), star!( seq!( lit!( \",\" ), \
+       ex!( B ) ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( List_2 <- seq!( or!( ex!( B ), lit!( \"x\" ) ), \
+       star!( seq!( ex!( C ), or!( ex!( B ), lit!( \"x\" ) ) ) ) ) );" ) );
+    assert!( !parser.code.contains( "rule!( List <-" ) );
+
+    let parser = codeForGrammar( b"A <- List(B, C)\n\
+                                   List(X, Sep) <- X (Sep X)*\n\
+                                   B <- 'b'\nC <- 'c'",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( List_B_C <- seq!( ex!( B ), star!( seq!( ex!( C ), \
+       ex!( B ) ) ) ) );" ) );
+
+    assert!( codeForGrammar( b"A <- List(B)\nList(X, Sep) <- X (Sep X)*\n\
+                               B <- 'b'",
+                             &Options::new() ).is_err() );
+  }
+
+
+  #[test]
+  fn codeForGrammar_StartRule() {
+    let options = Options { start: Some( "B".to_string() ), .. Options::new() };
+    let parser = codeForGrammar( b"A -> u8 <- B { |n| 1 }\n\
+                                   B -> u8 <- 'b' { |n| 2 }",
+                                 &options ).unwrap();
+    assert!( parser.code.contains( "parseFrom( rules::B, " ) );
+    assert!( !parser.code.contains( "parseFrom( rules::A, " ) );
+    assert!( parser.code.contains(
+      "pub fn parseValue<'a>( input: &'a [u8] ) \
+       -> Result< u8, ParseError > {\n  parse( input ).map( |node| rules::B_value( &node ) )\n}" ) );
+
+    // Only the start rule has to be reachable.
+    assert_eq!( parser.warnings.len(), 1 );
+    assert_eq!( parser.warnings[ 0 ].message, "rule `A` is never used" );
+
+    let options = Options { start: Some( "C".to_string() ), .. Options::new() };
+    assert!( codeForGrammar( b"A <- 'a'", &options ).is_err() );
+  }
+
+
+  #[test]
+  fn codeForGrammar_EntryPoints() {
+    let parser = codeForGrammar( b"File <- Item*\n@public Item <- 'i' Name\n\
+                                   Name <- [a-z]+",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "pub fn parse_Item<'a>( input: &'a [u8] ) \
+       -> Result< Node<'a>, ParseError > {\n  \
+       parseFrom( rules::Item, ParseState::new( input ), input )\n}" ) );
+    assert!( !parser.code.contains( "pub fn parse_Name<" ) );
+    assert!( !parser.code.contains( "pub fn parse_File<" ) );
+
+    let options = Options { public: true, .. Options::new() };
+    let parser = codeForGrammar( b"File <- List(Item)\nItem <- 'i' Space\n\
+                                   List(X) <- X*\n@silent Space <- ' '*",
+                                 &options ).unwrap();
+    assert!( parser.code.contains( "pub fn parse_File<" ) );
+    assert!( parser.code.contains( "pub fn parse_Item<" ) );
+    assert!( !parser.code.contains( "pub fn parse_List" ) );
+    assert!( !parser.code.contains( "pub fn parse_Space<" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_FullInput() {
+    let parser = codeForGrammar( b"A <- 'a'", &Options::new() ).unwrap();
+    assert!( parser.code.contains( "const FULL_INPUT: bool = false;" ) );
+    assert!( parser.code.contains( "pub fn parse_prefix<'a>(" ) );
+
+    let options = Options { full_input: true, .. Options::new() };
+    let parser = codeForGrammar( b"A <- 'a'", &options ).unwrap();
+    assert!( parser.code.contains( "const FULL_INPUT: bool = true;" ) );
+    assert!( !parser.code.contains( "NGFULL_INPUT" ) );
+  }
+
+
+  #[test]
+  fn codeForGrammar_SkipRuleConstant() {
+    let parser = codeForGrammar( b"A <- 'a'", &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "const SKIP_RULE: Option<base::Rule> = None;" ) );
+
+    let parser = codeForGrammar( b"A <- 'a'+\n@skip S <- ' '*",
+                                 &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "const SKIP_RULE: Option<base::Rule> = Some( rules::S );" ) );
+    assert!( !parser.code.contains( "NGSKIP_RULE" ) );
+  }
+
+
+  #[test]
+  fn crateForGrammar_UsesRuntimeCrate() {
+    let options = Options { full_input: true, .. Options::new() };
+    let parser = crateForGrammar( b"A <- 'a' B\nB <- 'b'\nC <- A",
+                                  &options ).unwrap();
+    assert!( parser.lib.contains( "extern crate nailgun_runtime;\n\
+                                   use nailgun_runtime::base;" ) );
+    assert!( parser.lib.contains( "\nmod rules;\n" ) );
+    assert!( parser.lib.contains( "parseFrom( rules::A, " ) );
+    assert!( parser.lib.contains( "const FULL_INPUT: bool = true;" ) );
+    assert!( !parser.lib.contains( "mod base" ) );
+    assert!( !parser.lib.contains( "rule!( B <- " ) );
+
+    assert!( parser.rules.starts_with( "use super::base;\n" ) );
+    assert!( parser.rules.contains( "\nrule!( B <- lit!( \"b\" ) );\n" ) );
+    assert_eq!( parser.warnings.len(), 1 );
+  }
+
+
+  #[test]
+  fn GeneratedCrate_Write() {
+    let dir = TempDir::new( "nailgun" ).unwrap();
+    let out_dir = dir.path().join( "parsers" ).join( "letters" );
+    let parser = crateForGrammar( b"A <- 'a'", &Options::new() ).unwrap();
+    parser.write( &out_dir ).unwrap();
+
+    let mut manifest = String::new();
+    File::open( out_dir.join( "Cargo.toml" ) ).unwrap()
+      .read_to_string( &mut manifest ).unwrap();
+    assert_eq!( manifest, parser.manifest( "letters" ) );
+    assert!( manifest.contains( "path = \"lib.rs\"" ) );
+    assert!( manifest.contains(
+      "[dependencies]\nnailgun_runtime = \"0.1.0\"" ) );
+
+    let mut lib = String::new();
+    File::open( out_dir.join( "lib.rs" ) ).unwrap()
+      .read_to_string( &mut lib ).unwrap();
+    assert_eq!( lib, parser.lib );
+    let mut rules = String::new();
+    File::open( out_dir.join( "rules.rs" ) ).unwrap()
+      .read_to_string( &mut rules ).unwrap();
+    assert_eq!( rules, parser.rules );
+
+    // Writing the crate again keeps changes made to its manifest.
+    writeFile( &out_dir.join( "Cargo.toml" ), b"[package]\n" );
+    parser.write( &out_dir ).unwrap();
+    let mut manifest = String::new();
+    File::open( out_dir.join( "Cargo.toml" ) ).unwrap()
+      .read_to_string( &mut manifest ).unwrap();
+    assert_eq!( manifest, "[package]\n" );
+  }
+
+
+  #[test]
+  fn codeForGrammar_ParseError() {
+    match codeForGrammar( b"A <- (", &Options::new() ) {
+      Err( Error::Parse( error ) ) => assert_eq!( error.offset, 6 ),
+      _ => panic!( "Should not parse." )
+    }
+  }
+
+
+  #[test]
+  fn codeForGrammar_InvalidGrammar() {
+    match codeForGrammar( b"A <- B", &Options::new() ) {
+      Err( Error::Invalid( diagnostics ) ) => assert_eq!( diagnostics.len(), 1 ),
+      _ => panic!( "Should be invalid." )
+    }
+  }
+
+
+  #[test]
+  fn codeForGrammarFile_Imports() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let grammar_path = temp_dir.path().join( "grammar.peg" );
+    writeFile( &grammar_path, b"import 'lexer.peg' as lex\n\
+                                A <- lex::Number (',' lex::Number)*" );
+    writeFile( &temp_dir.path().join( "lexer.peg" ),
+               b"Number <- Digit+\nDigit <- [0-9]\nUnused <- Digit" );
+
+    let parser = codeForGrammarFile( &grammar_path, &Options::new() ).unwrap();
+    assert!( parser.code.contains(
+      "rule!( A <- seq!( ex!( lex_Number ), star!( seq!( lit!( \",\" ), \
+       ex!( lex_Number ) ) ) ) );" ) );
+    assert!( parser.code.contains(
+      "rule!( lex_Number <- plus!( ex!( lex_Digit ) ) );" ) );
+    assert_eq!( parser.files, vec!( grammar_path.clone(),
+                                    temp_dir.path().join( "lexer.peg" ) ) );
+
+    // Problems are reported in the file they're in.
+    assert_eq!( parser.warnings.len(), 1 );
+    assert_eq!( parser.warnings[ 0 ].file,
+                Some( temp_dir.path().join( "lexer.peg" ) ) );
+    assert_eq!( ( parser.warnings[ 0 ].line, parser.warnings[ 0 ].column ),
+                ( 3, 1 ) );
+    assert_eq!( parser.warnings[ 0 ].message,
+                "rule `lex_Unused` is never used" );
+  }
+
+
+  #[test]
+  fn codeForGrammarFile_DuplicateAcrossFiles() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let grammar_path = temp_dir.path().join( "grammar.peg" );
+    let lexer_path = temp_dir.path().join( "lexer.peg" );
+    writeFile( &grammar_path, b"import 'lexer.peg'\nA <- B\nB <- 'b'" );
+    writeFile( &lexer_path, b"\nB <- 'c'" );
+
+    match codeForGrammarFile( &grammar_path, &Options::new() ) {
+      Err( Error::Invalid( diagnostics ) ) => {
+        assert_eq!( diagnostics.len(), 1 );
+        assert_eq!( diagnostics[ 0 ].to_string(),
+                    format!( "{}: line 2, column 1: error: rule `B` is \
+                              already defined on line 3 of `{}`",
+                             lexer_path.display(),
+                             grammar_path.display() ) );
+      }
+      _ => panic!( "Should be invalid." )
+    }
+  }
+
+
+  #[test]
+  fn codeForGrammar_ImportsRelativeToCurrentDirectory() {
+    match codeForGrammar( b"import 'no/such/dir/x.peg'\nA <- 'a'",
+                          &Options::new() ) {
+      Err( Error::Invalid( diagnostics ) ) => {
+        assert_eq!( diagnostics[ 0 ].file, None );
+        assert!( diagnostics[ 0 ].message
+                   .starts_with( "can't read `no/such/dir/x.peg`" ) );
+      }
+      _ => panic!( "Should be invalid." )
+    }
+  }
+
+
+  #[test]
+  fn compileGrammar_WritesModule() {
+    let temp_dir = TempDir::new( "nailgun" ).unwrap();
+    let grammar_path = temp_dir.path().join( "grammar.peg" );
+    let out_path = temp_dir.path().join( "my-parser.rs" );
+    File::create( &grammar_path ).and_then( |mut file| {
+      file.write_all( b"A <- 'a'" )
+    } ).unwrap();
+
+    compileGrammar( &grammar_path, &out_path, &Options::new() ).unwrap();
+
+    let mut code = String::new();
+    File::open( &out_path ).and_then( |mut file| {
+      file.read_to_string( &mut code )
+    } ).unwrap();
+    assert!( code.starts_with( "pub mod my_parser {\n" ) );
+    assert!( code.contains( "rule!( A <- lit!( \"a\" ) );" ) );
+  }
+}
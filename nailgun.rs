@@ -18,30 +18,21 @@
 
 extern crate getopts;
 extern crate tempdir;
-extern crate inlined_parser;
+extern crate nailgun;
 
 use tempdir::TempDir;
 #[cfg(not(test))]
 use getopts::Options;
 use std::env;
 use std::io;
-use std::io::Read;
 use std::io::Write;
 use std::fs::File;
 use std::process::Command;
 use std::path::Path;
-use self::prelude::PRELUDE;
 use self::printer::PRINTER_MAIN;
-use inlined_parser::{parse, Node};
-use std::str;
-use std::iter::repeat;
 
-mod generator;
-mod prelude;
 mod printer;
 
-static TOP_LEVEL_RULE : &'static str = "NGTOP_LEVEL_RULE";
-
 #[derive(Debug)]
 enum CliError {
   Io( io::Error ),
@@ -63,18 +54,13 @@ impl From<String> for CliError {
 }
 
 
-fn inputFromFile( input_file: &str ) -> Result<Vec<u8>, CliError> {
-  File::open( &Path::new( input_file ) ).and_then( |mut file| {
-    let mut data: Vec<u8> = vec!();
-    file.read_to_end( &mut data ).map( |_| data )
-  }).map_err( |_| CliError::Misc( format!( "Couldn't read: {}", input_file ) ) )
-}
-
-
-fn indentLines( input: &str, num_spaces: usize ) -> String {
-  let indent: String = repeat( " " ).take( num_spaces ).collect();
-  input.split( '\n' ).map(
-    |x| indent.clone() + x + "\n" ).collect::<Vec<String>>().concat()
+impl From<nailgun::Error> for CliError {
+  fn from( error: nailgun::Error ) -> CliError {
+    match error {
+      nailgun::Error::Io( error ) => CliError::Io( error ),
+      error => CliError::Misc( error.to_string() )
+    }
+  }
 }
 
 
@@ -88,22 +74,25 @@ fn printUsage( opts: &getopts::Options ) {
 }
 
 
-fn nameOfFirstRule<'a>( root: &'a Node<'a> ) -> String {
-  str::from_utf8(
-    &root.preOrder().find( |x| x.name == "Identifier" ).unwrap()
-        .matchedData() ).unwrap().trim_matches(' ').to_string()
+fn codeForGrammar( grammar_path: &str, options: &nailgun::Options )
+    -> Result<String, CliError> {
+  let parser = try!( nailgun::codeForGrammarFile( grammar_path, options ) );
+  for warning in parser.warnings.iter() {
+    try!( writeln!( &mut io::stderr(), "{}", warning ) );
+  }
+  Ok( parser.code )
 }
 
 
-fn codeForGrammar( input: &[u8] ) -> Result<String, CliError> {
-  parse( input ).map( |node| {
-    let parse_rules = indentLines( &generator::codeForNode( &node ), 2 );
-    let prepared_prelude = PRELUDE[ .. PRELUDE.len() -1 ].replace(
-      TOP_LEVEL_RULE,
-      &nameOfFirstRule( &node ) );
-
-    prepared_prelude + "\n" + &parse_rules + "}"
-  } ).ok_or( CliError::Misc( "Failed to parse PEG grammar".to_string() ) )
+// Returns exit code
+fn writeCrate( grammar_path: &str, out_dir: &str, options: &nailgun::Options )
+    -> Result<i32, CliError> {
+  let parser = try!( nailgun::crateForGrammarFile( grammar_path, options ) );
+  for warning in parser.warnings.iter() {
+    try!( writeln!( &mut io::stderr(), "{}", warning ) );
+  }
+  try!( parser.write( out_dir ) );
+  Ok( 0 )
 }
 
 
@@ -148,6 +137,23 @@ fn main() {
   opts.optopt( "g", "grammar",
                "Path to PEG grammar. Prints code for grammar if -i not given.",
                "FILE" );
+  opts.optflag( "m", "memoize",
+                "Generate a packrat parser that memoizes rule results." );
+  opts.optflag( "a", "ast",
+                "Generate typed AST nodes for the rules of the grammar." );
+  opts.optflag( "b", "bytes",
+                "Parse every rule in byte mode, for binary formats." );
+  opts.optopt( "s", "start",
+               "Start parsing with RULE instead of the first rule.",
+               "RULE" );
+  opts.optflag( "p", "public",
+                "Generate a parse_<rule> function for every rule." );
+  opts.optflag( "f", "full-input",
+                "Make parsing fail unless all of the input is consumed." );
+  opts.optopt( "o", "out-dir",
+               "Write the parser to DIR as a crate that depends on \
+                nailgun_runtime. Can't be used with -i.",
+               "DIR" );
 
   let args: Vec<_> = env::args().collect();
   let matches = opts.parse( &args[ 1.. ] ).unwrap();
@@ -156,17 +162,32 @@ fn main() {
     return;
   }
 
+  let mut options = nailgun::Options::new();
+  options.memoize = matches.opt_present( "m" );
+  options.ast = matches.opt_present( "a" );
+  options.bytes = matches.opt_present( "b" );
+  options.start = matches.opt_str( "s" );
+  options.public = matches.opt_present( "p" );
+  options.full_input = matches.opt_present( "f" );
+
   let exit_code = matches.opt_str( "g" )
     .ok_or( CliError::Misc( "Missing -g option".to_string() ) )
-    .and_then( |file| inputFromFile( &file ) )
-    .and_then( |input| codeForGrammar( &input ) )
-    .and_then( |grammar_code| {
-      if let Some( input_path ) = matches.opt_str( "i" ) {
-        printParseTree( &grammar_code, &input_path )
-      } else {
-        println!( "{}", grammar_code );
-        Ok( 0 )
+    .and_then( |file| {
+      if let Some( out_dir ) = matches.opt_str( "o" ) {
+        if matches.opt_present( "i" ) {
+          return Err( CliError::Misc(
+            "The -i and -o options can't be used together.".to_string() ) );
+        }
+        return writeCrate( &file, &out_dir, &options );
       }
+      codeForGrammar( &file, &options ).and_then( |grammar_code| {
+        if let Some( input_path ) = matches.opt_str( "i" ) {
+          printParseTree( &grammar_code, &input_path )
+        } else {
+          println!( "{}", grammar_code );
+          Ok( 0 )
+        }
+      } )
     } );
 
   std::process::exit( exit_code.unwrap_or_else( |error| {
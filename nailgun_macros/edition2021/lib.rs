@@ -0,0 +1,68 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The parsers the macros expand to have to build no matter the edition of the
+//! crate using them. In this one, `try` is a keyword and trait objects without
+//! `dyn` are errors.
+#![allow(non_snake_case)]
+#![cfg(test)]
+
+use nailgun_macros::{peg_grammar, Grammar};
+
+peg_grammar!( total, r#"
+  Sum -> i64    <- Sum '+' Number / Number {
+    |node| match node.contents {
+      base::Children( ref nodes ) if nodes.len() == 3 => {
+        Sum_value( &nodes[ 0 ] ) + Number_value( &nodes[ 2 ] )
+      }
+      _ => Number_value( node )
+    }
+  }
+  Number -> i64 <- [0-9]+ { |node| node.matchedText().parse().unwrap() }
+"# );
+
+peg_grammar!( lines, r#"
+  Line <- [a-z]* '\n'
+"# );
+
+#[derive(Grammar)]
+#[grammar = "../tests/grammars/assignments.peg"]
+struct Assignments;
+
+
+#[test]
+fn peg_grammar_Parses() {
+  assert_eq!( total::parseValue( b"1+22+3" ).unwrap(), 26 );
+  let error = total::parse( b"+1" ).unwrap_err();
+  assert_eq!( error.to_string(),
+              "line 1, column 1: expected one of Number, Sum" );
+}
+
+
+#[test]
+fn peg_grammar_ParsesStreams() {
+  let mut lines = vec!();
+  lines::parseStream( &b"ab\ncd\n"[..], 8, |node| {
+    lines.push( node.matchedText() );
+  } ).unwrap();
+  assert_eq!( lines, vec!( "ab\n", "cd\n" ) );
+}
+
+
+#[test]
+fn derive_Grammar_Parses() {
+  let node = Assignments::parse( b"x=42;yz=7;" ).unwrap();
+  assert_eq!( node.name, "Assignments" );
+  assert!( Assignments::parse( b"x=;" ).is_err() );
+}
@@ -0,0 +1,327 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Expands PEG grammars into parsers at compile time, so no separate code
+//! generation step is needed. The expanded code is the same code the `nailgun`
+//! binary prints for a grammar.
+//!
+//! A grammar can be given inline, as a string literal (PEG syntax like `'abc'`
+//! and `#` comments isn't valid Rust tokens):
+//!
+//! ```ignore
+//! peg_grammar!( arithmetic, r#"
+//!   Expr   <- Expr '+' Number / Number
+//!   Number <- [0-9]+
+//! "# );
+//!
+//! let tree = arithmetic::parse( b"1+2" );
+//! ```
+//!
+//! or read from a file relative to the crate root:
+//!
+//! ```ignore
+//! #[derive(Grammar)]
+//! #[grammar = "src/arithmetic.peg"]
+//! struct Arithmetic;
+//!
+//! let tree = Arithmetic::parse( b"1+2" );
+//! ```
+//!
+//! Flags of `nailgun::Options` can follow an inline grammar, so with `ast` the
+//! module also gets the typed tree of the grammar and a `parseAst` function:
+//!
+//! ```ignore
+//! peg_grammar!( arithmetic, "Expr <- Expr '+' Number / Number ...", ast );
+//! ```
+#![allow(non_snake_case)]
+#![deny(deprecated)]
+
+extern crate proc_macro;
+extern crate nailgun;
+
+use std::cmp;
+use std::env;
+use std::path::Path;
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+
+/// Expands `peg_grammar!( name, "grammar" )` into `pub mod name` holding the
+/// parser for the grammar. The flags of `nailgun::Options` can follow the
+/// grammar, named like their fields: `peg_grammar!( name, "grammar", ast )`.
+#[proc_macro]
+pub fn peg_grammar( input: TokenStream ) -> TokenStream {
+  let tokens: Vec<TokenTree> = input.into_iter().collect();
+  let ( name, grammar ) = match &tokens[ .. cmp::min( tokens.len(), 3 ) ] {
+    [ TokenTree::Ident( name ),
+      TokenTree::Punct( ref comma ),
+      TokenTree::Literal( literal ) ] if comma.as_char() == ',' => {
+      match stringLiteralValue( &literal.to_string() ) {
+        Some( grammar ) => ( name.to_string(), grammar ),
+        _ => return compileError( "The grammar must be a string literal." )
+      }
+    }
+    _ => return compileError(
+      "Expected a module name and a grammar, like \
+       peg_grammar!( name, \"Rule <- 'a'\" )." )
+  };
+  let options = match grammarOptions( &tokens[ 3 .. ] ) {
+    Ok( options ) => options,
+    Err( message ) => return compileError( &message )
+  };
+
+  match nailgun::codeForGrammar( grammar.as_bytes(), &options ) {
+    Ok( parser ) => moduleTokens( &name, &parser.code, "" ),
+    Err( error ) => compileError( &error.to_string() )
+  }
+}
+
+
+/// Derives a `parse` function for the grammar in the file named by the
+/// `#[grammar = "path"]` attribute. The path is relative to the directory of
+/// the crate's Cargo.toml; the files the grammar imports are relative to the
+/// grammar.
+#[proc_macro_derive(Grammar, attributes(grammar))]
+pub fn deriveGrammar( input: TokenStream ) -> TokenStream {
+  let tokens: Vec<TokenTree> = input.into_iter().collect();
+  let type_name = match typeName( &tokens ) {
+    Some( name ) => name,
+    _ => return compileError( "Grammar can only be derived for structs." )
+  };
+  let relative_path = match grammarAttribute( &tokens ) {
+    Some( path ) => path,
+    _ => return compileError(
+      "Missing #[grammar = \"path/to/grammar.peg\"] attribute." )
+  };
+
+  let manifest_dir = env::var( "CARGO_MANIFEST_DIR" )
+    .unwrap_or( ".".to_string() );
+  let path = Path::new( &manifest_dir ).join( &relative_path );
+  let parser = match nailgun::codeForGrammarFile( &path,
+                                                  &nailgun::Options::new() ) {
+    Ok( parser ) => parser,
+    Err( nailgun::Error::Io( error ) ) => {
+      return compileError( &error.to_string() )
+    }
+    Err( error ) => return compileError(
+      &format!( "{}: {}", path.display(), error ) )
+  };
+
+  let module_name = format!( "__nailgun_{}", type_name );
+  // Including the grammar files makes the compiler rebuild the crate when one
+  // of them changes.
+  let dependencies: Vec<String> = parser.files.iter()
+    .map( |file| format!( "include_bytes!( {:?} )",
+                          file.display().to_string() ) )
+    .collect();
+  let dependency = format!(
+    "const _GRAMMAR_FILES: &'static [&'static [u8]] = &[ {} ];\n",
+    dependencies.join( ", " ) );
+
+  let mut output = moduleTokens( &module_name, &parser.code, &dependency );
+  output.extend( format!(
+    "impl {0} {{
+       pub fn parse<'a>( input: &'a [u8] )
+           -> ::std::result::Result< {1}::Node<'a>, {1}::ParseError > {{
+         {1}::parse( input )
+       }}
+     }}", type_name, module_name ).parse::<TokenStream>().unwrap() );
+  output
+}
+
+
+fn moduleTokens( name: &str, code: &str, extra_items: &str ) -> TokenStream {
+  format!( "#[allow(non_snake_case)] pub mod {} {{\n{}\n{}}}",
+           name, code, extra_items ).parse().unwrap()
+}
+
+
+fn compileError( message: &str ) -> TokenStream {
+  format!( "compile_error!( {:?} );", message ).parse().unwrap()
+}
+
+
+// The options named after the grammar, each following a comma.
+fn grammarOptions( tokens: &[TokenTree] ) -> Result<nailgun::Options, String> {
+  let mut options = nailgun::Options::new();
+  for pair in tokens.chunks( 2 ) {
+    let option = match pair {
+      [ TokenTree::Punct( ref comma ), TokenTree::Ident( option ) ]
+          if comma.as_char() == ',' => option.to_string(),
+      // A trailing comma.
+      [ TokenTree::Punct( ref comma ) ] if comma.as_char() == ',' => break,
+      _ => return Err( "Expected options after the grammar, like \
+                        peg_grammar!( name, \"Rule <- 'a'\", ast )."
+                         .to_string() )
+    };
+    match &option[..] {
+      "ast" => options.ast = true,
+      "bytes" => options.bytes = true,
+      "full_input" => options.full_input = true,
+      "memoize" => options.memoize = true,
+      "public" => options.public = true,
+      _ => return Err( format!( "Unknown option `{}`; the options are ast, \
+                                 bytes, full_input, memoize and public.",
+                                option ) )
+    }
+  }
+  Ok( options )
+}
+
+
+// The identifier following the `struct` keyword.
+fn typeName( tokens: &[TokenTree] ) -> Option<String> {
+  tokens.windows( 2 ).filter_map( |pair| match ( &pair[ 0 ], &pair[ 1 ] ) {
+    ( &TokenTree::Ident( ref keyword ), &TokenTree::Ident( ref name ) )
+        if keyword.to_string() == "struct" => Some( name.to_string() ),
+    _ => None
+  } ).next()
+}
+
+
+// The value of the first `#[grammar = "..."]` attribute.
+fn grammarAttribute( tokens: &[TokenTree] ) -> Option<String> {
+  for token in tokens {
+    let group = match *token {
+      TokenTree::Group( ref group )
+          if group.delimiter() == Delimiter::Bracket => group,
+      _ => continue
+    };
+
+    let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+    match &inner[..] {
+      [ TokenTree::Ident( name ),
+        TokenTree::Punct( ref equals ),
+        TokenTree::Literal( literal ) ]
+          if name.to_string() == "grammar" && equals.as_char() == '=' => {
+        return stringLiteralValue( &literal.to_string() );
+      }
+      _ => ()
+    }
+  }
+  None
+}
+
+
+// Returns the value of the string literal with the given source text, like
+// `"a\nb"` or `r#"a"#`. Returns None for anything but string literals, and for
+// escapes Rust doesn't have.
+fn stringLiteralValue( source: &str ) -> Option<String> {
+  if source.starts_with( 'r' ) {
+    let hashes = source[ 1 .. ].chars().take_while( |x| *x == '#' ).count();
+    let start = hashes + 2;
+    if source.len() < start + hashes + 1 {
+      return None;
+    }
+    return Some( source[ start .. source.len() - hashes - 1 ].to_string() );
+  }
+
+  if !source.starts_with( '"' ) || !source.ends_with( '"' ) ||
+     source.len() < 2 {
+    return None;
+  }
+
+  let mut value = String::new();
+  let mut chars = source[ 1 .. source.len() - 1 ].chars();
+  while let Some( ch ) = chars.next() {
+    if ch != '\\' {
+      value.push( ch );
+      continue;
+    }
+
+    let rest = match chars.next() {
+      Some( 'n' ) => { value.push( '\n' ); continue }
+      Some( 'r' ) => { value.push( '\r' ); continue }
+      Some( 't' ) => { value.push( '\t' ); continue }
+      Some( '0' ) => { value.push( '\0' ); continue }
+      Some( ch @ '\\' ) | Some( ch @ '\'' ) | Some( ch @ '"' ) => {
+        value.push( ch );
+        continue
+      }
+      // A backslash at the end of a line skips the line break and the
+      // leading whitespace of the next line.
+      Some( '\n' ) => chars.as_str().trim_start_matches(
+        |x| x == ' ' || x == '\t' || x == '\n' || x == '\r' ),
+      // Like `\x7f`, for ASCII chars only.
+      Some( 'x' ) => {
+        let rest = chars.as_str();
+        if rest.len() < 2 || !rest.is_char_boundary( 2 ) {
+          return None;
+        }
+        match u8::from_str_radix( &rest[ .. 2 ], 16 ) {
+          Ok( byte ) if byte < 0x80 => value.push( byte as char ),
+          _ => return None
+        }
+        &rest[ 2 .. ]
+      }
+      // Like `\u{3bb}`, where the digits can be separated by underscores.
+      Some( 'u' ) => {
+        let rest = chars.as_str();
+        let end = match rest.find( '}' ) {
+          Some( end ) if rest.starts_with( '{' ) => end,
+          _ => return None
+        };
+        let digits: String =
+          rest[ 1 .. end ].chars().filter( |x| *x != '_' ).collect();
+        let ch = u32::from_str_radix( &digits, 16 ).ok()
+          .and_then( ::std::char::from_u32 );
+        match ch {
+          Some( ch ) => value.push( ch ),
+          _ => return None
+        }
+        &rest[ end + 1 .. ]
+      }
+      _ => return None
+    };
+    chars = rest.chars();
+  }
+  Some( value )
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::stringLiteralValue;
+
+  #[test]
+  fn stringLiteralValue_Works() {
+    assert_eq!( stringLiteralValue( r#""abc""# ), Some( "abc".to_string() ) );
+    assert_eq!( stringLiteralValue( r#""a\n\"b\\""# ),
+                Some( "a\n\"b\\".to_string() ) );
+    assert_eq!( stringLiteralValue( r#""a\
+                                        b""# ),
+                Some( "ab".to_string() ) );
+    assert_eq!( stringLiteralValue( r#""\x41\x7f\'\u{3bb}\u{1_F600}\t""# ),
+                Some( "A\x7f'\u{3bb}\u{1F600}\t".to_string() ) );
+    assert_eq!( stringLiteralValue( r##"r"a\n""## ),
+                Some( r"a\n".to_string() ) );
+    assert_eq!( stringLiteralValue( r###"r#"a"b"#"### ),
+                Some( r#"a"b"#.to_string() ) );
+  }
+
+
+  #[test]
+  fn stringLiteralValue_NotAString() {
+    assert_eq!( stringLiteralValue( "'a'" ), None );
+    assert_eq!( stringLiteralValue( "12" ), None );
+    assert_eq!( stringLiteralValue( "b\"a\"" ), None );
+  }
+
+
+  #[test]
+  fn stringLiteralValue_InvalidEscape() {
+    assert_eq!( stringLiteralValue( r#""\q""# ), None );
+    assert_eq!( stringLiteralValue( r#""\x80""# ), None );
+    assert_eq!( stringLiteralValue( r#""\x4""# ), None );
+    assert_eq!( stringLiteralValue( r#""\u{d800}""# ), None );
+    assert_eq!( stringLiteralValue( r#""\u41""# ), None );
+  }
+}
@@ -0,0 +1,427 @@
+// Copyright 2014 Strahinja Val Markovic
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![allow(non_snake_case)]
+
+#[macro_use]
+extern crate nailgun_macros;
+
+peg_grammar!( sum, r#"
+  Sum    <- Sum '+' Number / Number  # Left-recursive.
+  Number <- [0-9]+
+"# );
+
+peg_grammar!( total, r#"
+  Sum -> i64    <- Sum '+' Number / Number {
+    |node| match node.contents {
+      base::Children( ref nodes ) if nodes.len() == 3 => {
+        Sum_value( &nodes[ 0 ] ) + Number_value( &nodes[ 2 ] )
+      }
+      _ => Number_value( node )
+    }
+  }
+  Number -> i64 <- [0-9]+ { |node| node.matchedText().parse().unwrap() }
+"# );
+
+peg_grammar!( labeled, r#"
+  Pair  <- key:Word '=' value:Word (',' values:Word)*
+  Word  <- [a-z]+
+"# );
+
+peg_grammar!( annotated, r#"
+  List            <- Item (Comma Item)*
+  Item            <- Number / Name
+  @inline Number  <- Digit+
+  @token Name     <- [a-z]+
+  @silent Comma   <- Spacing ',' Spacing
+  @silent Spacing <- ' '*
+  Digit           <- [0-9]
+"# );
+
+peg_grammar!( skipping, r#"
+  Call          <- Name '(' (Name (',' Name)*)? ')'
+  Name          <~ [a-z] [a-z0-9]*
+  @skip Spacing <- (' ' / '#' (!'\n' .)* '\n')*
+"# );
+
+peg_grammar!( anchored, r#"
+  Assignments   <- (Name '=' [0-9]+ ';')*
+  Name          <~ [a-z]+
+  @skip Spacing <- (' ' / '\n' / '#' (!'\n' .)* '\n')*
+"#, full_input );
+
+peg_grammar!( recovering, r#"
+  Block     <- Statement* !.
+  Statement <- (Name '=' Name ';')^SkipLine
+  Name      <- [a-z]+
+  SkipLine  <- (!';' .)* ';'
+"# );
+
+peg_grammar!( committing, r#"
+  Statement <- If / Call
+  If        <- 'if ' % Name ':' Name
+  Call      <- Name '()'
+  Name      <- [a-z]+
+"# );
+
+peg_grammar!( caseless, r#"
+  Select <- 'select'i ' '+ Name 'from'i ' '+ Name
+  Name   <- [a-z]i+ ' '*
+"# );
+
+peg_grammar!( quoting, r#"
+  Quoted <- '"' [^"\\]* '"'
+"# );
+
+peg_grammar!( binary, r#"
+  File   <- Magic Chunk* Text
+  @bytes Magic  <- '\x89PNG\r\n' [\x1A] .
+  @bytes Chunk  <- 'id'i [\x80-\xFF]
+  Text   <- [^\x00-\x1F]*
+"# );
+
+peg_grammar!( fixed_width, r#"
+  Record <- Date ' ' Code{1,3}
+  Date   <- [0-9]{4} '-' [0-9]{2} '-' [0-9]{2}
+  Code   <- [A-Z]{2,} ';'
+"# );
+
+peg_grammar!( lists, r#"
+  Lists  <- List(Number, ',') ';' List(Word, Space)
+  List(Item, Sep) <- Item (Sep Item)*
+  Number <- [0-9]+
+  Word   <- [a-z]+
+  Space  <- ' '+
+"# );
+
+peg_grammar!( statements, r#"
+  Statements <- Statement+
+  @public Statement <- Name '=' Value ';'
+  @public Value <- [0-9]+ / Name
+  Name <- [a-z]+
+"# );
+
+peg_grammar!( identifiers, r#"
+  Identifier <- [\p{XID_Start}_] [\p{XID_Continue}]*
+"# );
+
+peg_grammar!( escaped, "Lambda <- '\u{3bb}' [\x61-\x7a]+" );
+
+peg_grammar!( typed, r#"
+  Sum   <- Sum ('+' / '-') Value / Value
+  Value <- [0-9]+ / Name
+  Name  <- [a-z]+
+"#, ast );
+
+#[derive(Grammar)]
+#[grammar = "../examples/arithmetic.peg"]
+struct Arithmetic;
+
+#[derive(Grammar)]
+#[grammar = "tests/grammars/assignments.peg"]
+struct Assignments;
+
+
+#[test]
+fn peg_grammar_Parses() {
+  let node = sum::parse( b"1+22+3" ).unwrap();
+  assert_eq!( node.name, "Sum" );
+  assert_eq!( node.end, 6 );
+  assert!( sum::parse( b"+1" ).is_err() );
+}
+
+
+#[test]
+fn peg_grammar_DecodesEscapes() {
+  assert!( escaped::parse( "\u{3bb}xy".as_bytes() ).is_ok() );
+  assert!( escaped::parse( b"\\u{3bb}xy" ).is_err() );
+}
+
+
+#[test]
+fn peg_grammar_BuildsTypedTree() {
+  let sum = match typed::parseAst( b"12-x" ).unwrap() {
+    typed::ast::Sum::Sum( left, operator, right ) => {
+      assert_eq!( operator, "-" );
+      match *right {
+        typed::ast::Value::Name( ref name ) => assert_eq!( name.text, "x" ),
+        _ => panic!( "expected a name" )
+      }
+      left
+    }
+    _ => panic!( "expected a sum" )
+  };
+  match *sum {
+    typed::ast::Sum::Value( ref value ) => match **value {
+      typed::ast::Value::Alternative1( ref digits ) => {
+        assert_eq!( digits, "12" )
+      }
+      _ => panic!( "expected digits" )
+    },
+    _ => panic!( "expected a value" )
+  }
+}
+
+
+#[test]
+fn peg_grammar_RunsActions() {
+  assert_eq!( total::parseValue( b"1+22+3" ).unwrap(), 26 );
+  assert_eq!( total::parseValue( b"7" ).unwrap(), 7 );
+  assert!( total::parseValue( b"+1" ).is_err() );
+}
+
+
+#[test]
+fn peg_grammar_Labels() {
+  let node = labeled::parse( b"a=bc,d,e" ).unwrap();
+  assert_eq!( node.child( "key" ).unwrap().matchedText(), "a" );
+  assert_eq!( node.child( "value" ).unwrap().matchedText(), "bc" );
+  let values: Vec<String> = node.children_labeled( "values" ).iter()
+    .map( |node| node.matchedText() ).collect();
+  assert_eq!( values, vec!( "d", "e" ) );
+}
+
+
+#[test]
+fn peg_grammar_Annotations() {
+  let node = annotated::parse( b"12 , ab" ).unwrap();
+  let names: Vec<&str> = node.preOrder().map( |node| node.name ).collect();
+  assert_eq!( names,
+              vec!( "List", "Item", "Digit", "Digit", "Item", "Name" ) );
+  assert_eq!( node.preOrder().last().unwrap().contents,
+              annotated::Data( b"ab" ) );
+}
+
+
+#[test]
+fn peg_grammar_SkipsBetweenElements() {
+  let node = skipping::parse( b"f ( a1,  b # comment\n , c )" ).unwrap();
+  let names: Vec<String> = node.preOrder()
+    .filter( |node| node.name == "Name" )
+    .map( |node| node.matchedText() ).collect();
+  assert_eq!( names, vec!( "f", "a1", "b", "c" ) );
+  assert!( node.preOrder().all( |node| node.name != "Spacing" ) );
+  assert!( skipping::parse( b"f(a 1)" ).is_err() );
+}
+
+
+#[test]
+fn peg_grammar_SkipsAroundStartRule() {
+  let node = skipping::parse( b"  # comment\n f(a)" ).unwrap();
+  assert_eq!( node.matchedText(), "f(a)" );
+
+  let ( node, consumed ) = skipping::parse_prefix( b" f(a) g" ).unwrap();
+  assert_eq!( node.matchedText(), "f(a)" );
+  assert_eq!( consumed, 6 );
+}
+
+
+#[test]
+fn peg_grammar_FullInputAllowsTrailingSkip() {
+  assert!( anchored::parse( b"x = 1;\n" ).is_ok() );
+  assert!( anchored::parse( b"x = 1; # done\n" ).is_ok() );
+
+  let error = anchored::parse( b"x = 1; y" ).unwrap_err();
+  assert_eq!( error.offset, 7 );
+}
+
+
+#[test]
+fn peg_grammar_ParsesStreams() {
+  let mut names = vec!();
+  skipping::parseStream( &b"f(a)g(b, c)h()"[..], 8, |node| {
+    names.push( node.preOrder().nth( 1 ).unwrap().matchedText() );
+  } ).unwrap();
+  assert_eq!( names, vec!( "f", "g", "h" ) );
+
+  match skipping::parseStream( &b"f(a)g(bcdefgh)"[..], 8, |_| () ) {
+    Err( skipping::StreamError::WindowExceeded( offset ) ) => {
+      assert_eq!( offset, 4 )
+    }
+    _ => panic!( "Should exceed the window." )
+  }
+}
+
+
+#[test]
+fn peg_grammar_RecoversFromErrors() {
+  let node = recovering::parse( b"a=b;a=;c=d;e;" ).unwrap();
+  let statements: Vec<bool> = node.preOrder()
+    .filter( |node| node.name == "Statement" )
+    .map( |node| node.isError() ).collect();
+  assert_eq!( statements, vec!( false, true, false, true ) );
+
+  let errors = node.errors();
+  assert_eq!( ( errors[ 0 ].start, errors[ 0 ].end ), ( 4, 7 ) );
+  assert_eq!( errors[ 0 ].contents,
+              recovering::NodeContents::Error(
+                vec!( recovering::Expected::Rule( "Name" ) ) ) );
+  assert_eq!( ( errors[ 1 ].start, errors[ 1 ].end ), ( 11, 13 ) );
+}
+
+
+#[test]
+fn peg_grammar_IgnoresCase() {
+  assert!( caseless::parse( b"select a from b" ).is_ok() );
+  assert!( caseless::parse( b"SELECT Ab FROM bC" ).is_ok() );
+
+  let error = caseless::parse( b"SELECT a FRUM b" ).unwrap_err();
+  assert_eq!( error.offset, 9 );
+  assert_eq!( error.expected,
+              vec!( caseless::Expected::Literal( b" " ),
+                    caseless::Expected::LiteralIgnoringCase( b"from" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_NegatedClass() {
+  assert!( quoting::parse( b"\"a b\"" ).is_ok() );
+  assert!( quoting::parse( "\"\u{e9}\"".as_bytes() ).is_ok() );
+  assert!( quoting::parse( b"\"\xff\"" ).is_ok() );
+
+  let error = quoting::parse( b"\"a\\\"" ).unwrap_err();
+  assert_eq!( error.offset, 2 );
+  assert_eq!( error.expected,
+              vec!( quoting::Expected::Literal( b"\"" ),
+                    quoting::Expected::Class( b"^\"\\\\" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_ByteMode() {
+  assert!( binary::parse( b"\x89PNG\r\n\x1a\xffID\x80iD\xfe\xc3\xa9" ).is_ok() );
+  assert!( binary::parse( b"\x89PNG\r\n\x1a\x00" ).is_ok() );
+
+  // Byte mode literals are bytes, and `.` takes one byte of a UTF-8 char.
+  assert!( binary::parse( "\u{89}PNG\r\n\x1a\u{e9}".as_bytes() ).is_err() );
+  assert!( binary::parse( b"\x89PNG\r\n\x1a\xc3\xa9\xc3\xa9" ).is_ok() );
+
+  let error = binary::parse( b"\x89PNG\r\n\x1a" ).unwrap_err();
+  assert_eq!( error.offset, 7 );
+  assert_eq!( error.expected, vec!( binary::Expected::AnyByte ) );
+
+  let error = binary::parse( b"\x89PNG\r\n\x1b\x00" ).unwrap_err();
+  assert_eq!( error.offset, 6 );
+  assert_eq!( error.expected, vec!( binary::Expected::Class( b"\\x1A" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_CountedRepetition() {
+  let node = fixed_width::parse( b"2014-03-09 AB;XYZ;QQ;" ).unwrap();
+  assert_eq!( node.end, 21 );
+
+  // At most three codes are matched.
+  let node = fixed_width::parse( b"2014-03-09 AB;CD;EF;GH;" ).unwrap();
+  assert_eq!( node.end, 20 );
+
+  let error = fixed_width::parse( b"14-03-09 AB;" ).unwrap_err();
+  assert_eq!( error.offset, 2 );
+  assert_eq!( error.expected,
+              vec!( fixed_width::Expected::Class( b"0-9" ) ) );
+
+  let error = fixed_width::parse( b"2014-03-09 A;" ).unwrap_err();
+  assert_eq!( error.offset, 12 );
+  assert_eq!( error.expected,
+              vec!( fixed_width::Expected::Class( b"A-Z" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_ParameterizedRules() {
+  let node = lists::parse( b"1,22,3;ab  cd e" ).unwrap();
+  assert_eq!( node.end, 15 );
+
+  let error = lists::parse( b"1,22;" ).unwrap_err();
+  assert_eq!( error.offset, 5 );
+  assert_eq!( error.expected,
+              vec!( lists::Expected::Rule( "List_Word_Space" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_EntryPoints() {
+  let node = statements::parse( b"a=1;b=a;" ).unwrap();
+  assert_eq!( node.name, "Statements" );
+
+  let node = statements::parse_Statement( b"a=1;" ).unwrap();
+  assert_eq!( node.name, "Statement" );
+  assert_eq!( node.end, 4 );
+
+  let node = statements::parse_Value( b"abc" ).unwrap();
+  assert_eq!( node.name, "Value" );
+  assert_eq!( node.end, 3 );
+
+  let error = statements::parse_Value( b"=" ).unwrap_err();
+  assert_eq!( error.offset, 0 );
+}
+
+
+#[test]
+fn peg_grammar_ParsePrefix() {
+  let ( node, consumed ) = statements::parse_prefix( b"a=1;b=;" ).unwrap();
+  assert_eq!( node.name, "Statements" );
+  assert_eq!( consumed, 4 );
+
+  assert!( statements::parse_prefix( b"=" ).is_err() );
+}
+
+
+#[test]
+fn peg_grammar_UnicodeProperties() {
+  assert!( identifiers::parse( b"_foo1" ).is_ok() );
+  assert!( identifiers::parse( "\u{3bb}\u{3b1}\u{d7}".as_bytes() ).is_ok() );
+
+  let error = identifiers::parse( b"1foo" ).unwrap_err();
+  assert_eq!( error.offset, 0 );
+  assert_eq!( error.expected,
+              vec!( identifiers::Expected::Class( br"\p{XID_Start}_" ) ) );
+}
+
+
+#[test]
+fn peg_grammar_CutCommitsToAlternative() {
+  assert!( committing::parse( b"if a:b" ).is_ok() );
+  assert!( committing::parse( b"iffy()" ).is_ok() );
+
+  // Without the cut, the error would be about the `Call` alternative.
+  let error = committing::parse( b"if a;b" ).unwrap_err();
+  assert_eq!( error.offset, 4 );
+  assert_eq!( error.expected,
+              vec!( committing::Expected::Literal( b":" ),
+                    committing::Expected::Class( b"a-z" ) ) );
+}
+
+
+#[test]
+fn derive_Grammar_Parses() {
+  let node = Arithmetic::parse( b"2+5*3*(2*8+6)" ).unwrap();
+  assert_eq!( node.name, "Expr" );
+  assert_eq!( node.end, 13 );
+
+  let error = Arithmetic::parse( b"(" ).unwrap_err();
+  assert_eq!( error.offset, 1 );
+}
+
+
+#[test]
+fn derive_Grammar_Imports() {
+  let node = Assignments::parse( b"x=42;yz=7;" ).unwrap();
+  assert_eq!( node.name, "Assignments" );
+  assert_eq!( node.end, 10 );
+
+  let error = Assignments::parse( b"x=;" ).unwrap_err();
+  assert_eq!( error.offset, 2 );
+  assert_eq!( error.expected,
+              vec!( __nailgun_Assignments::Expected::Rule( "token_Number" ) ) );
+}
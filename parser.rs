@@ -12,71 +12,261 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 #![allow(non_snake_case)]
-#![cfg_attr(test, allow(dead_code))]
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 #![deny(deprecated)]
 
-#[cfg(not(test))]
-pub use base::{Node, ParseState, Data, Children, NodeContents, PreOrderNodes};
+// These aren't gated on cfg(not(test)) because generated parsers are included
+// in other crates, and parse() must still exist when those are tested.
+pub use self::base::{Node, ParseState, Data, Children, NodeContents,
+                     PreOrderNodes, ParseError, Expected, StreamError, Edit,
+                     unicodeProperty};
+use std::io;
 
 #[macro_use]
 mod base;
 
 macro_rules! rule(
+  // Typed rules also get a function that runs the rule's semantic action on
+  // the node the rule produced.
+  (
+    memo $name:ident <- $body:expr
+      => $value:ident -> $value_type:ty = $action:expr
+  ) => (
+    rule!( memo $name <- $body );
+    rule!( @value $value, $value_type, $action );
+  );
+  (
+    leftrec $name:ident <- $body:expr
+      => $value:ident -> $value_type:ty = $action:expr
+  ) => (
+    rule!( leftrec $name <- $body );
+    rule!( @value $value, $value_type, $action );
+  );
   (
     $name:ident <- $body:expr
+      => $value:ident -> $value_type:ty = $action:expr
+  ) => (
+    rule!( $name <- $body );
+    rule!( @value $value, $value_type, $action );
+  );
+  // The rule's mode says what it produces: a `node` named after the rule, no
+  // nodes if it's `silent`, or the nodes of its body if it's `inline`.
+  (
+    memo $mode:ident $name:ident <- $body:expr
   ) => (
     pub fn $name<'a>( parse_state: &base::ParseState<'a> )
          -> std::option::Option< base::ParseResult<'a> > {
-      use base::Expression;
-      use base::Node;
-      use base::ParseResult;
-      use std::clone::Clone;
-      use std::option::Option::{Some, None};
-
-      match $body.apply( parse_state ) {
-        Some( result ) => {
-          let state = result.parse_state.clone();
-          Some( ParseResult::oneNode(
-              Node::withChildren( stringify!( $name ), result.nodes ), state ) )
-        }
-        _ => None
-      }
+      base::memoized( stringify!( $name ), parse_state, |parse_state| {
+        rule!( @apply $mode $name, $body, parse_state )
+      } )
+    }
+  );
+  (
+    leftrec $mode:ident $name:ident <- $body:expr
+  ) => (
+    pub fn $name<'a>( parse_state: &base::ParseState<'a> )
+         -> std::option::Option< base::ParseResult<'a> > {
+      base::leftRecursive( stringify!( $name ), parse_state, |parse_state| {
+        rule!( @apply $mode $name, $body, parse_state )
+      } )
+    }
+  );
+  (
+    memo $name:ident <- $body:expr
+  ) => (
+    rule!( memo node $name <- $body );
+  );
+  (
+    leftrec $name:ident <- $body:expr
+  ) => (
+    rule!( leftrec node $name <- $body );
+  );
+  (
+    $mode:ident $name:ident <- $body:expr
+  ) => (
+    pub fn $name<'a>( parse_state: &base::ParseState<'a> )
+         -> std::option::Option< base::ParseResult<'a> > {
+      rule!( @apply $mode $name, $body, parse_state )
+    }
+  );
+  (
+    $name:ident <- $body:expr
+  ) => (
+    rule!( node $name <- $body );
+  );
+  (
+    @apply node $name:ident, $body:expr, $parse_state:ident
+  ) => (
+    base::namedNode( stringify!( $name ), $parse_state, |parse_state| {
+      base::Expression::apply( $body, parse_state )
+    } )
+  );
+  (
+    @apply silent $name:ident, $body:expr, $parse_state:ident
+  ) => (
+    base::Expression::apply( $body, $parse_state ).map( |result| {
+      base::ParseResult::fromParseState( result.parse_state )
+    } )
+  );
+  (
+    @apply inline $name:ident, $body:expr, $parse_state:ident
+  ) => (
+    base::Expression::apply( $body, $parse_state )
+  );
+  (
+    @value $value:ident, $value_type:ty, $action:expr
+  ) => (
+    pub fn $value<'a>( node: &base::Node<'a> ) -> $value_type {
+      base::applyAction( node, $action )
     }
   );
 );
 
-#[cfg(not(test))]
-pub fn parse<'a>( input: &'a [u8] ) -> Option< Node<'a> > {
-  let parse_state = ParseState { input: input, offset: 0 };
-  match rules::Grammar( &parse_state ) {
-    Some( result ) => Some( result.nodes.into_iter().next().unwrap() ),
-    _ => None
+// Whether `parse` fails unless the start rule matches all of its input.
+const FULL_INPUT: bool = false;
+
+// The `@skip` rule of the grammar, if it has one. It's matched before and after
+// the start rule, like it is between the elements of rules.
+const SKIP_RULE: Option<base::Rule> = None;
+
+
+pub fn parse<'a>( input: &'a [u8] ) -> Result< Node<'a>, ParseError > {
+  parseFrom( rules::Grammar, ParseState::new( input ), input )
+}
+
+
+/// Parses the start of `input` with the start rule, returning the node of the
+/// match and the number of bytes it consumed, counting the skipped input after
+/// the match. Unlike `parse`, this never fails because of input left after it.
+pub fn parse_prefix<'a>( input: &'a [u8] )
+    -> Result< ( Node<'a>, usize ), ParseError > {
+  parsePrefixFrom( rules::Grammar, &ParseState::new( input ), input )
+}
+
+
+/// Parses `input`, which is the input `previous` was parsed from with `edit`
+/// applied to it. The parts of `previous` the edit couldn't have changed are
+/// reused instead of being parsed again, so a small edit is quick to reparse
+/// no matter how large the input is. The reused parts are shared with
+/// `previous`, so its input has to live as long as the new tree.
+///
+/// The reused nodes don't carry the expectations that failed while they were
+/// parsed, and those make up what a `ParseError` says was expected. So if the
+/// reparse fails, or recovered from errors, the input is parsed again in full
+/// to report the same errors `parse` would. Memo entries of the previous parse
+/// aren't reused since `parse` doesn't keep them, but the reused nodes are
+/// remembered for their rules like freshly parsed ones.
+pub fn reparse<'a>( previous: &Node<'a>, edit: &Edit, input: &'a [u8] )
+    -> Result< Node<'a>, ParseError > {
+  let parse_state = ParseState::new( input );
+  parse_state.context.reuse( previous, edit );
+  let result = parseFrom( rules::Grammar, parse_state, input );
+  let has_errors = match result {
+    Ok( ref node ) => !node.errors().is_empty(),
+    Err( _ ) => true
+  };
+  if has_errors {
+    parse( input )
+  } else {
+    result
   }
 }
 
 
-mod rules {
-  #![no_implicit_prelude]
+// Parses the input of `parse_state` starting with `rule`. The `parse_<rule>`
+// functions of public rules start with their own rule.
+fn parseFrom<'a>( rule: base::Rule,
+                  parse_state: ParseState<'a>,
+                  input: &'a [u8] ) -> Result< Node<'a>, ParseError > {
+  let ( node, consumed ) = try!( parsePrefixFrom( rule, &parse_state, input ) );
+  if FULL_INPUT && consumed < input.len() {
+    return Err( parse_state.context.unconsumedError( input, consumed ) );
+  }
+  Ok( node )
+}
+
+
+fn parsePrefixFrom<'a>( rule: base::Rule,
+                        parse_state: &ParseState<'a>,
+                        input: &'a [u8] )
+    -> Result< ( Node<'a>, usize ), ParseError > {
+  match rule( &skip( parse_state ) ) {
+    Some( result ) => {
+      let consumed = skip( &result.parse_state ).offset;
+      Ok( ( result.nodes.into_iter().next().unwrap(), consumed ) )
+    }
+    _ => Err( parse_state.context.parseError( input ) )
+  }
+}
+
+
+// Returns the state following the input the skip rule matches at
+// `parse_state`. What the skip rule expected isn't part of parse errors, since
+// the input around the start rule is never wrong for lacking skipped text.
+fn skip<'a>( parse_state: &ParseState<'a> ) -> ParseState<'a> {
+  let result = SKIP_RULE.and_then( |rule| {
+    parse_state.context.quietly( || rule( parse_state ) )
+  } );
+  match result {
+    Some( result ) => result.parse_state,
+    _ => parse_state.clone()
+  }
+}
 
-  use base;
+
+/// Parses the input read from `reader` as a series of matches of the start
+/// rule, calling `callback` with the node of every match. At most `window`
+/// bytes of input are held in memory; see `base::StreamInput`.
+pub fn parseStream<R, F>( reader: R, window: usize, mut callback: F )
+    -> Result< (), StreamError >
+    where R: io::Read, F: FnMut( &Node ) {
+  let mut input = base::StreamInput::new( reader, window );
+  while let Some( () ) = try!( input.parseNext( rules::Grammar, |nodes| {
+    for node in nodes {
+      callback( node );
+    }
+  } ) ) {}
+  Ok(())
+}
+
+
+mod rules {
+  use super::base;
   use std;
 
   // RULES START
 
-  rule!( Grammar <- seq!( ex!( Spacing ), plus!( ex!( Definition ) ), ex!( EndOfFile ) ) );
-  rule!( Definition <- seq!( ex!( Identifier ), ex!( ARROW ), ex!( Expression ) ) );
+  rule!( Grammar <- seq!( ex!( Spacing ), star!( ex!( Import ) ), plus!( ex!( Definition ) ), ex!( EndOfFile ) ) );
+  rule!( Import <- seq!( ex!( IMPORT ), ex!( Literal ), opt!( seq!( ex!( AS ), ex!( Identifier ) ) ) ) );
+  rule!( Definition <- seq!( star!( ex!( Annotation ) ), ex!( Identifier ), opt!( ex!( Parameters ) ), opt!( ex!( ReturnType ) ), ex!( ARROW ), ex!( Expression ), opt!( ex!( Action ) ) ) );
+  rule!( Annotation <- seq!( ex!( AT ), ex!( Identifier ) ) );
+  rule!( Parameters <- seq!( ex!( OPEN ), ex!( Identifier ), star!( seq!( ex!( COMMA ), ex!( Identifier ) ) ), ex!( CLOSE ) ) );
   rule!( Expression <- seq!( ex!( Sequence ), star!( seq!( ex!( SLASH ), ex!( Sequence ) ) ) ) );
   rule!( Sequence <- star!( ex!( Prefix ) ) );
-  rule!( Prefix <- seq!( opt!( or!( ex!( AND ), ex!( NOT ), ex!( FUSE ) ) ), ex!( Suffix ) ) );
-  rule!( Suffix <- seq!( ex!( Primary ), opt!( or!( ex!( QUESTION ), ex!( STAR ), ex!( PLUS ) ) ) ) );
-  rule!( Primary <- or!( seq!( ex!( Identifier ), not!( ex!( ARROW ) ) ), seq!( ex!( OPEN ), ex!( Expression ), ex!( CLOSE ) ), ex!( Literal ), ex!( Class ), ex!( DOT ) ) );
-  rule!( Identifier <- seq!( fuse!( seq!( ex!( IdentStart ), star!( ex!( IdentCont ) ) ) ), ex!( Spacing ) ) );
+  rule!( Prefix <- seq!( opt!( ex!( Label ) ), opt!( or!( ex!( AND ), ex!( NOT ), ex!( FUSE ) ) ), ex!( Suffix ), opt!( ex!( Recovery ) ) ) );
+  rule!( Suffix <- seq!( ex!( Primary ), opt!( or!( ex!( QUESTION ), ex!( STAR ), ex!( PLUS ), ex!( Repetition ) ) ) ) );
+  rule!( Primary <- or!( seq!( ex!( Call ), not!( or!( ex!( ARROW ), ex!( RETURNS ) ) ) ), seq!( ex!( Identifier ), not!( seq!( opt!( ex!( Parameters ) ), or!( ex!( ARROW ), ex!( RETURNS ) ) ) ) ), seq!( ex!( OPEN ), ex!( Expression ), ex!( CLOSE ) ), ex!( Literal ), ex!( Class ), ex!( DOT ), ex!( CUT ) ) );
+  rule!( Call <- seq!( and!( seq!( ex!( Name ), lit!( "(" ) ) ), ex!( Identifier ), ex!( OPEN ), ex!( Expression ), star!( seq!( ex!( COMMA ), ex!( Expression ) ) ), ex!( CLOSE ) ) );
+  rule!( Identifier <- seq!( fuse!( ex!( Name ) ), ex!( Spacing ) ) );
+  rule!( Name <- seq!( ex!( IdentStart ), star!( ex!( IdentCont ) ), star!( seq!( lit!( "::" ), ex!( IdentStart ), star!( ex!( IdentCont ) ) ) ) ) );
   rule!( IdentStart <- class!( "a-zA-Z_" ) );
   rule!( IdentCont <- or!( ex!( IdentStart ), class!( "0-9" ) ) );
-  rule!( Literal <- seq!( fuse!( or!( seq!( class!( "'" ), star!( seq!( not!( class!( "'" ) ), ex!( Char ) ) ), class!( "'" ) ), seq!( class!( "\"" ), star!( seq!( not!( class!( "\"" ) ), ex!( Char ) ) ), class!( "\"" ) ) ) ), ex!( Spacing ) ) );
-  rule!( Class <- seq!( lit!( "[" ), star!( seq!( not!( lit!( "]" ) ), ex!( Range ) ) ), lit!( "]" ), ex!( Spacing ) ) );
+  rule!( Literal <- seq!( fuse!( or!( seq!( class!( "'" ), star!( seq!( not!( class!( "'" ) ), ex!( Char ) ) ), class!( "'" ) ), seq!( class!( "\"" ), star!( seq!( not!( class!( "\"" ) ), ex!( Char ) ) ), class!( "\"" ) ) ) ), opt!( ex!( IgnoreCase ) ), ex!( Spacing ) ) );
+  rule!( Class <- seq!( lit!( "[" ), opt!( ex!( Negation ) ), star!( seq!( not!( lit!( "]" ) ), or!( ex!( Property ), ex!( Range ) ) ) ), lit!( "]" ), opt!( ex!( IgnoreCase ) ), ex!( Spacing ) ) );
   rule!( Range <- or!( seq!( ex!( Char ), lit!( "-" ), ex!( Char ) ), ex!( Char ) ) );
-  rule!( Char <- or!( seq!( lit!( "\\" ), class!( "nrt'\"[]\\" ) ), seq!( lit!( "\\" ), class!( "0-2" ), class!( "0-7" ), class!( "0-7" ) ), seq!( lit!( "\\" ), class!( "0-7" ), opt!( class!( "0-7" ) ) ), seq!( not!( lit!( "\\" ) ), &base::Dot ) ) );
+  rule!( Char <- or!( seq!( lit!( "\\" ), class!( "nrt'\"[]\\\\^" ) ), seq!( lit!( "\\x" ), class!( "0-9a-fA-F" ), class!( "0-9a-fA-F" ) ), seq!( lit!( "\\" ), class!( "0-2" ), class!( "0-7" ), class!( "0-7" ) ), seq!( lit!( "\\" ), class!( "0-7" ), opt!( class!( "0-7" ) ) ), seq!( not!( lit!( "\\" ) ), &base::Dot ) ) );
+  rule!( Negation <- lit!( "^" ) );
+  rule!( Property <- seq!( lit!( "\\p{" ), ex!( PropertyName ), lit!( "}" ) ) );
+  rule!( PropertyName <- fuse!( plus!( class!( "a-zA-Z0-9_" ) ) ) );
+  rule!( IgnoreCase <- seq!( lit!( "i" ), not!( ex!( IdentCont ) ) ) );
+  rule!( Repetition <- seq!( ex!( LBRACE ), ex!( Count ), opt!( seq!( ex!( COMMA ), opt!( ex!( Count ) ) ) ), ex!( RBRACE ) ) );
+  rule!( Count <- seq!( fuse!( plus!( class!( "0-9" ) ) ), ex!( Spacing ) ) );
+  rule!( Label <- seq!( ex!( Identifier ), ex!( COLON ) ) );
+  rule!( Recovery <- seq!( ex!( CARET ), ex!( Suffix ) ) );
+  rule!( ReturnType <- seq!( ex!( RETURNS ), ex!( RustType ) ) );
+  rule!( RustType <- fuse!( plus!( seq!( not!( ex!( ARROW ) ), not!( ex!( EndOfLine ) ), &base::Dot ) ) ) );
+  rule!( Action <- seq!( lit!( "{" ), ex!( ActionCode ), lit!( "}" ), ex!( Spacing ) ) );
+  rule!( ActionCode <- fuse!( star!( or!( seq!( lit!( "{" ), ex!( ActionCode ), lit!( "}" ) ), seq!( not!( lit!( "{" ) ), not!( lit!( "}" ) ), &base::Dot ) ) ) ) );
   rule!( ARROW <- or!( ex!( FUSEARROW ), ex!( LEFTARROW ) ) );
   rule!( LEFTARROW <- seq!( lit!( "<-" ), ex!( Spacing ) ) );
   rule!( FUSEARROW <- seq!( lit!( "<~" ), ex!( Spacing ) ) );
@@ -90,6 +280,16 @@ mod rules {
   rule!( CLOSE <- seq!( lit!( ")" ), ex!( Spacing ) ) );
   rule!( DOT <- seq!( lit!( "." ), ex!( Spacing ) ) );
   rule!( FUSE <- seq!( lit!( "~" ), ex!( Spacing ) ) );
+  rule!( RETURNS <- seq!( lit!( "->" ), ex!( Spacing ) ) );
+  rule!( COLON <- seq!( lit!( ":" ), ex!( Spacing ) ) );
+  rule!( AT <- seq!( lit!( "@" ), ex!( Spacing ) ) );
+  rule!( CARET <- seq!( lit!( "^" ), ex!( Spacing ) ) );
+  rule!( CUT <- seq!( lit!( "%" ), ex!( Spacing ) ) );
+  rule!( LBRACE <- seq!( lit!( "{" ), ex!( Spacing ) ) );
+  rule!( RBRACE <- seq!( lit!( "}" ), ex!( Spacing ) ) );
+  rule!( COMMA <- seq!( lit!( "," ), ex!( Spacing ) ) );
+  rule!( IMPORT <- seq!( lit!( "import" ), not!( ex!( IdentCont ) ), ex!( Spacing ) ) );
+  rule!( AS <- seq!( lit!( "as" ), not!( ex!( IdentCont ) ), ex!( Spacing ) ) );
   rule!( Spacing <- fuse!( star!( or!( ex!( Space ), ex!( Comment ) ) ) ) );
   rule!( Comment <- fuse!( seq!( lit!( "#" ), star!( seq!( not!( ex!( EndOfLine ) ), &base::Dot ) ), ex!( EndOfLine ) ) ) );
   rule!( Space <- or!( lit!( " " ), lit!( "\t" ), ex!( EndOfLine ) ) );
@@ -101,7 +301,8 @@ mod rules {
   #[cfg(test)]
   mod tests {
     use super::{EndOfFile, EndOfLine, Space, Comment, Spacing, Char, Range,
-                Class, Literal, Identifier, Definition, Grammar};
+                Class, Literal, Identifier, Definition, Action, Grammar,
+                Repetition, Primary, Import};
 
     macro_rules! consumes(
       (
@@ -148,6 +349,83 @@ mod rules {
                                     '\\' [0-2][0-7][0-7] /
                                     '\\' [0-7][0-7]? /
                                     !'\\' ."# ) );
+      assert!( consumes!( Definition,
+                          "Number -> u32 <- [0-9]+ { |n| n.len() as u32 }" ) );
+      assert!( !consumes!( Definition, "Number -> <- [0-9]+" ) );
+      assert!( consumes!( Definition, "Sum <- lhs:Term '+' rhs : Term" ) );
+      assert!( consumes!( Definition, "@silent Spacing <- [ ]*" ) );
+      assert!( consumes!( Definition, "@memo @ inline\nA <- 'a'" ) );
+      assert!( consumes!( Definition, "List <- items:(Item (',' Item)*)?" ) );
+      assert!( consumes!( Definition, "Block <- '{' s:Statement*^Skip '}'^''" ) );
+      assert!( consumes!( Definition, "If <- 'if' % Cond Body / 'i' % Body" ) );
+      assert!( consumes!( Definition, "List(X, Sep) <- X (Sep X)*" ) );
+      assert!( consumes!( Definition, "Pair( A,B ) -> u8 <- A B { |n| 1 }" ) );
+      assert!( !consumes!( Definition, "List() <- 'a'" ) );
+    }
+
+    #[test]
+    fn Import_Works() {
+      assert!( consumes!( Import, "import \"lexer.peg\"\n" ) );
+      assert!( consumes!( Import, "import 'lexer.peg' as lex" ) );
+
+      assert!( !consumes!( Import, "imports 'lexer.peg'" ) );
+      assert!( !consumes!( Import, "import lexer" ) );
+      assert!( !consumes!( Import, "import 'lexer.peg' as" ) );
+    }
+
+    #[test]
+    fn Primary_Calls() {
+      assert!( consumes!( Primary, "List(Item, ',')" ) );
+      assert!( consumes!( Primary, "List(Item / 'x'+, List(A, B))" ) );
+      assert!( consumes!( Primary, "lex::List(lex::Item, ',')" ) );
+      assert!( !consumes!( Primary, "List (Item, ',')" ) );
+    }
+
+    #[test]
+    fn Action_Works() {
+      assert!( consumes!( Action, "{ |n| n.len() }" ) );
+      assert!( consumes!( Action, "{ |n| { let x = { 1 }; x } }  \n" ) );
+      assert!( !consumes!( Action, "{ |n| { n }" ) );
+    }
+
+    #[test]
+    fn reparse_MatchesFullParse() {
+      use std::cmp;
+      use super::super::{parse, reparse, Edit};
+
+      let input = b"A <- B 'x' / C\nB <- [a-z]+ # letters\nC <- 'c'*\n";
+      let previous = parse( input ).unwrap();
+      let edits = [ Edit::new( 0, 1, b"Top" ),
+                    Edit::new( 7, 10, b"\"y\" D" ),
+                    Edit::new( 15, 15, b"# comment\n" ),
+                    Edit::new( 21, 24, b"0-9" ),
+                    Edit::new( 47, 47, b"D <- 'd'\n" ) ];
+      for edit in edits.iter() {
+        let edited = edit.apply( input );
+        assert_eq!( reparse( &previous, edit, &edited ), parse( &edited ) );
+      }
+
+      let edit = Edit::new( 0, 1, b"(" );
+      let edited = edit.apply( input );
+      assert_eq!( reparse( &previous, &edit, &edited ), parse( &edited ) );
+
+      // Edits all over a larger grammar, most of which make it invalid.
+      let input = include_bytes!( "examples/nailgun.peg" );
+      let previous = parse( input ).unwrap();
+      let fragments: [&[u8]; 10] = [ b"", b"(", b")", b"/", b"'", b" <- ",
+                                     b"#", b"\n", b"x", b"^" ];
+      let mut seed: usize = 1;
+      let mut random = |limit: usize| {
+        seed = ( seed * 1103515245 + 12345 ) % ( 1 << 31 );
+        ( seed >> 8 ) % limit
+      };
+      for _ in 0 .. 300 {
+        let start = random( input.len() );
+        let end = start + random( cmp::min( 4, input.len() - start ) + 1 );
+        let edit = Edit::new( start, end, fragments[ random( 10 ) ] );
+        let edited = edit.apply( input );
+        assert_eq!( reparse( &previous, &edit, &edited ), parse( &edited ) );
+      }
     }
 
     #[test]
@@ -158,6 +436,11 @@ mod rules {
       assert!( consumes!( Identifier, "a123" ) );
       assert!( consumes!( Identifier, "a  \n" ) );
 
+      assert!( consumes!( Identifier, "lexer::Number" ) );
+      assert!( consumes!( Identifier, "a::b::c " ) );
+
+      assert!( !consumes!( Identifier, "a::" ) );
+      assert!( !consumes!( Identifier, "a:b" ) );
       assert!( !consumes!( Identifier, "1a" ) );
       assert!( !consumes!( Identifier, "1" ) );
       assert!( !consumes!( Identifier, "Ć" ) );
@@ -169,6 +452,9 @@ mod rules {
       assert!( consumes!( Literal, r#""abc""# ) );
       assert!( consumes!( Literal, "'abc'  \n" ) );
       assert!( !consumes!( Literal, "'abc''bb'" ) );
+      assert!( consumes!( Literal, "'abc'i" ) );
+      assert!( consumes!( Literal, "'abc'i  \n" ) );
+      assert!( !consumes!( Literal, "'abc'ix" ) );
     }
 
     #[test]
@@ -177,6 +463,15 @@ mod rules {
       assert!( consumes!( Class, "[a-z]  \n" ) );
       assert!( consumes!( Class, "[abc]" ) );
       assert!( consumes!( Class, "[abc0-9g]" ) );
+      assert!( consumes!( Class, "[a-z]i" ) );
+      assert!( !consumes!( Class, "[a-z]in" ) );
+      assert!( consumes!( Class, "[^a-z]" ) );
+      assert!( consumes!( Class, "[^]" ) );
+      assert!( consumes!( Class, r"[\^a]" ) );
+      assert!( consumes!( Class, "[^a-z]i" ) );
+      assert!( consumes!( Class, r"[\p{L}_0-9]" ) );
+      assert!( consumes!( Class, r"[^\p{XID_Start}\p{Greek}]" ) );
+      assert!( !consumes!( Class, r"[\p{}]" ) );
     }
 
     #[test]
@@ -185,12 +480,24 @@ mod rules {
       assert!( consumes!( Range, "a" ) );
     }
 
+    #[test]
+    fn Repetition_Works() {
+      assert!( consumes!( Repetition, "{4}" ) );
+      assert!( consumes!( Repetition, "{2,5}" ) );
+      assert!( consumes!( Repetition, "{ 2, }" ) );
+
+      assert!( !consumes!( Repetition, "{}" ) );
+      assert!( !consumes!( Repetition, "{,5}" ) );
+      assert!( !consumes!( Repetition, "{ |n| n.end }" ) );
+    }
+
     #[test]
     fn Char_Works() {
       assert!( consumes!( Char, r"\n" ) );
       assert!( consumes!( Char, r"\]" ) );
       assert!( consumes!( Char, r"\\" ) );
       assert!( consumes!( Char, r"\'" ) );
+      assert!( consumes!( Char, r"\^" ) );
       assert!( consumes!( Char, "a" ) );
       assert!( consumes!( Char, "x" ) );
       assert!( consumes!( Char, "Ć" ) );
@@ -198,8 +505,11 @@ mod rules {
       assert!( consumes!( Char, r"\277" ) );
       assert!( consumes!( Char, r"\77" ) );
       assert!( consumes!( Char, r"\7" ) );
+      assert!( consumes!( Char, r"\x41" ) );
+      assert!( consumes!( Char, r"\xfF" ) );
 
       assert!( !consumes!( Char, "aa" ) );
+      assert!( !consumes!( Char, r"\x4" ) );
     }
 
     #[test]
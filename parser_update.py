@@ -21,7 +21,8 @@ import os.path as p
 
 DEV_PARSER_FILE = './parser.rs'
 PRELUDE_FILE = './prelude.rs'
-INLINED_PARSER_FILE = './inlined_parser.rs'
+INLINED_PARSER_FILE = './inlined_parser/lib.rs'
+RUNTIME_FILE = './runtime/lib.rs'
 INPUT_PEG_FILE = './examples/nailgun.peg'
 
 COPYRIGHT_HEADER = """
@@ -123,21 +124,75 @@ def InlineModules( filename, contents ):
 
 
 def PutPlaceholderForTopRule( contents ):
-  return contents.replace( u'match rules::Grammar(',
-                           u'match rules::NGTOP_LEVEL_RULE(' )
+  return contents.replace( u'rules::Grammar',
+                           u'rules::NGTOP_LEVEL_RULE' )
 
 
-def PreludeWrap( contents ):
+def PutPlaceholderForFullInput( contents ):
+  return contents.replace( u'const FULL_INPUT: bool = false;',
+                           u'const FULL_INPUT: bool = NGFULL_INPUT;' )
+
+
+def PutPlaceholderForSkipRule( contents ):
+  return contents.replace(
+    u'const SKIP_RULE: Option<base::Rule> = None;',
+    u'const SKIP_RULE: Option<base::Rule> = NGSKIP_RULE;' )
+
+
+# Generated crates get the base module from the nailgun_runtime crate and have
+# their rules in rules.rs.
+def UseRuntimeCrate( contents ):
+  contents = contents.replace(
+    u'#[macro_use]\nmod base;',
+    u'#[macro_use]\nextern crate nailgun_runtime;\nuse nailgun_runtime::base;' )
+  return re.sub( ur'\nmod rules \{.*', u'\nmod rules;\n', contents,
+                 flags = re.DOTALL )
+
+
+def ExtractRulesModule( contents ):
+  match = re.search( ur'\nmod rules \{\n(.*)\n\}', contents,
+                     flags = re.DOTALL )
+  return re.sub( u'^  ', u'', match.group( 1 ), flags = re.MULTILINE ) + u'\n'
+
+
+# The runtime crate's macros are used by the rules of other crates, so they
+# have to be exported.
+def ExportMacros( contents ):
+  return re.sub( ur'^( *)macro_rules!',
+                 u'\\1#[macro_export]\n\\1macro_rules!',
+                 contents,
+                 flags = re.MULTILINE )
+
+
+def PreludeStatic( name, contents ):
+  # We add allow(dead_code, unused_imports, unused_macros) so that the user
+  # doesn't get warnings if their generated grammar only uses some features of
+  # PEG (and thus only some of the generated code) and not all.
   return ''.join( [
-    COPYRIGHT_HEADER,
-    # We add allow(dead_code) so that the user doesn't get warnings if their
-    # generated grammar only uses some features of PEG (and thus only some of
-    # the generated code) and not all.
-    """pub static PRELUDE : &'static str = r###"#![allow(dead_code)]\n""",
+    """pub static {0} : &'static str = r###"#![allow(dead_code)]\n""".format(
+      name ),
+    """#![allow(unused_imports, unused_macros)]\n""",
     contents,
     '"###;' ] )
 
 
+def PreludeWrap( contents, crate_contents, rules_contents ):
+  return '\n\n'.join( [
+    COPYRIGHT_HEADER + PreludeStatic( 'PRELUDE', contents ),
+    PreludeStatic( 'CRATE_PRELUDE', crate_contents ),
+    u"pub static RULES_PRELUDE : &'static str = r###\"" + rules_contents +
+      u'"###;' ] )
+
+
+def RuntimeWrap( contents ):
+  return ''.join( [
+    COPYRIGHT_HEADER.lstrip(),
+    u'#![allow(non_snake_case)]\n#![allow(dead_code)]\n'
+      u'#![allow(unused_imports, unused_macros)]\n\n',
+    ExportMacros( contents ),
+    u'\n' ] )
+
+
 def ExtractRules( inlined_parser ):
   consume = False
   rules = []
@@ -159,20 +214,72 @@ def ReplaceRules( parser, new_rules ):
   return parser[ : match.start( 1 ) ] + new_rules + parser[ match.end( 1 ) : ]
 
 
+# Newer compilers deprecate `try!`, which the generated code denies, and later
+# editions reserve `try`. The generated code is compiled by the user's compiler,
+# so it uses the `?` operator instead.
+def ReplaceTryMacro( contents ):
+  while True:
+    start = contents.find( u'try!(' )
+    if start < 0:
+      return contents
+    depth = 0
+    index = start + len( u'try!' )
+    while True:
+      char = contents[ index ]
+      if char == u'"':
+        index = contents.index( u'"', index + 1 )
+        while contents[ index - 1 ] == u'\\':
+          index = contents.index( u'"', index + 1 )
+      elif char == u'(':
+        depth += 1
+      elif char == u')':
+        depth -= 1
+        if depth == 0:
+          break
+      index += 1
+
+    # Lines after the first were aligned to the text after `try!( `.
+    lines = contents[ start + len( u'try!(' ) : index ].strip().split( u'\n' )
+    lines = [ lines[ 0 ] ] + [ re.sub( u'^      ', u'', line )
+                               for line in lines[ 1: ] ]
+    contents = ( contents[ : start ] + u'\n'.join( lines ) + u'?' +
+                 contents[ index + 1 : ] )
+
+
+def Cleanup( contents ):
+  contents = StripTests( contents )
+  contents = StripComments( contents )
+  contents = StripCrateId( contents )
+  contents = StripExtraWhitespace( contents )
+  contents = ReplaceTryMacro( contents )
+  contents = PutPlaceholderForTopRule( contents )
+  contents = PutPlaceholderForFullInput( contents )
+  return PutPlaceholderForSkipRule( contents )
+
+
 def Main():
   dev_parser = FileContents( DEV_PARSER_FILE )
   prelude = StripRules( dev_parser )
+  crate_prelude = prelude
   prelude = InlineModules( DEV_PARSER_FILE, prelude )
-  prelude = StripTests( prelude )
-  prelude = StripComments( prelude )
-  prelude = StripCrateId( prelude )
-  prelude = StripExtraWhitespace( prelude )
-  prelude = PutPlaceholderForTopRule( prelude )
-  prelude = PreludeWrap( prelude )
+  prelude = Cleanup( prelude )
+  crate_prelude = Cleanup( crate_prelude )
+  prelude = PreludeWrap( prelude,
+                         UseRuntimeCrate( crate_prelude ),
+                         ExtractRulesModule( crate_prelude ) )
 
   with codecs.open( PRELUDE_FILE, 'w+', 'utf-8' ) as f:
     f.write( prelude )
 
+  runtime = InlineModules( DEV_PARSER_FILE, u'#[macro_use]\npub mod base;' )
+  runtime = StripTests( runtime )
+  runtime = StripComments( runtime )
+  runtime = StripExtraWhitespace( runtime )
+  runtime = ReplaceTryMacro( runtime )
+
+  with codecs.open( RUNTIME_FILE, 'w+', 'utf-8' ) as f:
+    f.write( RuntimeWrap( runtime ) )
+
   subprocess.check_output( [ 'cargo', 'build', '--release' ] )
   inlined_parser = subprocess.check_output(
     [ './target/release/nailgun', '-g', INPUT_PEG_FILE ] )
@@ -25,9 +25,9 @@ fn inputFromFile( input_file: &str ) -> Vec<u8> {
 fn main() {
   let args: std::vec::Vec<_> = std::env::args().collect();
   match parse( &inputFromFile( &args.get( 1 ).unwrap() ) ) {
-    Some( ref node ) => println!( "{:?}", node ),
-    _ => {
-      println!( "Couldn't parse input." );
+    Ok( ref node ) => println!( "{:?}", node ),
+    Err( ref error ) => {
+      println!( "Couldn't parse input at {}", error );
       std::process::exit( 1 );
     }
   };
@@ -0,0 +1,160 @@
+#!/usr/bin/env python
+#
+# Copyright 2014 Strahinja Val Markovic
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+# Regenerates the Unicode property tables in base/unicode.rs from a directory
+# with the Unicode Character Database files, as found in UCD.zip at
+# http://www.unicode.org/Public/UCD/latest/ucd/
+#
+# Usage: ./unicode_update.py path/to/ucd
+
+import re
+import sys
+import os.path as p
+
+UNICODE_FILE = './base/unicode.rs'
+GENERAL_CATEGORY_FILE = 'extracted/DerivedGeneralCategory.txt'
+SCRIPTS_FILE = 'Scripts.txt'
+CORE_PROPERTIES_FILE = 'DerivedCoreProperties.txt'
+CORE_PROPERTIES = [ 'XID_Start', 'XID_Continue' ]
+
+# The general categories that are unions of other categories.
+CATEGORY_GROUPS = {
+  'L': [ 'Lu', 'Ll', 'Lt', 'Lm', 'Lo' ],
+  'LC': [ 'Lu', 'Ll', 'Lt' ],
+  'M': [ 'Mn', 'Mc', 'Me' ],
+  'N': [ 'Nd', 'Nl', 'No' ],
+  'P': [ 'Pc', 'Pd', 'Ps', 'Pe', 'Pi', 'Pf', 'Po' ],
+  'S': [ 'Sm', 'Sc', 'Sk', 'So' ],
+  'Z': [ 'Zs', 'Zl', 'Zp' ],
+  'C': [ 'Cc', 'Cf', 'Cs', 'Co', 'Cn' ],
+}
+
+MAX_LINE_LENGTH = 80
+
+
+def ReadRanges( filename, wanted = None ):
+  """Returns a dict from property values to lists of inclusive codepoint
+  ranges, for a UCD file with lines like '0041..005A ; Lu # comment'."""
+  ranges = {}
+  with open( filename ) as f:
+    for line in f:
+      line = line.split( '#' )[ 0 ].strip()
+      if not line:
+        continue
+      codepoints, value = [ part.strip() for part in line.split( ';' )[ : 2 ] ]
+      if wanted and value not in wanted:
+        continue
+      bounds = [ int( bound, 16 ) for bound in codepoints.split( '..' ) ]
+      ranges.setdefault( value, [] ).append( ( bounds[ 0 ], bounds[ -1 ] ) )
+  return ranges
+
+
+def UnicodeVersion( filename ):
+  with open( filename ) as f:
+    return re.search( r'-(\d+\.\d+\.\d+)\.txt', f.readline() ).group( 1 )
+
+
+def MergeRanges( ranges ):
+  merged = []
+  for start, end in sorted( ranges ):
+    if merged and start <= merged[ -1 ][ 1 ] + 1:
+      merged[ -1 ] = ( merged[ -1 ][ 0 ], max( end, merged[ -1 ][ 1 ] ) )
+    else:
+      merged.append( ( start, end ) )
+  return merged
+
+
+def ConstantName( prefix, value ):
+  return prefix + re.sub( r'\W', '_', value ).upper()
+
+
+def TableCode( name, ranges ):
+  lines = [ 'const {0}: Table = &['.format( name ) ]
+  line = ' '
+  for start, end in MergeRanges( ranges ):
+    pair = ' ( 0x{0:X}, 0x{1:X} ),'.format( start, end )
+    if len( line ) + len( pair ) > MAX_LINE_LENGTH:
+      lines.append( line )
+      line = ' '
+    line += pair
+  lines.append( line.rstrip( ',' ) )
+  lines.append( '];' )
+  return '\n'.join( lines )
+
+
+def PropertiesCode( properties ):
+  lines = [ 'const PROPERTIES: '
+            '&\'static [( &\'static str, &\'static [Table] )] = &[' ]
+  for name, tables in sorted( properties.items() ):
+    line = '  ( "{0}", &[ {1} ] ),'.format( name, ', '.join( tables ) )
+    if len( line ) > MAX_LINE_LENGTH:
+      indent = '\n' + ' ' * len( '  ( "{0}", &[ '.format( name ) )
+      line = '  ( "{0}", &[ {1} ] ),'.format( name,
+                                              ( ',' + indent ).join( tables ) )
+    lines.append( line )
+  lines[ -1 ] = lines[ -1 ].rstrip( ',' )
+  lines.append( '];' )
+  return '\n'.join( lines )
+
+
+def TablesCode( ucd_dir ):
+  tables = []
+  properties = {}
+
+  def AddTables( prefix, ranges_by_value ):
+    for value, ranges in sorted( ranges_by_value.items() ):
+      name = ConstantName( prefix, value )
+      tables.append( TableCode( name, ranges ) )
+      if value in properties:
+        raise ValueError( 'Duplicate property {0}'.format( value ) )
+      properties[ value ] = [ name ]
+
+  AddTables( 'CATEGORY_',
+             ReadRanges( p.join( ucd_dir, GENERAL_CATEGORY_FILE ) ) )
+  for group, categories in CATEGORY_GROUPS.items():
+    properties[ group ] = [ ConstantName( 'CATEGORY_', category )
+                            for category in categories ]
+  AddTables( 'SCRIPT_', ReadRanges( p.join( ucd_dir, SCRIPTS_FILE ) ) )
+  AddTables( 'PROPERTY_', ReadRanges( p.join( ucd_dir, CORE_PROPERTIES_FILE ),
+                                      CORE_PROPERTIES ) )
+
+  header = ( '// Generated by unicode_update.py from version {0} of the '
+             'Unicode Character\n// Database; do not edit by hand.'.format(
+               UnicodeVersion( p.join( ucd_dir, SCRIPTS_FILE ) ) ) )
+  return '\n\n'.join( [ header ] + tables + [ PropertiesCode( properties ) ] )
+
+
+def ReplaceTables( contents, tables ):
+  match = re.search( r'// UNICODE TABLES START\n\n(.*?)\s*'
+                     r'// UNICODE TABLES END',
+                     contents,
+                     flags = re.DOTALL | re.MULTILINE )
+  return contents[ : match.start( 1 ) ] + tables + contents[ match.end( 1 ) : ]
+
+
+def Main():
+  if len( sys.argv ) != 2:
+    sys.exit( 'Usage: {0} path/to/ucd'.format( sys.argv[ 0 ] ) )
+
+  with open( UNICODE_FILE ) as f:
+    contents = ReplaceTables( f.read(), TablesCode( sys.argv[ 1 ] ) )
+
+  with open( UNICODE_FILE, 'w' ) as f:
+    f.write( contents )
+
+
+if __name__ == "__main__":
+  Main()